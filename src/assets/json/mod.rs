@@ -1,5 +1,8 @@
 use crate::assets::{Asset, Loader};
+use crate::core::physics::CollisionWorld;
 use crate::core::transform::Transform;
+use crate::event::notify_spawned;
+use crate::resources::Resources;
 use hecs::{Entity, World};
 use serde_derive::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
@@ -14,12 +17,39 @@ pub use web::*;
 
 #[typetag::serde]
 pub trait Prefab: std::fmt::Debug {
+    /// Create the components of the prefab. No `GameEvent::Spawned` is sent for the entity so
+    /// prefer the other spawn functions, or call `notify_spawned` after.
     fn spawn(&self, world: &mut hecs::World) -> hecs::Entity;
-    fn spawn_with_transform(&self, world: &mut hecs::World, transform: Transform) -> hecs::Entity {
+
+    fn spawn_with_transform(
+        &self,
+        world: &mut hecs::World,
+        resources: &Resources,
+        transform: Transform,
+    ) -> hecs::Entity {
         let e = self.spawn(world);
         world
             .insert_one(e, transform)
             .expect("Cannot add Transform to entity");
+        notify_spawned(resources, e);
+        e
+    }
+
+    /// Spawn the prefab and let the caller modify the components (change a value, add a tag...)
+    /// before the rigid body is added to the physics world.
+    fn spawn_with_overrides(
+        &self,
+        world: &mut hecs::World,
+        resources: &Resources,
+        overrides: &mut dyn FnMut(&mut hecs::World, hecs::Entity),
+    ) -> hecs::Entity {
+        let e = self.spawn(world);
+        overrides(world, e);
+
+        if let Some(mut physics) = resources.fetch_mut::<CollisionWorld>() {
+            physics.add_entity(world, e);
+        }
+        notify_spawned(resources, e);
         e
     }
 
@@ -97,3 +127,70 @@ where
         asset
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::physics::RigidBodyComponent;
+    use crate::event::{CustomGameEvent, EventQueue, EventReader, GameEvent, SpawnedEntities};
+
+    #[derive(Debug, Clone)]
+    struct TestEvent;
+    impl CustomGameEvent for TestEvent {}
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Crate;
+
+    #[typetag::serde]
+    impl Prefab for Crate {
+        fn spawn(&self, world: &mut hecs::World) -> hecs::Entity {
+            world.spawn((
+                Transform::default(),
+                RigidBodyComponent::new_dynamic_cuboid(1.0, 1.0),
+            ))
+        }
+    }
+
+    #[test]
+    fn overrides_run_before_physics() {
+        let mut world = hecs::World::new();
+        let mut resources = Resources::default();
+        resources.insert(CollisionWorld::default());
+
+        let e = Crate.spawn_with_overrides(&mut world, &resources, &mut |world, e| {
+            world.get_mut::<RigidBodyComponent>(e).unwrap().sensor = true;
+        });
+
+        let h = world.get::<RigidBodyComponent>(e).unwrap().handle.unwrap();
+        let physics = resources.fetch::<CollisionWorld>().unwrap();
+        let body = physics.rigid_bodies().get(h).unwrap();
+        let collider = physics.colliders().get(body.colliders()[0]).unwrap();
+        assert!(collider.is_sensor());
+    }
+
+    #[test]
+    fn spawn_functions_send_spawned() {
+        let mut world = hecs::World::new();
+        let mut resources = Resources::default();
+        resources.insert(EventQueue::<TestEvent>::new());
+        resources.insert(SpawnedEntities::default());
+        let mut reader = EventReader::<TestEvent>::new(&mut resources);
+
+        let a = Crate.spawn_with_transform(&mut world, &resources, Transform::default());
+        let b = Crate.spawn_with_overrides(&mut world, &resources, &mut |_, _| {});
+        {
+            let mut spawned = resources.fetch_mut::<SpawnedEntities>().unwrap();
+            let mut chan = resources.fetch_mut::<EventQueue<TestEvent>>().unwrap();
+            chan.flush_spawned(&mut spawned);
+        }
+
+        let spawned: Vec<_> = reader
+            .read(&resources)
+            .filter_map(|ev| match ev {
+                GameEvent::Spawned(e) => Some(e),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(spawned, vec![a, b]);
+    }
+}
@@ -19,7 +19,8 @@ pub mod shader;
 pub mod sprite;
 
 #[cfg(target_arch = "wasm32")]
-pub fn create_asset_managers(_surface: &mut Context, resources: &mut Resources) {
+/// The managers only need a graphics context to upload the assets, see `update_asset_managers`.
+pub fn insert_asset_managers(resources: &mut Resources) {
     let base_path = get_assets_path();
 
     let sprite_manager: AssetManager<SpriteAsset> =
@@ -37,7 +38,8 @@ pub fn create_asset_managers(_surface: &mut Context, resources: &mut Resources)
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-pub fn create_asset_managers(_surface: &mut Context, resources: &mut Resources) {
+/// The managers only need a graphics context to upload the assets, see `update_asset_managers`.
+pub fn insert_asset_managers(resources: &mut Resources) {
     let base_path = get_assets_path();
 
     #[cfg(not(feature = "packed"))]
@@ -61,6 +63,10 @@ pub fn create_asset_managers(_surface: &mut Context, resources: &mut Resources)
     resources.insert(shader_loader);
 }
 
+pub fn create_asset_managers(_surface: &mut Context, resources: &mut Resources) {
+    insert_asset_managers(resources);
+}
+
 pub fn update_asset_managers(surface: &mut Context, resources: &Resources) {
     {
         let mut sprite_manager = resources.fetch_mut::<AssetManager<SpriteAsset>>().unwrap();
@@ -1,7 +1,7 @@
 use crate::assets::{Asset, AssetError, AssetManager, Loader};
-use crate::render::mesh::{ShaderUniform, VertexSemantics};
+use crate::render::mesh::{compile_shader, ShaderUniform, VertexSemantics};
+use crate::render::post_process::{DEFAULT_POST_PROCESS_VS, POST_PROCESS_VS_SOURCE};
 use crate::render::Context;
-use luminance_front::context::GraphicsContext;
 use luminance_front::shader::Program;
 use std::path::{Path, PathBuf};
 /// Load with this handle. Filenames for the vertex and fragment shaders
@@ -46,13 +46,20 @@ impl ShaderLoader {
 impl Loader<ShaderAsset, ShaderHandle> for ShaderLoader {
     fn load(&mut self, asset_name: (String, String)) -> Asset<ShaderAsset> {
         info!("Will load {:?}", asset_name);
-        let vertex_shader_filename = self.base_path.join(asset_name.0);
+        let vertex_shader_filename = self.base_path.join(&asset_name.0);
         let fragment_shader_filename = self.base_path.join(asset_name.1);
 
         let mut asset = Asset::new();
 
+        // The default post-process vertex shader is shipped with the engine.
+        let vertex_shader = if asset_name.0 == DEFAULT_POST_PROCESS_VS {
+            Ok(POST_PROCESS_VS_SOURCE.to_string())
+        } else {
+            std::fs::read_to_string(&vertex_shader_filename)
+        };
+
         match (
-            std::fs::read_to_string(&vertex_shader_filename),
+            vertex_shader,
             std::fs::read_to_string(&fragment_shader_filename),
         ) {
             (Ok(vertex_shader), Ok(fragment_shader)) => {
@@ -76,11 +83,10 @@ impl Loader<ShaderAsset, ShaderHandle> for ShaderLoader {
         asset
     }
 
+    /// A shader that does not compile puts the asset in error state (the compilation log is in
+    /// the error). The mesh renderer draws a placeholder instead.
     fn upload_to_gpu(&self, ctx: &mut Context, inner: &mut ShaderAsset) -> Result<(), AssetError> {
-        let shader = ctx
-            .new_shader_program::<VertexSemantics, (), ShaderUniform>()
-            .from_strings(&inner.vertex_shader, None, None, &inner.fragment_shader)?
-            .ignore_warnings();
+        let shader = compile_shader(ctx, &inner.vertex_shader, &inner.fragment_shader)?;
         inner.shader = Some(shader);
         Ok(())
     }
@@ -33,6 +33,17 @@ impl SpriteAsset {
             SpriteAsset::Uploaded(tex) => Some(tex),
         }
     }
+
+    /// Width and height of the texture in pixels.
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            SpriteAsset::Loading(w, h, _, _) => (*w, *h),
+            SpriteAsset::Uploaded(tex) => {
+                let [w, h] = tex.size();
+                (w, h)
+            }
+        }
+    }
 }
 
 impl Default for SpriteAsset {
@@ -44,6 +55,48 @@ impl Default for SpriteAsset {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SpriteAssetMetadata {
     pub sampler: SamplerDef,
+    /// Build the mip chain and use trilinear filtering, so that sprites drawn much smaller
+    /// than their texture do not shimmer. Replaces the minification filter of the sampler.
+    #[serde(default)]
+    pub generate_mipmaps: bool,
+}
+
+impl SpriteAssetMetadata {
+    fn to_sampler(&self) -> Sampler {
+        let mut sampler = self.sampler.to_sampler();
+        if self.generate_mipmaps {
+            sampler.min_filter = MinFilter::LinearMipmapLinear;
+        }
+        sampler
+    }
+}
+
+/// Number of mipmaps below the base level for a full mip chain.
+fn mipmap_levels(w: u32, h: u32) -> usize {
+    (31 - w.max(h).max(1).leading_zeros()) as usize
+}
+
+/// Create the texture and upload the texels. Textures sampled with a mipmap filter get a full
+/// mip chain.
+pub(crate) fn new_texture(
+    ctx: &mut Context,
+    w: u32,
+    h: u32,
+    data: &[u8],
+    sampler: &Sampler,
+) -> Result<Texture<Dim2, NormRGBA8UI>, AssetError> {
+    let mipmaps = match sampler.min_filter {
+        MinFilter::Nearest | MinFilter::Linear => 0,
+        _ => mipmap_levels(w, h),
+    };
+    let mut tex = Texture::new(ctx, [w, h], mipmaps, sampler.clone())?;
+    let gen_mipmaps = if mipmaps > 0 {
+        GenMipmaps::Yes
+    } else {
+        GenMipmaps::No
+    };
+    tex.upload_raw(gen_mipmaps, data)?;
+    Ok(tex)
 }
 
 impl Default for SpriteAssetMetadata {
@@ -57,6 +110,7 @@ impl Default for SpriteAssetMetadata {
                 mag_filter: MagFilterDef::Linear,
                 depth_comparison: None,
             },
+            generate_mipmaps: false,
         }
     }
 }
@@ -109,7 +163,7 @@ impl Loader<SpriteAsset> for SpriteSyncLoader {
         let mut asset = Asset::new();
         let asset_path = self.base_path.join(&asset_name);
         let metadata = self.load_metadata(&asset_name);
-        let sampler = metadata.sampler.to_sampler();
+        let sampler = metadata.to_sampler();
 
         match load_texels(asset_path) {
             Ok((w, h, data)) => asset.set_loaded(SpriteAsset::Loading(w, h, data, sampler)),
@@ -125,9 +179,7 @@ impl Loader<SpriteAsset> for SpriteSyncLoader {
 
     fn upload_to_gpu(&self, ctx: &mut Context, inner: &mut SpriteAsset) -> Result<(), AssetError> {
         let tex = if let SpriteAsset::Loading(w, h, data, sampler) = inner {
-            let mut tex = Texture::new(ctx, [*w, *h], 0, sampler.clone())?;
-            tex.upload_raw(GenMipmaps::No, data)?;
-            tex
+            new_texture(ctx, *w, *h, data, sampler)?
         } else {
             panic!("Expecting Loading variant.")
         };
@@ -30,11 +30,9 @@ pub struct SpritePackLoader {
 #[cfg(feature = "packed")]
 mod implementation {
     use super::*;
-    use crate::assets::sprite::SpriteAsset;
+    use crate::assets::sprite::{new_texture, SpriteAsset};
     use crate::assets::{Asset, AssetError, Loader};
     use crate::render::Context;
-    use luminance::context::GraphicsContext;
-    use luminance::texture::{GenMipmaps, Texture};
     use std::path::PathBuf;
 
     impl SpritePackLoader {
@@ -69,9 +67,7 @@ mod implementation {
             inner: &mut SpriteAsset,
         ) -> Result<(), AssetError> {
             let tex = if let SpriteAsset::Loading(w, h, data, sampler) = inner {
-                let mut tex = Texture::new(ctx, [*w, *h], 0, sampler.clone())?;
-                tex.upload_raw(GenMipmaps::No, data)?;
-                tex
+                new_texture(ctx, *w, *h, data, sampler)?
             } else {
                 panic!("Expecting Loading variant.")
             };
@@ -1,7 +1,8 @@
-use crate::assets::sprite::{MagFilterDef, MinFilterDef, SamplerDef, SpriteAsset, WrapDef};
+use crate::assets::sprite::{
+    new_texture, MagFilterDef, MinFilterDef, SamplerDef, SpriteAsset, WrapDef,
+};
 use crate::assets::{Asset, AssetError, Loader};
 use crate::render::Context;
-use luminance_front::texture::{GenMipmaps, Texture};
 
 use image::ImageFormat;
 use luminance::shader::UniformType::Sampler1D;
@@ -25,9 +26,7 @@ impl Loader<SpriteAsset> for AsyncWebLoader {
     fn upload_to_gpu(&self, ctx: &mut Context, inner: &mut SpriteAsset) -> Result<(), AssetError> {
         info!("Will upload to GPU");
         let tex = if let SpriteAsset::Loading(w, h, data, sampler) = inner {
-            let mut tex = Texture::new(ctx, [*w, *h], 0, sampler.clone())?;
-            tex.upload_raw(GenMipmaps::No, &data)?;
-            tex
+            new_texture(ctx, *w, *h, data, sampler)?
         } else {
             panic!("Expecting Loading variant.")
         };
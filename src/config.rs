@@ -1,6 +1,7 @@
 
 
 
+use crate::event::{CustomGameEvent, EventQueue, GameEvent};
 use serde::de::DeserializeOwned;
 use serde_derive::{Deserialize, Serialize};
 
@@ -15,7 +16,7 @@ where
     serde_json::from_str(&content).map_err(|e| e.into())
 }
 
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Default, Debug, Serialize, Deserialize, Copy, Clone)]
 pub struct GameEngineConfig {
     pub show_gizmos: bool,
 }
@@ -44,6 +45,24 @@ pub struct GameEngineConfig {
 //     }
 // }
 
+/// How frames are presented on the screen.
+#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+pub struct DisplayConfig {
+    /// Wait for the vertical sync before presenting a frame. Avoids tearing. Desktop only.
+    pub vsync: bool,
+    /// The main loop sleeps if a frame is faster than that. None to run as fast as possible.
+    pub max_fps: Option<u32>,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            vsync: true,
+            max_fps: Some(60),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Copy, Clone)]
 pub struct AudioConfig {
     pub background_volume: u32,
@@ -60,3 +79,60 @@ impl Default for AudioConfig {
         }
     }
 }
+
+/// A setting that was changed through `Settings`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    BackgroundVolume,
+    EffectsVolume,
+    ShowGizmos,
+}
+
+/// Configuration that can change while the game is running (e.g. from a settings menu). It is
+/// created by the game from the `GameEngineConfig` resource and the audio configuration. The
+/// setters send a `GameEvent::SettingChanged` so that the systems can apply the new value.
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    engine: GameEngineConfig,
+    audio: AudioConfig,
+}
+
+impl Settings {
+    pub fn new(engine: GameEngineConfig, audio: AudioConfig) -> Self {
+        Self { engine, audio }
+    }
+
+    pub fn engine(&self) -> &GameEngineConfig {
+        &self.engine
+    }
+
+    pub fn audio(&self) -> &AudioConfig {
+        &self.audio
+    }
+
+    /// Volume between 0 and 100.
+    pub fn set_background_volume<GE>(&mut self, volume: u32, events: &mut EventQueue<GE>)
+    where
+        GE: CustomGameEvent,
+    {
+        self.audio.background_volume = volume.min(100);
+        events.single_write(GameEvent::SettingChanged(Setting::BackgroundVolume));
+    }
+
+    /// Volume between 0 and 100.
+    pub fn set_effects_volume<GE>(&mut self, volume: u32, events: &mut EventQueue<GE>)
+    where
+        GE: CustomGameEvent,
+    {
+        self.audio.effects_volume = volume.min(100);
+        events.single_write(GameEvent::SettingChanged(Setting::EffectsVolume));
+    }
+
+    pub fn set_show_gizmos<GE>(&mut self, show_gizmos: bool, events: &mut EventQueue<GE>)
+    where
+        GE: CustomGameEvent,
+    {
+        self.engine.show_gizmos = show_gizmos;
+        events.single_write(GameEvent::SettingChanged(Setting::ShowGizmos));
+    }
+}
@@ -1,11 +1,15 @@
-use crate::core::timer::Timer;
+use crate::core::disable::Disabled;
+use crate::core::physics::{CollisionWorld, RigidBodyComponent};
+use crate::core::timer::{scaled_dt, Timer};
 use crate::event::{CustomGameEvent, EventQueue, GameEvent};
 use crate::render::mesh::{Material, MeshRender};
 //use crate::render::sprite::Sprite;
 use crate::resources::Resources;
+use anyhow::anyhow;
 use log::error;
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::Path;
 use std::time::Duration;
 
 /// One animation (in one spreadsheet).
@@ -19,6 +23,7 @@ pub struct Animation {
     pub current_index: usize,
     pub elapsed_frame: usize,
 
+    /// Duration of a frame. Its elapsed time is saved with the animation.
     pub frame_duration: Timer,
 }
 
@@ -62,6 +67,184 @@ pub struct AnimationController {
     pub delete_on_finished: bool,
 }
 
+impl AnimationController {
+    /// Play the animation from its first keyframe. Does nothing if the animation is already
+    /// playing.
+    pub fn play(&mut self, name: &str) {
+        if self.current_animation.as_deref() == Some(name) {
+            return;
+        }
+
+        if let Some(animation) = self.animations.get_mut(name) {
+            animation.current_index = 0;
+            animation.elapsed_frame = 0;
+            animation.frame_duration.reset();
+            self.current_animation = Some(name.to_string());
+        } else {
+            warn!("No animation named {}", name);
+        }
+    }
+}
+
+/// Keyframes and frame duration of one animation in an `AnimationSet` file.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AnimationDefinition {
+    /// Sprite number and number of frames to elapse for each keyframe.
+    pub keyframes: Vec<(usize, usize)>,
+    /// Duration of a frame in seconds.
+    pub frame_duration: f32,
+}
+
+/// Animations of a spritesheet, keyed by name, as authored in a JSON file. Convert it to an
+/// `AnimationController` to use it. For example:
+///
+/// ```json
+/// {
+///     "animations": {
+///         "idle": { "keyframes": [[0, 10], [1, 10]], "frame_duration": 0.016 },
+///         "walk": { "keyframes": [[2, 5], [3, 5], [4, 5]], "frame_duration": 0.016 }
+///     },
+///     "current_animation": "idle"
+/// }
+/// ```
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct AnimationSet {
+    pub animations: HashMap<String, AnimationDefinition>,
+    /// Animation played at the beginning.
+    #[serde(default)]
+    pub current_animation: Option<String>,
+    #[serde(default)]
+    pub delete_on_finished: bool,
+}
+
+impl AnimationSet {
+    pub fn load_from_path<P: AsRef<Path>>(p: P) -> Result<Self, anyhow::Error> {
+        let content = std::fs::read_to_string(p)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Load the file and build a ready-to-use controller.
+    pub fn controller_from_path<P: AsRef<Path>>(
+        p: P,
+    ) -> Result<AnimationController, anyhow::Error> {
+        Self::load_from_path(p)?.into_controller()
+    }
+
+    /// Build the controller. Fails if an animation has no keyframe or if the current animation
+    /// is not in the set, as the animation system would panic on them.
+    pub fn into_controller(self) -> Result<AnimationController, anyhow::Error> {
+        if let Some((name, _)) = self
+            .animations
+            .iter()
+            .find(|(_, def)| def.keyframes.is_empty())
+        {
+            return Err(anyhow!("Animation {} has no keyframe", name));
+        }
+        if let Some(ref current) = self.current_animation {
+            if !self.animations.contains_key(current) {
+                return Err(anyhow!("Unknown current animation {}", current));
+            }
+        }
+
+        let animations = self
+            .animations
+            .into_iter()
+            .map(|(name, def)| {
+                let animation =
+                    Animation::new(def.keyframes, Timer::of_seconds(def.frame_duration));
+                (name, animation)
+            })
+            .collect();
+        Ok(AnimationController {
+            animations,
+            current_animation: self.current_animation,
+            delete_on_finished: self.delete_on_finished,
+        })
+    }
+}
+
+/// Enable the collider of the entity only during some keyframes of an animation. Use it with
+/// a sensor body to get precise melee hitboxes. The body stays in the simulation.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct AnimationDrivenCollider {
+    /// Animation name -> ranges of keyframe indices (start inclusive, end exclusive) during which
+    /// the collider is enabled.
+    pub active_frames: HashMap<String, Vec<(usize, usize)>>,
+
+    /// Entity that plays the animation. None means this entity.
+    #[serde(skip)]
+    pub source: Option<hecs::Entity>,
+}
+
+impl AnimationDrivenCollider {
+    /// Returns true if the body should be active for the current keyframe of the controller.
+    pub fn is_active(&self, controller: &AnimationController) -> bool {
+        controller
+            .current_animation
+            .as_ref()
+            .and_then(|name| {
+                let animation = controller.animations.get(name)?;
+                let ranges = self.active_frames.get(name)?;
+                Some(ranges.iter().any(|(start, end)| {
+                    animation.current_index >= *start && animation.current_index < *end
+                }))
+            })
+            .unwrap_or(false)
+    }
+}
+
+/// Cycle through sprites of a spritesheet at a constant rate (rotating coin, torch...). Simpler
+/// than an `AnimationController` for cosmetic loops.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpriteFlipbook {
+    /// Sprite numbers in the spritesheet.
+    pub frames: Vec<u32>,
+    /// Duration of each frame.
+    pub frame_time: Timer,
+    /// Start again after the last frame. Otherwise, stay on the last frame.
+    #[serde(default)]
+    pub looping: bool,
+    /// Index in `frames`.
+    #[serde(default)]
+    pub current: usize,
+}
+
+impl SpriteFlipbook {
+    pub fn new(frames: Vec<u32>, frame_time: Timer, looping: bool) -> Self {
+        Self {
+            frames,
+            frame_time,
+            looping,
+            current: 0,
+        }
+    }
+
+    /// True when a non-looping flipbook reached its last frame.
+    pub fn finished(&self) -> bool {
+        !self.looping && self.current + 1 >= self.frames.len()
+    }
+
+    fn advance(&mut self, dt: Duration) {
+        if self.frames.is_empty() || self.finished() {
+            return;
+        }
+        self.frame_time.tick(dt);
+        // Several frames can elapse during a long update.
+        while self.frame_time.finished() && self.frame_time.deadline() > 0.0 {
+            self.frame_time.restart();
+            if self.current + 1 < self.frames.len() {
+                self.current += 1;
+            } else if self.looping {
+                self.current = 0;
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+/// Update the sprite animations. The game runs it at each fixed step, before the physic step,
+/// so that the animation speed does not depend on the frame rate.
 pub struct AnimationSystem;
 
 impl AnimationSystem {
@@ -69,9 +252,11 @@ impl AnimationSystem {
     where
         GE: CustomGameEvent,
     {
+        let dt = scaled_dt(resources, dt);
         let mut events = vec![];
         for (e, (controller, render)) in world
             .query::<(&mut AnimationController, &mut MeshRender)>()
+            .without::<Disabled>()
             .iter()
         {
             if let Material::Sprite {
@@ -85,7 +270,7 @@ impl AnimationSystem {
                         animation.frame_duration.tick(dt);
                         // Check if one animation frame has elapsed. If yes, then increase the elapsed frame count
                         if animation.frame_duration.finished() {
-                            animation.frame_duration.reset();
+                            animation.frame_duration.restart();
                             animation.elapsed_frame += 1;
                         }
 
@@ -111,4 +296,218 @@ impl AnimationSystem {
             channel.drain_vec_write(&mut events);
         }
     }
+
+    /// Set the sprite of the entities with a `SpriteFlipbook` to the current frame.
+    pub fn flipbooks(&mut self, world: &mut hecs::World, dt: Duration, resources: &Resources) {
+        let dt = scaled_dt(resources, dt);
+        for (_, (flipbook, render)) in world
+            .query::<(&mut SpriteFlipbook, &mut MeshRender)>()
+            .without::<Disabled>()
+            .iter()
+        {
+            flipbook.advance(dt);
+            if let Material::Sprite {
+                ref mut sprite_nb, ..
+            } = render.material
+            {
+                if let Some(frame) = flipbook.frames.get(flipbook.current) {
+                    *sprite_nb = *frame;
+                }
+            }
+        }
+    }
+
+    /// Enable or disable the colliders of the entities that have an `AnimationDrivenCollider`
+    /// depending on the current keyframe. Call it after `animate`.
+    pub fn update_colliders(&mut self, world: &mut hecs::World, resources: &Resources) {
+        let mut to_update = vec![];
+        for (e, (driven, rbc, controller)) in world
+            .query::<(
+                &AnimationDrivenCollider,
+                &RigidBodyComponent,
+                Option<&AnimationController>,
+            )>()
+            .without::<Disabled>()
+            .iter()
+        {
+            let active = match driven.source {
+                Some(source) => world
+                    .get::<AnimationController>(source)
+                    .map(|controller| driven.is_active(&controller))
+                    .unwrap_or(false),
+                None => controller
+                    .map(|controller| driven.is_active(controller))
+                    .unwrap_or(false),
+            };
+            to_update.push((e, active, rbc.handle.is_none()));
+        }
+
+        let mut physics = match resources.fetch_mut::<CollisionWorld>() {
+            Some(physics) => physics,
+            None => return,
+        };
+        for (e, active, new_body) in to_update {
+            if new_body {
+                physics.add_entity(world, e);
+            }
+            if physics.is_collider_enabled(e) != active {
+                physics.set_collider_enabled(e, active);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::transform::Transform;
+
+    #[derive(Debug, Clone)]
+    struct TestEvent;
+    impl CustomGameEvent for TestEvent {}
+
+    fn sprite() -> MeshRender {
+        MeshRender {
+            enabled: true,
+            material: Material::Sprite {
+                sprite_id: "knight".to_string(),
+                sprite_nb: 0,
+                columns: 4,
+                rows: 1,
+                alpha_cutoff: None,
+                rotation_steps: 0,
+            },
+            depth: 0,
+            layer: Default::default(),
+        }
+    }
+
+    #[test]
+    fn collider_is_active_during_the_frame_range() {
+        let mut resources = Resources::default();
+        resources.insert(EventQueue::<TestEvent>::new());
+        resources.insert(CollisionWorld::default());
+
+        // Each keyframe lasts one frame of 250ms.
+        let mut controller = AnimationController::default();
+        controller.animations.insert(
+            "attack".to_string(),
+            Animation::new(
+                vec![(0, 0), (1, 0), (2, 0), (3, 0)],
+                Timer::of_seconds(0.25),
+            ),
+        );
+        controller.current_animation = Some("attack".to_string());
+        let mut driven = AnimationDrivenCollider::default();
+        driven
+            .active_frames
+            .insert("attack".to_string(), vec![(1, 3)]);
+
+        let mut world = hecs::World::new();
+        let e = world.spawn((
+            Transform::default(),
+            RigidBodyComponent::new_sensor_cuboid(1.0, 1.0),
+            controller,
+            driven,
+            sprite(),
+        ));
+
+        let mut states = vec![];
+        for _ in 0..4 {
+            AnimationSystem.animate::<TestEvent>(
+                &mut world,
+                Duration::from_millis(250),
+                &resources,
+            );
+            AnimationSystem.update_colliders(&mut world, &resources);
+            let index =
+                world.get::<AnimationController>(e).unwrap().animations["attack"].current_index;
+            let enabled = resources
+                .fetch::<CollisionWorld>()
+                .unwrap()
+                .is_collider_enabled(e);
+            states.push((index, enabled));
+        }
+        assert_eq!(states, vec![(1, true), (2, true), (3, false), (0, false)]);
+    }
+
+    #[test]
+    fn load_an_animation_set() {
+        let path = std::env::temp_dir().join("snoozeng-animation-set.json");
+        std::fs::write(
+            &path,
+            r#"{
+                "animations": {
+                    "idle": { "keyframes": [[0, 10], [1, 10]], "frame_duration": 0.1 },
+                    "walk": { "keyframes": [[2, 5], [3, 5], [4, 5]], "frame_duration": 0.05 }
+                },
+                "current_animation": "walk"
+            }"#,
+        )
+        .unwrap();
+
+        let controller = AnimationSet::controller_from_path(&path).unwrap();
+        assert_eq!(controller.current_animation, Some("walk".to_string()));
+        let idle = &controller.animations["idle"];
+        assert_eq!(idle.keyframes, vec![(0, 10), (1, 10)]);
+        assert_eq!(idle.frame_duration.deadline(), 0.1);
+        let walk = &controller.animations["walk"];
+        assert_eq!(walk.keyframes, vec![(2, 5), (3, 5), (4, 5)]);
+        assert_eq!(walk.frame_duration.deadline(), 0.05);
+    }
+
+    #[test]
+    fn invalid_animation_set() {
+        let set: AnimationSet = serde_json::from_str(
+            r#"{ "animations": { "idle": { "keyframes": [], "frame_duration": 0.1 } } }"#,
+        )
+        .unwrap();
+        assert!(set.into_controller().is_err());
+
+        let set: AnimationSet = serde_json::from_str(
+            r#"{
+                "animations": { "idle": { "keyframes": [[0, 1]], "frame_duration": 0.1 } },
+                "current_animation": "walk"
+            }"#,
+        )
+        .unwrap();
+        assert!(set.into_controller().is_err());
+    }
+
+    #[test]
+    fn flipbook_loops() {
+        let mut flipbook = SpriteFlipbook::new(vec![4, 5, 6], Timer::of_seconds(0.1), true);
+        flipbook.advance(Duration::from_millis(250));
+        assert_eq!(flipbook.current, 2);
+        flipbook.advance(Duration::from_millis(100));
+        assert_eq!(flipbook.current, 0);
+    }
+
+    #[test]
+    fn flipbook_stops_on_the_last_frame() {
+        let mut flipbook = SpriteFlipbook::new(vec![4, 5], Timer::of_seconds(0.1), false);
+        flipbook.advance(Duration::from_secs(1));
+        assert_eq!(flipbook.current, 1);
+        assert!(flipbook.finished());
+    }
+
+    #[test]
+    fn flipbook_sets_the_sprite_number() {
+        let resources = Resources::default();
+        let mut world = hecs::World::new();
+        let e = world.spawn((
+            SpriteFlipbook::new(vec![4, 5, 6], Timer::of_seconds(0.1), true),
+            sprite(),
+        ));
+
+        let mut sprites = vec![];
+        for _ in 0..4 {
+            AnimationSystem.flipbooks(&mut world, Duration::from_millis(110), &resources);
+            match world.get::<MeshRender>(e).unwrap().material {
+                Material::Sprite { sprite_nb, .. } => sprites.push(sprite_nb),
+                _ => unreachable!(),
+            }
+        }
+        assert_eq!(sprites, vec![5, 6, 4, 5]);
+    }
 }
@@ -1,14 +1,46 @@
-use crate::config::AudioConfig;
-use crate::event::{CustomGameEvent, EventQueue, GameEvent};
+use crate::config::{AudioConfig, Setting, Settings};
+use crate::event::{CustomGameEvent, EventQueue, EventReaderId, GameEvent};
 use crate::resources::Resources;
-use shrev::ReaderId;
+
+/// Background tracks played one after the other. After the last track, the playlist starts
+/// again from the first one. Start it with `play_playlist`.
+#[derive(Debug, Clone, Default)]
+pub struct Playlist {
+    tracks: Vec<String>,
+    current: usize,
+}
+
+impl Playlist {
+    pub fn new(tracks: Vec<String>) -> Self {
+        Self { tracks, current: 0 }
+    }
+
+    /// Track being played.
+    pub fn current(&self) -> Option<&str> {
+        self.tracks.get(self.current).map(|t| t.as_str())
+    }
+
+    /// Go to the next track, wrapping at the end of the playlist.
+    pub fn advance(&mut self) -> Option<&str> {
+        if self.tracks.is_empty() {
+            return None;
+        }
+        self.current = (self.current + 1) % self.tracks.len();
+        self.current()
+    }
+}
 
 pub struct AudioSystem<GE>
 where
     GE: CustomGameEvent,
 {
     current_background: Option<String>,
-    rdr_id: ReaderId<GameEvent<GE>>,
+    /// False if the current background music should be played only once.
+    looping: bool,
+    /// True when the current background music has been sent to the backend. Assets might still
+    /// be loading when the music is requested.
+    started: bool,
+    rdr_id: EventReaderId<GE>,
     config: AudioConfig,
     backend: backend::AudioBackend,
 }
@@ -24,6 +56,8 @@ where
             backend,
             config,
             current_background: None,
+            looping: true,
+            started: false,
             rdr_id: channel.register_reader(),
         }) {
             Ok(system) => Ok(system),
@@ -35,23 +69,74 @@ where
     }
 
     pub fn process(&mut self, resources: &Resources) {
-        let channel = resources.fetch::<EventQueue<GE>>().unwrap();
-        for ev in channel.read(&mut self.rdr_id) {
+        let events: Vec<_> = {
+            let channel = resources.fetch::<EventQueue<GE>>().unwrap();
+            channel.read(&mut self.rdr_id).cloned().collect()
+        };
+
+        let mut next_track = None;
+        for ev in events {
             match ev {
                 GameEvent::PlayBackgroundMusic(name) => {
-                    self.current_background =
-                        self.backend
-                            .play_background_music(name, &self.config, resources);
+                    self.play_background(&name, true, resources);
+                }
+                GameEvent::PlayBackgroundMusicOnce(name) => {
+                    self.play_background(&name, false, resources);
+                }
+                GameEvent::MusicFinished(name) => {
+                    if let Some(mut playlist) = resources.fetch_mut::<Playlist>() {
+                        if playlist.current() == Some(name.as_str()) {
+                            next_track = playlist.advance().map(|t| t.to_string());
+                        }
+                    }
                 }
                 GameEvent::PlaySound(name) => {
-                    self.backend.play_sound(name, resources);
+                    self.backend.play_sound(&name, resources);
+                }
+                GameEvent::SettingChanged(Setting::BackgroundVolume)
+                | GameEvent::SettingChanged(Setting::EffectsVolume) => {
+                    if let Some(settings) = resources.fetch::<Settings>() {
+                        self.config = *settings.audio();
+                        self.backend.set_volumes(&self.config);
+                    }
                 }
                 _ => (),
             }
         }
 
-        // LOOP !
-        self.backend.repeat_bg(&self.current_background, resources);
+        if let Some(track) = next_track {
+            self.play_background(&track, false, resources);
+        }
+
+        if let Some(finished) = self.update_background(resources) {
+            let mut channel = resources.fetch_mut::<EventQueue<GE>>().unwrap();
+            channel.single_write(GameEvent::MusicFinished(finished));
+        }
+    }
+
+    fn play_background(&mut self, name: &str, looping: bool, resources: &Resources) {
+        self.current_background = self
+            .backend
+            .play_background_music(name, &self.config, resources);
+        self.looping = looping;
+        self.started = !self.backend.background_empty();
+    }
+
+    /// Repeat the looping music or start the music that was still loading. Returns the name of
+    /// the music that was played once and just finished.
+    fn update_background(&mut self, resources: &Resources) -> Option<String> {
+        if self.current_background.is_none() || !self.backend.background_empty() {
+            return None;
+        }
+
+        if self.looping || !self.started {
+            // LOOP !
+            self.backend.repeat_bg(&self.current_background, resources);
+            self.started = !self.backend.background_empty();
+            None
+        } else {
+            self.current_background.take()
+        }
     }
 }
 
@@ -63,6 +148,29 @@ where
     channel.single_write(GameEvent::PlayBackgroundMusic(name.to_string()));
 }
 
+/// Play the background music once.
+pub fn play_background_music_once<GE>(resources: &Resources, name: &str)
+where
+    GE: CustomGameEvent,
+{
+    let mut channel = resources.fetch_mut::<EventQueue<GE>>().unwrap();
+    channel.single_write(GameEvent::PlayBackgroundMusicOnce(name.to_string()));
+}
+
+/// Replace the `Playlist` resource and play its first track.
+pub fn play_playlist<GE>(resources: &Resources, tracks: Vec<String>)
+where
+    GE: CustomGameEvent,
+{
+    let playlist = Playlist::new(tracks);
+    if let Some(first) = playlist.current() {
+        play_background_music_once::<GE>(resources, first);
+    }
+    if let Some(mut current) = resources.fetch_mut::<Playlist>() {
+        *current = playlist;
+    }
+}
+
 pub fn play_sound<GE>(resources: &Resources, name: &str)
 where
     GE: CustomGameEvent,
@@ -171,6 +279,18 @@ mod backend {
             }
         }
 
+        pub fn set_volumes(&mut self, config: &AudioConfig) {
+            self.background
+                .set_volume(config.background_volume as f32 / 100.0);
+            for sink in &mut self.sound_sinks {
+                sink.set_volume(config.effects_volume as f32 / 100.0);
+            }
+        }
+
+        pub fn background_empty(&self) -> bool {
+            self.background.empty()
+        }
+
         pub fn repeat_bg(&mut self, current_bg: &Option<String>, resources: &Resources) {
             if let Some(ref bg) = current_bg {
                 if self.background.empty() {
@@ -196,3 +316,23 @@ mod backend {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn playlist_wraps_around() {
+        let mut playlist = Playlist::new(vec!["a.ogg".to_string(), "b.ogg".to_string()]);
+        assert_eq!(playlist.current(), Some("a.ogg"));
+        assert_eq!(playlist.advance(), Some("b.ogg"));
+        assert_eq!(playlist.advance(), Some("a.ogg"));
+    }
+
+    #[test]
+    fn empty_playlist() {
+        let mut playlist = Playlist::default();
+        assert_eq!(playlist.current(), None);
+        assert_eq!(playlist.advance(), None);
+    }
+}
@@ -1,13 +1,81 @@
+use crate::core::curve::Easing;
+use crate::core::timer::{scaled_dt, Timer};
+use crate::core::window::WindowDim;
+use crate::event::{CustomGameEvent, EventQueue, GameEvent};
 use crate::geom2::{Matrix4f, Vector2f};
+use crate::resources::Resources;
 use hecs::World;
 use rapier2d::na::{Matrix4, Point3, Vector3, Vector4};
+use std::cell::Cell;
+use std::time::Duration;
 
 /// Camera to display stuff to the screen. If main is true, then it will be used for the rendering.
-/// If multiple main camera, then the first one will be used.
+/// If multiple main camera without viewport, then the first one will be used. Main cameras with
+/// a viewport will render to their part of the screen (split-screen).
 #[derive(Debug)]
 pub struct Camera {
     pub main: bool,
     pub position: Vector2f,
+    /// Part of the screen to render to. None is the whole screen.
+    pub viewport: Option<ViewportRect>,
+    /// Current `move_to` transition.
+    transition: Option<CameraTransition>,
+}
+
+/// Move of the camera to a fixed point (cutscenes...).
+#[derive(Debug, Clone)]
+struct CameraTransition {
+    start: Vector2f,
+    target: Vector2f,
+    timer: Timer,
+    easing: Easing,
+}
+
+/// Sub-rectangle of the game viewport, in normalized coordinates (between 0 and 1). Origin
+/// is the bottom-left corner.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ViewportRect {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Default for ViewportRect {
+    fn default() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            w: 1.0,
+            h: 1.0,
+        }
+    }
+}
+
+impl ViewportRect {
+    pub fn new(x: f32, y: f32, w: f32, h: f32) -> Self {
+        Self { x, y, w, h }
+    }
+
+    /// Left half of the screen.
+    pub fn left_half() -> Self {
+        Self::new(0.0, 0.0, 0.5, 1.0)
+    }
+
+    /// Right half of the screen.
+    pub fn right_half() -> Self {
+        Self::new(0.5, 0.0, 0.5, 1.0)
+    }
+
+    /// Get the pixel rectangle (x, y, width, height) from the game viewport in pixels.
+    pub fn to_pixels(&self, x: u32, y: u32, width: f32, height: f32) -> (u32, u32, u32, u32) {
+        (
+            x + (self.x * width).round() as u32,
+            y + (self.y * height).round() as u32,
+            (self.w * width).round() as u32,
+            (self.h * height).round() as u32,
+        )
+    }
 }
 
 impl Camera {
@@ -15,6 +83,58 @@ impl Camera {
         Self {
             main: true,
             position: Vector2f::zeros(),
+            viewport: None,
+            transition: None,
+        }
+    }
+
+    pub fn with_viewport(viewport: ViewportRect) -> Camera {
+        Self {
+            main: true,
+            position: Vector2f::zeros(),
+            viewport: Some(viewport),
+            transition: None,
+        }
+    }
+
+    /// Move the camera smoothly from its current position to the target. Replaces the current
+    /// transition. The `CameraTransitionSystem` moves the camera and sends
+    /// `GameEvent::CameraArrived` at the end. Code that moves the camera every frame (e.g. to
+    /// follow the player) should not run while `is_moving` is true.
+    pub fn move_to(&mut self, target: Vector2f, duration: Duration, easing: Easing) {
+        self.transition = Some(CameraTransition {
+            start: self.position,
+            target,
+            timer: Timer::of_seconds(duration.as_secs_f32()),
+            easing,
+        });
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.transition.is_some()
+    }
+
+    /// Stop the transition where the camera is.
+    pub fn stop(&mut self) {
+        self.transition = None;
+    }
+
+    /// Returns true if the camera just arrived.
+    fn update_transition(&mut self, dt: Duration) -> bool {
+        let transition = match self.transition.as_mut() {
+            Some(transition) => transition,
+            None => return false,
+        };
+
+        transition.timer.tick(dt);
+        let t = transition.easing.apply(transition.timer.progress());
+        self.position = transition.start + (transition.target - transition.start) * t;
+        if transition.timer.finished() {
+            self.position = transition.target;
+            self.transition = None;
+            true
+        } else {
+            false
         }
     }
 
@@ -27,45 +147,468 @@ impl Camera {
     }
 }
 
+/// The first camera with `main` set, or the first camera if there is no main camera.
+pub fn main_camera(world: &World) -> Option<hecs::Entity> {
+    let mut query = world.query::<&Camera>();
+    let mut fallback = None;
+    for (e, camera) in query.iter() {
+        if camera.main {
+            return Some(e);
+        }
+        fallback = fallback.or(Some(e));
+    }
+    fallback
+}
+
+/// View matrix of the `main_camera`.
 pub fn get_view_matrix(world: &World) -> Option<Matrix4f> {
-    world
-        .query::<&Camera>()
-        .iter()
-        .map(|(_, c)| c.to_view())
-        .next()
+    let e = main_camera(world)?;
+    world.get::<Camera>(e).ok().map(|c| c.to_view())
+}
+
+/// Move the cameras that have a transition. See `Camera::move_to`.
+pub struct CameraTransitionSystem;
+
+impl CameraTransitionSystem {
+    pub fn update<GE>(&mut self, world: &mut hecs::World, dt: Duration, resources: &Resources)
+    where
+        GE: CustomGameEvent,
+    {
+        let dt = scaled_dt(resources, dt);
+        let mut events = vec![];
+        for (e, camera) in world.query::<&mut Camera>().iter() {
+            if camera.update_transition(dt) {
+                events.push(GameEvent::CameraArrived(e));
+            }
+        }
+
+        if !events.is_empty() {
+            let mut channel = resources.fetch_mut::<EventQueue<GE>>().unwrap();
+            channel.drain_vec_write(&mut events);
+        }
+    }
+}
+
+/// Get the view matrices and viewports of the cameras used for rendering. Only the first main
+/// camera without viewport is kept. If there is no main camera, the first camera will be used.
+pub fn get_render_targets(world: &World) -> Vec<(Matrix4f, ViewportRect)> {
+    let mut has_fullscreen = false;
+    let mut targets = vec![];
+    for (_, camera) in world.query::<&Camera>().iter() {
+        if !camera.main {
+            continue;
+        }
+
+        match camera.viewport {
+            Some(viewport) => targets.push((camera.to_view(), viewport)),
+            None if !has_fullscreen => {
+                has_fullscreen = true;
+                targets.push((camera.to_view(), ViewportRect::default()));
+            }
+            None => (),
+        }
+    }
+
+    if targets.is_empty() {
+        if let Some(view) = get_view_matrix(world) {
+            targets.push((view, ViewportRect::default()));
+        }
+    }
+
+    targets
+}
+
+/// Position of the mouse cursor in world coordinates. Updated at the beginning of each frame.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MouseWorldPosition(pub Vector2f);
+
+/// Compute the part of the window where the game is rendered, keeping the aspect ratio of the
+/// virtual dimensions. Returns (width, height, x, y) of the viewport. x and y are from the
+/// bottom-left corner of the window.
+pub fn letterbox_viewport(
+    window_dim: &WindowDim,
+    virtual_dim: &VirtualDim,
+) -> (f32, f32, u32, u32) {
+    let aspect_ratio = virtual_dim.aspect();
+
+    let w = window_dim.width;
+    let h = window_dim.height;
+    if w as f32 > (h as f32 * aspect_ratio).ceil() {
+        let (viewport_w, viewport_h) = ((h as f32 * aspect_ratio).ceil(), h as f32);
+        let y = 0u32;
+        let x = ((w as f32 - viewport_w) / 2.0).round() as u32;
+        (viewport_w, viewport_h, x, y)
+    } else {
+        let (viewport_w, viewport_h) = (w as f32, (w as f32 / aspect_ratio).ceil());
+        let y = ((h as f32 - viewport_h) / 2.0).round() as u32;
+        let x = 0u32;
+        (viewport_w, viewport_h, x, y)
+    }
+}
+
+/// Convert the cursor position (in pixels, from the top-left corner of the window) to world
+/// coordinates. The camera whose viewport contains the cursor is used, with the projection of
+/// its viewport. Without split-screen, the `ViewProjection` resource is used.
+pub fn cursor_to_world(cursor: Vector2f, world: &World, resources: &Resources) -> Option<Vector2f> {
+    let window_dim = *resources.fetch::<WindowDim>().unwrap();
+    let virtual_dim = *resources.fetch::<VirtualDim>().unwrap();
+    let targets = get_render_targets(world);
+    if let [(_, viewport)] = targets.as_slice() {
+        if *viewport == ViewportRect::default() {
+            return resources.fetch::<ViewProjection>()?.screen_to_world(
+                cursor,
+                &window_dim,
+                &virtual_dim,
+            );
+        }
+    }
+
+    let (viewport_w, viewport_h, x, y) = letterbox_viewport(&window_dim, &virtual_dim);
+    // Viewports have their origin at the bottom-left corner.
+    let cursor = Vector2f::new(cursor.x, window_dim.height as f32 - cursor.y);
+
+    let mut cameras = targets.iter().map(|(view, viewport)| {
+        (
+            view,
+            viewport,
+            viewport.to_pixels(x, y, viewport_w, viewport_h),
+        )
+    });
+    let (view, viewport, (cam_x, cam_y, cam_w, cam_h)) = cameras
+        .clone()
+        .find(|(_, _, (cam_x, cam_y, cam_w, cam_h))| {
+            let rel = cursor - Vector2f::new(*cam_x as f32, *cam_y as f32);
+            rel.x >= 0.0 && rel.x < *cam_w as f32 && rel.y >= 0.0 && rel.y < *cam_h as f32
+        })
+        .or_else(|| cameras.next())?;
+
+    let projection = resources.fetch::<ProjectionMatrix>().unwrap();
+    let ndc = pixel_to_ndc(cursor, (cam_x, cam_y, cam_w as f32, cam_h as f32));
+    ndc_to_world(ndc, &projection.for_viewport(viewport), view)
+}
+
+/// Convert a position in pixels (from the top-left corner of the window) to world coordinates,
+/// with the `ViewProjection` resource (main camera). The letterbox bars are taken into account
+/// so a position in the bars will be outside of the world bounds. None if there is no camera.
+pub fn screen_to_world(screen_coords: Vector2f, resources: &Resources) -> Option<Vector2f> {
+    let window_dim = resources.fetch::<WindowDim>().unwrap();
+    let virtual_dim = resources.fetch::<VirtualDim>().unwrap();
+    resources
+        .fetch::<ViewProjection>()?
+        .screen_to_world(screen_coords, &window_dim, &virtual_dim)
+}
+
+/// Convert world coordinates to a position in pixels (from the top-left corner of the window),
+/// with the `ViewProjection` resource (main camera). Inverse of `screen_to_world`.
+pub fn world_to_screen(world_coords: Vector2f, resources: &Resources) -> Option<Vector2f> {
+    let window_dim = resources.fetch::<WindowDim>().unwrap();
+    let virtual_dim = resources.fetch::<VirtualDim>().unwrap();
+    let view_projection = resources.fetch::<ViewProjection>()?;
+    Some(view_projection.world_to_screen(world_coords, &window_dim, &virtual_dim))
+}
+
+/// Projection matrix times the view matrix of the main camera. The game updates the resource at
+/// the beginning of the frame and after the scene update. The inverse is computed the first
+/// time it is needed after the camera or the projection changed.
+#[derive(Clone, Debug)]
+pub struct ViewProjection {
+    matrix: Matrix4f,
+    /// Not computed yet if None. The inner None means that the matrix cannot be inverted.
+    inverse: Cell<Option<Option<Matrix4f>>>,
+}
+
+impl Default for ViewProjection {
+    fn default() -> Self {
+        Self {
+            matrix: Matrix4f::identity(),
+            inverse: Cell::new(Some(Some(Matrix4f::identity()))),
+        }
+    }
 }
 
-pub fn screen_to_world(
-    screen_coords: Vector2f,
-    projection_matrix: Matrix4f,
-    world: &World,
-) -> Vector2f {
-    let view = get_view_matrix(world).unwrap();
-    let pv = projection_matrix * view;
-    let inv = pv.try_inverse().unwrap();
-    let mouse_pos_world = inv * Vector4::new(screen_coords.x, screen_coords.y, 0.0, 1.0);
-    Vector2f::new(mouse_pos_world.x, mouse_pos_world.y)
+impl ViewProjection {
+    pub fn new(projection_matrix: &Matrix4f, view: &Matrix4f) -> Self {
+        Self {
+            matrix: projection_matrix * view,
+            inverse: Cell::new(None),
+        }
+    }
+
+    /// Replace the matrix if the projection or the view changed.
+    pub fn update(&mut self, projection_matrix: &Matrix4f, view: &Matrix4f) {
+        let matrix = projection_matrix * view;
+        if matrix != self.matrix {
+            self.matrix = matrix;
+            self.inverse.set(None);
+        }
+    }
+
+    pub fn matrix(&self) -> &Matrix4f {
+        &self.matrix
+    }
+
+    /// None if the matrix cannot be inverted.
+    pub fn inverse(&self) -> Option<Matrix4f> {
+        match self.inverse.get() {
+            Some(inverse) => inverse,
+            None => {
+                let inverse = self.matrix.try_inverse();
+                self.inverse.set(Some(inverse));
+                inverse
+            }
+        }
+    }
+
+    /// Same as `world_to_screen`.
+    pub fn world_to_screen(
+        &self,
+        world_coords: Vector2f,
+        window_dim: &WindowDim,
+        virtual_dim: &VirtualDim,
+    ) -> Vector2f {
+        let (viewport_w, viewport_h, x, y) = letterbox_viewport(window_dim, virtual_dim);
+        let ndc = self.matrix * Vector4::new(world_coords.x, world_coords.y, 0.0, 1.0);
+
+        let pixel_x = x as f32 + (ndc.x + 1.0) / 2.0 * viewport_w;
+        let pixel_y = y as f32 + (ndc.y + 1.0) / 2.0 * viewport_h;
+        // Viewports have their origin at the bottom-left corner.
+        Vector2f::new(pixel_x, window_dim.height as f32 - pixel_y)
+    }
+
+    /// Same as `screen_to_world`. None if the matrix cannot be inverted.
+    pub fn screen_to_world(
+        &self,
+        screen_coords: Vector2f,
+        window_dim: &WindowDim,
+        virtual_dim: &VirtualDim,
+    ) -> Option<Vector2f> {
+        let (viewport_w, viewport_h, x, y) = letterbox_viewport(window_dim, virtual_dim);
+        // Viewports have their origin at the bottom-left corner.
+        let screen_coords =
+            Vector2f::new(screen_coords.x, window_dim.height as f32 - screen_coords.y);
+        let ndc = pixel_to_ndc(screen_coords, (x, y, viewport_w, viewport_h));
+        let pos = self.inverse()? * Vector4::new(ndc.x, ndc.y, 0.0, 1.0);
+        Some(Vector2f::new(pos.x, pos.y))
+    }
+}
+
+/// Update the `ViewProjection` resource from the `ProjectionMatrix` and the main camera.
+pub fn update_view_projection(world: &World, resources: &Resources) {
+    let view = match get_view_matrix(world) {
+        Some(view) => view,
+        None => return,
+    };
+    let projection = resources.fetch::<ProjectionMatrix>().unwrap().matrix();
+    if let Some(mut view_projection) = resources.fetch_mut::<ViewProjection>() {
+        view_projection.update(&projection, &view);
+    }
+}
+
+/// Normalized device coordinates of a pixel (origin at the bottom-left corner) in the viewport
+/// (x, y, width, height).
+fn pixel_to_ndc(pixel: Vector2f, (x, y, w, h): (u32, u32, f32, f32)) -> Vector2f {
+    Vector2f::new(
+        (pixel.x - x as f32) / w * 2.0 - 1.0,
+        (pixel.y - y as f32) / h * 2.0 - 1.0,
+    )
+}
+
+fn ndc_to_world(ndc: Vector2f, projection_matrix: &Matrix4f, view: &Matrix4f) -> Option<Vector2f> {
+    let inv = (projection_matrix * view).try_inverse()?;
+    let pos = inv * Vector4::new(ndc.x, ndc.y, 0.0, 1.0);
+    Some(Vector2f::new(pos.x, pos.y))
+}
+
+/// Direction of the Y axis in world coordinates.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum YAxis {
+    /// Origin at the bottom-left corner of the screen.
+    Up,
+    /// Origin at the top-left corner of the screen.
+    Down,
+}
+
+impl Default for YAxis {
+    fn default() -> Self {
+        YAxis::Up
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
-pub struct ProjectionMatrix(pub(crate) Matrix4f);
+pub struct ProjectionOptions {
+    pub y_axis: YAxis,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Default for ProjectionOptions {
+    fn default() -> Self {
+        Self {
+            y_axis: YAxis::Up,
+            near: -1.0,
+            far: 10.0,
+        }
+    }
+}
+
+impl ProjectionOptions {
+    fn to_matrix(&self, w: f32, h: f32) -> Matrix4f {
+        match self.y_axis {
+            YAxis::Up => Matrix4f::new_orthographic(0.0, w, 0.0, h, self.near, self.far),
+            YAxis::Down => Matrix4f::new_orthographic(0.0, w, h, 0.0, self.near, self.far),
+        }
+    }
+}
+
+/// Orthographic projection. Use the same matrix in `screen_to_world` so that the coordinates
+/// are consistent with the rendering. The last field is the size of the projected area.
+#[derive(Copy, Clone, Debug)]
+pub struct ProjectionMatrix(pub(crate) Matrix4f, ProjectionOptions, (f32, f32));
 
 impl ProjectionMatrix {
     pub fn new(w: f32, h: f32) -> Self {
-        Self(Matrix4f::new_orthographic(0.0, w, 0.0, h, -1.0, 10.0))
+        Self::with_options(w, h, ProjectionOptions::default())
+    }
+
+    pub fn with_options(w: f32, h: f32, options: ProjectionOptions) -> Self {
+        Self(options.to_matrix(w, h), options, (w, h))
     }
 
     pub fn resize(&mut self, w: f32, h: f32) {
-        self.0 = Matrix4f::new_orthographic(0.0, w, 0.0, h, -1.0, 10.0);
+        self.0 = self.1.to_matrix(w, h);
+        self.2 = (w, h);
+    }
+
+    pub fn matrix(&self) -> Matrix4f {
+        self.0
+    }
+
+    /// Projection of a camera that renders to a part of the screen. The world keeps the scale
+    /// of a fullscreen camera so the visible area has the aspect ratio of the viewport.
+    pub fn for_viewport(&self, viewport: &ViewportRect) -> Matrix4f {
+        if *viewport == ViewportRect::default() {
+            return self.0;
+        }
+        let (w, h) = self.2;
+        self.1.to_matrix(w * viewport.w, h * viewport.h)
+    }
+
+    pub fn options(&self) -> &ProjectionOptions {
+        &self.1
     }
 }
 
 /// Resolution of the game. Independant of window dim
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct VirtualDim(pub u32, pub u32);
 
 impl VirtualDim {
     pub fn aspect(&self) -> f32 {
         self.0 as f32 / self.1 as f32
     }
+
+    /// Change the resolution of the game, e.g. from an options menu. The game rebuilds the
+    /// projection before rendering the frame.
+    pub fn set(&mut self, width: u32, height: u32) {
+        assert!(width > 0 && height > 0, "Virtual dimensions cannot be zero");
+        self.0 = width;
+        self.1 = height;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn two_cameras_two_viewports() {
+        let mut world = World::new();
+        world.spawn((Camera::with_viewport(ViewportRect::left_half()),));
+        world.spawn((Camera::with_viewport(ViewportRect::right_half()),));
+
+        // The game viewport is centered in the window with black bars on the sides.
+        let window_dim = WindowDim::new(1000, 600);
+        let (w, h, x, y) = letterbox_viewport(&window_dim, &VirtualDim(800, 600));
+        let mut rects: Vec<_> = get_render_targets(&world)
+            .iter()
+            .map(|(_, viewport)| viewport.to_pixels(x, y, w, h))
+            .collect();
+        rects.sort();
+        assert_eq!(rects, vec![(100, 0, 400, 600), (500, 0, 400, 600)]);
+    }
+
+    /// World position of a pixel (from the top-left corner) in a 800x600 window.
+    fn pixel_to_world(y_axis: YAxis, pixel: Vector2f) -> Vector2f {
+        let options = ProjectionOptions {
+            y_axis,
+            ..ProjectionOptions::default()
+        };
+        let projection = ProjectionMatrix::with_options(800.0, 600.0, options);
+        ViewProjection::new(&projection.matrix(), &Matrix4f::identity())
+            .screen_to_world(pixel, &WindowDim::new(800, 600), &VirtualDim(800, 600))
+            .unwrap()
+    }
+
+    #[test]
+    fn y_down_projection() {
+        let top = Vector2f::new(0.0, 0.0);
+        let bottom = Vector2f::new(0.0, 600.0);
+        assert!((pixel_to_world(YAxis::Down, top) - Vector2f::new(0.0, 0.0)).norm() < 1e-3);
+        assert!((pixel_to_world(YAxis::Down, bottom) - Vector2f::new(0.0, 600.0)).norm() < 1e-3);
+        assert!((pixel_to_world(YAxis::Up, top) - Vector2f::new(0.0, 600.0)).norm() < 1e-3);
+        assert!((pixel_to_world(YAxis::Up, bottom) - Vector2f::new(0.0, 0.0)).norm() < 1e-3);
+    }
+
+    const WINDOW: WindowDim = WindowDim {
+        width: 200,
+        height: 200,
+    };
+    const VIRTUAL: VirtualDim = VirtualDim(200, 200);
+
+    fn projection() -> Matrix4f {
+        Matrix4f::new_orthographic(-10.0, 10.0, -10.0, 10.0, -1.0, 1.0)
+    }
+
+    fn assert_close(a: Vector2f, b: Vector2f) {
+        assert!((a - b).norm() < 1e-3, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn world_to_screen_and_back() {
+        let vp = ViewProjection::new(&projection(), &Matrix4f::identity());
+        let screen = vp.world_to_screen(Vector2f::new(10.0, 10.0), &WINDOW, &VIRTUAL);
+        assert_close(screen, Vector2f::new(200.0, 0.0));
+
+        let world = vp.screen_to_world(screen, &WINDOW, &VIRTUAL).unwrap();
+        assert_close(world, Vector2f::new(10.0, 10.0));
+    }
+
+    #[test]
+    fn update_invalidates_the_inverse() {
+        let mut vp = ViewProjection::new(&projection(), &Matrix4f::identity());
+        let center = Vector2f::new(100.0, 100.0);
+        assert_close(
+            vp.screen_to_world(center, &WINDOW, &VIRTUAL).unwrap(),
+            Vector2f::zeros(),
+        );
+
+        // Camera moved to (5, 0).
+        let view = Matrix4f::new_translation(&Vector3::new(-5.0, 0.0, 0.0));
+        vp.update(&projection(), &view);
+        assert_close(
+            vp.screen_to_world(center, &WINDOW, &VIRTUAL).unwrap(),
+            Vector2f::new(5.0, 0.0),
+        );
+        // Same as a freshly computed one.
+        assert_eq!(vp.inverse(), (projection() * view).try_inverse());
+    }
+
+    #[test]
+    fn singular_matrix_has_no_inverse() {
+        let vp = ViewProjection::new(&Matrix4f::zeros(), &Matrix4f::identity());
+        assert!(vp.inverse().is_none());
+        assert!(vp
+            .screen_to_world(Vector2f::zeros(), &WINDOW, &VIRTUAL)
+            .is_none());
+    }
 }
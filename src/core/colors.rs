@@ -118,6 +118,13 @@ impl RgbaColor {
     pub fn to_normalized(self) -> [f32; 4] {
         [self.r, self.g, self.b, self.a]
     }
+
+    /// Color with the rgb multiplied by the alpha. The particles are blended with
+    /// `(One, 1 - src_alpha)` which expects premultiplied colors; straight colors are added on
+    /// top of the background instead (glow effect).
+    pub fn premultiplied(&self) -> [f32; 4] {
+        [self.r * self.a, self.g * self.a, self.b * self.a, self.a]
+    }
 }
 
 impl CurveNode for RgbaColor {}
@@ -135,6 +142,32 @@ impl std::ops::Mul<f32> for RgbaColor {
     }
 }
 
+/// Component-wise multiplication (tint).
+impl std::ops::Mul for RgbaColor {
+    type Output = RgbaColor;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self {
+            r: self.r * rhs.r,
+            g: self.g * rhs.g,
+            b: self.b * rhs.b,
+            a: self.a * rhs.a,
+        }
+    }
+}
+
+impl std::ops::MulAssign<f32> for RgbaColor {
+    fn mul_assign(&mut self, rhs: f32) {
+        *self = *self * rhs;
+    }
+}
+
+impl std::ops::AddAssign for RgbaColor {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
 impl std::ops::Add for RgbaColor {
     type Output = Self;
 
@@ -12,6 +12,21 @@ pub trait CurveNode:
 {
 }
 
+/// What the curve returns for a t outside of the first and last x.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum ClampMode {
+    /// Return the first or last y.
+    Clamp,
+    /// Continue the first or last segment.
+    Extrapolate,
+}
+
+impl Default for ClampMode {
+    fn default() -> Self {
+        ClampMode::Clamp
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Curve<T>
 where
@@ -19,6 +34,8 @@ where
 {
     pub(crate) xs: Vec<f32>,
     pub(crate) ys: Vec<T>,
+    #[serde(default)]
+    pub(crate) clamp_mode: ClampMode,
 }
 
 impl<T> Default for Curve<T>
@@ -29,6 +46,7 @@ where
         Self {
             xs: vec![],
             ys: vec![],
+            clamp_mode: ClampMode::default(),
         }
     }
 }
@@ -38,13 +56,33 @@ where
     T: CurveNode,
 {
     pub fn new(xs: Vec<f32>, ys: Vec<T>) -> Self {
-        Self { xs, ys }
+        Self {
+            xs,
+            ys,
+            clamp_mode: ClampMode::default(),
+        }
     }
 
+    pub fn with_clamp_mode(mut self, clamp_mode: ClampMode) -> Self {
+        self.clamp_mode = clamp_mode;
+        self
+    }
+
+    /// Interpolate linearly between the points. Out of range, the result depends on the
+    /// `ClampMode` (`Clamp` by default). A curve with a single point is constant.
     pub fn y(&self, t: f32) -> T {
         // why use a curve otherwise.
         assert!(self.xs.len() == self.ys.len() && !self.ys.is_empty());
 
+        let last = self.ys.len() - 1;
+        if self.clamp_mode == ClampMode::Clamp {
+            if t <= self.xs[0] {
+                return self.ys[0].clone();
+            } else if t >= self.xs[last] {
+                return self.ys[last].clone();
+            }
+        }
+
         // First find the x corresponding to this t. (lower bound)
         let mut idx = 0usize;
         for (i, &x) in self.xs.iter().enumerate() {
@@ -54,9 +92,13 @@ where
 
             idx = i;
         }
+        // Extrapolate past the end with the last segment.
+        if idx == last && last > 0 {
+            idx -= 1;
+        }
 
         let lower_y = unsafe { self.ys.get_unchecked(idx).clone() };
-        if idx == self.ys.len() - 1 {
+        if idx == last {
             lower_y
         } else {
             let lower_t: f32 = *unsafe { self.xs.get_unchecked(idx) };
@@ -69,7 +111,106 @@ where
             val
         }
     }
+
+    /// Evaluate the curve at `n` evenly spaced points from the first to the last x, e.g. to bake
+    /// a color ramp in a texture. The first and last samples are the ends of the curve.
+    pub fn sample(&self, n: usize) -> Vec<T> {
+        assert!(self.xs.len() == self.ys.len() && !self.ys.is_empty());
+        let first = self.xs[0];
+        let last = self.xs[self.xs.len() - 1];
+
+        match n {
+            0 => vec![],
+            1 => vec![self.y(first)],
+            _ => {
+                let step = (last - first) / (n - 1) as f32;
+                (0..n)
+                    .map(|i| {
+                        // Use the exact last x to avoid rounding past the end of the curve.
+                        let t = if i == n - 1 {
+                            last
+                        } else {
+                            first + step * i as f32
+                        };
+                        self.y(t)
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Easing functions used to remap a normalized parameter (between 0 and 1) before
+/// interpolating.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum Easing {
+    Linear,
+    /// Slow start
+    EaseIn,
+    /// Slow end
+    EaseOut,
+    /// Slow start and slow end
+    EaseInOut,
+}
+
+impl Default for Easing {
+    fn default() -> Self {
+        Easing::Linear
+    }
+}
+
+impl Easing {
+    /// Remap t. t is clamped between 0 and 1 first.
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.max(0.0).min(1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    -1.0 + (4.0 - 2.0 * t) * t
+                }
+            }
+        }
+    }
 }
 
 impl CurveNode for Vector2f {}
 impl CurveNode for f32 {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_includes_both_ends() {
+        let curve = Curve::new(vec![0.0, 1.0], vec![0.0f32, 10.0]);
+        assert_eq!(curve.sample(5), vec![0.0, 2.5, 5.0, 7.5, 10.0]);
+        assert_eq!(curve.sample(1), vec![0.0]);
+        assert!(curve.sample(0).is_empty());
+
+        let curve = Curve::new(vec![0.5], vec![3.0f32]);
+        assert_eq!(curve.sample(3), vec![3.0, 3.0, 3.0]);
+    }
+
+    #[test]
+    fn clamp_and_extrapolate() {
+        let curve = Curve::new(vec![0.0, 1.0], vec![0.0f32, 10.0]);
+        assert_eq!(curve.y(2.0), 10.0);
+        assert_eq!(curve.y(-1.0), 0.0);
+
+        let curve = curve.with_clamp_mode(ClampMode::Extrapolate);
+        assert_eq!(curve.y(2.0), 20.0);
+        assert_eq!(curve.y(-1.0), -10.0);
+    }
+
+    #[test]
+    fn single_point_is_constant() {
+        let curve = Curve::new(vec![0.5], vec![3.0f32]).with_clamp_mode(ClampMode::Extrapolate);
+        assert_eq!(curve.y(0.0), 3.0);
+        assert_eq!(curve.y(1.0), 3.0);
+    }
+}
@@ -0,0 +1,45 @@
+//! Deactivate an entity without despawning it. Useful for object pooling.
+//!
+//! A disabled entity is not rendered, not animated and its rigid body is suspended (no
+//! collision, not moving) until the entity is enabled again.
+use crate::core::physics::CollisionWorld;
+use crate::core::transform::Transform;
+use crate::resources::Resources;
+use serde_derive::{Deserialize, Serialize};
+
+/// Marker component. Systems skip the entities that have it.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Disabled;
+
+/// Disable an entity. Its rigid body (if any) is suspended but stays in the simulation, so
+/// its handle and joints are still valid.
+pub fn disable(world: &mut hecs::World, resources: &Resources, e: hecs::Entity) {
+    if let Err(err) = world.insert_one(e, Disabled) {
+        error!("Cannot disable entity {:?} = {:?}", e, err);
+        return;
+    }
+
+    if let Some(mut physics) = resources.fetch_mut::<CollisionWorld>() {
+        physics.suspend_body(e);
+    }
+}
+
+/// Enable an entity that was disabled. Its rigid body (if any) is resumed at the current
+/// position of the entity. A body that was never added to the simulation is added now.
+pub fn enable(world: &mut hecs::World, resources: &Resources, e: hecs::Entity) {
+    if world.remove_one::<Disabled>(e).is_err() {
+        return;
+    }
+
+    if let Some(mut physics) = resources.fetch_mut::<CollisionWorld>() {
+        physics.add_entity(world, e);
+        if let Ok(t) = world.get::<Transform>(e) {
+            physics.resume_body(e, &t);
+        }
+    }
+}
+
+/// Returns true if the entity has the `Disabled` marker.
+pub fn is_disabled(world: &hecs::World, e: hecs::Entity) -> bool {
+    world.get::<Disabled>(e).is_ok()
+}
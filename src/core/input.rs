@@ -3,6 +3,10 @@ use crate::{HEIGHT, WIDTH};
 use serde::de::DeserializeOwned;
 use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
+use std::time::Duration;
+
+/// Buffered presses older than this are forgotten.
+const MAX_BUFFER_DURATION: Duration = Duration::from_secs(1);
 
 pub mod ser;
 pub trait InputAction: Hash + Eq + PartialEq + Clone + DeserializeOwned {
@@ -18,6 +22,17 @@ where
     pub right: A,
 }
 
+/// Bindings used instead of the default ones while the context is on top of the stack.
+#[derive(Debug)]
+struct InputContext<A>
+where
+    A: InputAction,
+{
+    name: String,
+    key_mapping: HashMap<VirtualKey, A>,
+    mouse_mapping: HashMap<VirtualButton, A>,
+}
+
 #[derive(Debug, Default)]
 pub struct Input<A>
 where
@@ -27,11 +42,18 @@ where
     action_state: HashMap<A, bool>,
     just_pressed: HashSet<A>,
     just_released: HashSet<A>,
+    /// Recent presses that were not consumed yet, with the game time since they were pressed.
+    /// See `consume_buffered`.
+    buffered: Vec<(A, Duration)>,
 
     mouse_pos: glam::Vec2,
 
     key_mapping: HashMap<VirtualKey, A>,
     mouse_mapping: HashMap<VirtualButton, A>,
+
+    /// Only the bindings of the top context are active. The default bindings are used when the
+    /// stack is empty.
+    contexts: Vec<InputContext<A>>,
 }
 
 impl<A> Input<A>
@@ -46,41 +68,103 @@ where
             action_state: HashMap::default(),
             just_pressed: HashSet::default(),
             just_released: HashSet::default(),
+            buffered: vec![],
             mouse_pos: glam::Vec2::zero(),
             key_mapping,
             mouse_mapping,
+            contexts: vec![],
+        }
+    }
+
+    /// Replace the active bindings, e.g. when a menu is opened so that the gameplay actions do
+    /// not fire behind it. The actions that were down are released.
+    pub fn push_context(
+        &mut self,
+        name: &str,
+        key_mapping: HashMap<VirtualKey, A>,
+        mouse_mapping: HashMap<VirtualButton, A>,
+    ) {
+        self.release_all();
+        self.contexts.push(InputContext {
+            name: name.to_string(),
+            key_mapping,
+            mouse_mapping,
+        });
+    }
+
+    /// Restore the previous bindings. Returns the name of the removed context.
+    pub fn pop_context(&mut self) -> Option<String> {
+        let context = self.contexts.pop()?;
+        self.release_all();
+        Some(context.name)
+    }
+
+    /// Name of the active context, None for the default bindings.
+    pub fn current_context(&self) -> Option<&str> {
+        self.contexts.last().map(|c| c.name.as_str())
+    }
+
+    fn key_action(&self, key: &VirtualKey) -> Option<A> {
+        match self.contexts.last() {
+            Some(context) => context.key_mapping.get(key).cloned(),
+            None => self.key_mapping.get(key).cloned(),
+        }
+    }
+
+    fn button_action(&self, btn: &VirtualButton) -> Option<A> {
+        match self.contexts.last() {
+            Some(context) => context.mouse_mapping.get(btn).cloned(),
+            None => self.mouse_mapping.get(btn).cloned(),
         }
     }
 
-    pub fn prepare(&mut self) {
+    /// Forget the state of the actions when the bindings change. Otherwise an action could stay
+    /// down as its key release is not bound anymore.
+    fn release_all(&mut self) {
+        self.action_state.clear();
+        self.just_pressed.clear();
+        self.just_released.clear();
+        self.buffered.clear();
+    }
+
+    /// Start a new frame. `dt` is the game time since the last frame (scaled, zero when the
+    /// game is paused); it ages the buffered presses.
+    pub fn prepare(&mut self, dt: Duration) {
         self.just_pressed.clear();
         self.just_released.clear();
+
+        for (_, age) in self.buffered.iter_mut() {
+            *age += dt;
+        }
+        self.buffered.retain(|(_, age)| *age <= MAX_BUFFER_DURATION);
     }
     pub fn process_event(&mut self, ev: InputEvent) {
         match ev {
             InputEvent::KeyEvent(key, VirtualAction::Pressed) => {
-                if let Some(action) = self.key_mapping.get(&key).cloned() {
+                if let Some(action) = self.key_action(&key) {
                     self.action_state.insert(action.clone(), true);
+                    self.buffered.push((action.clone(), Duration::default()));
                     self.just_pressed.insert(action);
                 }
             }
 
             InputEvent::KeyEvent(key, VirtualAction::Release) => {
-                if let Some(action) = self.key_mapping.get(&key).cloned() {
+                if let Some(action) = self.key_action(&key) {
                     self.action_state.insert(action.clone(), false);
                     self.just_released.insert(action);
                 }
             }
 
             InputEvent::MouseEvent(btn, VirtualAction::Pressed) => {
-                if let Some(action) = self.mouse_mapping.get(&btn).cloned() {
+                if let Some(action) = self.button_action(&btn) {
                     self.action_state.insert(action.clone(), true);
+                    self.buffered.push((action.clone(), Duration::default()));
                     self.just_pressed.insert(action);
                 }
             }
 
             InputEvent::MouseEvent(btn, VirtualAction::Release) => {
-                if let Some(action) = self.mouse_mapping.get(&btn).cloned() {
+                if let Some(action) = self.button_action(&btn) {
                     self.action_state.insert(action.clone(), false);
                     self.just_released.insert(action);
                 }
@@ -109,10 +193,31 @@ where
         self.just_released.contains(&action)
     }
 
+    /// Returns true if the action was pressed less than `window` ago and was not consumed yet.
+    /// The press is consumed so it will not trigger twice. Useful to accept a jump pressed just
+    /// before landing. `window` cannot be longer than one second.
+    pub fn consume_buffered(&mut self, action: A, window: Duration) -> bool {
+        let found = self
+            .buffered
+            .iter()
+            .any(|(buffered_action, age)| *buffered_action == action && *age <= window);
+
+        if found {
+            self.buffered
+                .retain(|(buffered_action, _)| *buffered_action != action);
+        }
+        found
+    }
+
     pub fn action_down(&self, action: A) -> bool {
         self.action_state.get(&action).copied().unwrap_or(false)
     }
 
+    /// Position of the cursor in pixels, from the top-left corner of the window.
+    pub fn raw_mouse_position(&self) -> glam::Vec2 {
+        self.mouse_pos
+    }
+
     pub fn mouse_position(&self) -> glam::Vec2 {
         glam::vec2(
             (self.mouse_pos.x() / WIDTH as f32) * 2.0 - 1.0,
@@ -120,3 +225,70 @@ where
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_derive::Deserialize;
+
+    #[derive(Debug, Clone, Hash, PartialEq, Eq, Deserialize)]
+    enum Action {
+        Jump,
+        Confirm,
+    }
+
+    impl InputAction for Action {
+        fn get_default_key_mapping() -> HashMap<VirtualKey, Self> {
+            let mut mapping = HashMap::new();
+            mapping.insert(VirtualKey::Space, Action::Jump);
+            mapping
+        }
+
+        fn get_default_mouse_mapping() -> HashMap<VirtualButton, Self> {
+            HashMap::new()
+        }
+    }
+
+    fn press(input: &mut Input<Action>, key: VirtualKey) {
+        input.process_event(InputEvent::KeyEvent(key, VirtualAction::Pressed));
+    }
+
+    #[test]
+    fn context_masks_the_default_bindings() {
+        let mut input = Input::new(Action::get_default_key_mapping(), HashMap::new());
+        press(&mut input, VirtualKey::Space);
+        assert!(input.action_down(Action::Jump));
+
+        let mut menu = HashMap::new();
+        menu.insert(VirtualKey::Escape, Action::Confirm);
+        input.push_context("menu", menu, HashMap::new());
+        assert_eq!(input.current_context(), Some("menu"));
+        // Released when the context is pushed.
+        assert!(!input.action_down(Action::Jump));
+
+        input.prepare(Duration::default());
+        press(&mut input, VirtualKey::Space);
+        press(&mut input, VirtualKey::Escape);
+        assert!(!input.is_just_pressed(Action::Jump));
+        assert!(input.is_just_pressed(Action::Confirm));
+
+        assert_eq!(input.pop_context(), Some("menu".to_string()));
+        input.prepare(Duration::default());
+        press(&mut input, VirtualKey::Space);
+        assert!(input.is_just_pressed(Action::Jump));
+    }
+
+    #[test]
+    fn buffered_presses_age_with_the_game_time() {
+        let mut input = Input::new(Action::get_default_key_mapping(), HashMap::new());
+        press(&mut input, VirtualKey::Space);
+
+        // Paused frames do not age the press.
+        input.prepare(Duration::default());
+        input.prepare(Duration::from_millis(50));
+        assert!(!input.consume_buffered(Action::Jump, Duration::from_millis(40)));
+        assert!(input.consume_buffered(Action::Jump, Duration::from_millis(60)));
+        // Consumed.
+        assert!(!input.consume_buffered(Action::Jump, Duration::from_millis(60)));
+    }
+}
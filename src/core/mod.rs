@@ -3,12 +3,20 @@ pub mod audio;
 pub mod camera;
 pub mod colors;
 pub mod curve;
+pub mod disable;
 pub mod input;
+pub mod name;
 pub mod noise;
+pub mod owner;
+pub mod path;
 pub mod physics;
 pub mod random;
+pub mod registry;
 pub mod scene;
+pub mod scheduler;
 pub mod serialization;
+pub mod spatial;
 pub mod timer;
 pub mod transform;
+pub mod tween;
 pub mod window;
@@ -0,0 +1,132 @@
+//! Give names to entities and find them by name (level scripting, debugging...).
+use crate::event::{CustomGameEvent, EventReader, GameEvent};
+use crate::resources::Resources;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Name(pub String);
+
+/// Entities by name. Updated by the game from the `Spawned` and `Delete` events. Several
+/// entities can have the same name.
+#[derive(Debug, Default)]
+pub struct NameIndex {
+    by_name: HashMap<String, Vec<hecs::Entity>>,
+    by_entity: HashMap<hecs::Entity, String>,
+}
+
+impl NameIndex {
+    /// First entity spawned with this name.
+    pub fn find_by_name(&self, name: &str) -> Option<hecs::Entity> {
+        self.by_name
+            .get(name)
+            .and_then(|entities| entities.first())
+            .copied()
+    }
+
+    /// All the entities with this name, in spawn order.
+    pub fn find_all_by_name(&self, name: &str) -> &[hecs::Entity] {
+        self.by_name
+            .get(name)
+            .map(|entities| entities.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Add an entity to the index. Entities spawned from a prefab or with `notify_spawned` are
+    /// added automatically.
+    pub fn insert(&mut self, e: hecs::Entity, name: &str) {
+        self.remove(e);
+        self.by_name.entry(name.to_string()).or_default().push(e);
+        self.by_entity.insert(e, name.to_string());
+    }
+
+    pub fn remove(&mut self, e: hecs::Entity) {
+        if let Some(name) = self.by_entity.remove(&e) {
+            if let Some(entities) = self.by_name.get_mut(&name) {
+                entities.retain(|other| *other != e);
+                if entities.is_empty() {
+                    self.by_name.remove(&name);
+                }
+            }
+        }
+    }
+}
+
+/// Keep the `NameIndex` up to date.
+pub struct NameIndexSystem<GE>
+where
+    GE: CustomGameEvent,
+{
+    reader: EventReader<GE>,
+}
+
+impl<GE> NameIndexSystem<GE>
+where
+    GE: CustomGameEvent,
+{
+    pub fn new(resources: &mut Resources) -> Self {
+        Self {
+            reader: EventReader::new(resources),
+        }
+    }
+
+    pub fn update(&mut self, world: &hecs::World, resources: &Resources) {
+        let mut index = resources.fetch_mut::<NameIndex>().unwrap();
+        for ev in self.reader.read(resources) {
+            match ev {
+                GameEvent::Spawned(e) => {
+                    if let Ok(name) = world.get::<Name>(e) {
+                        index.insert(e, &name.0);
+                    }
+                }
+                GameEvent::Delete(e) => index.remove(e),
+                _ => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventQueue;
+
+    #[derive(Debug, Clone)]
+    struct TestEvent;
+    impl CustomGameEvent for TestEvent {}
+
+    #[test]
+    fn spawn_and_find() {
+        let mut world = hecs::World::new();
+        let mut resources = Resources::default();
+        resources.insert(EventQueue::<TestEvent>::new());
+        resources.insert(NameIndex::default());
+        let mut system = NameIndexSystem::<TestEvent>::new(&mut resources);
+
+        let door = world.spawn((Name("door".to_string()),));
+        let other_door = world.spawn((Name("door".to_string()),));
+        let nameless = world.spawn(());
+        {
+            let mut chan = resources.fetch_mut::<EventQueue<TestEvent>>().unwrap();
+            for e in &[door, other_door, nameless] {
+                chan.single_write(GameEvent::Spawned(*e));
+            }
+        }
+        system.update(&world, &resources);
+
+        {
+            let index = resources.fetch::<NameIndex>().unwrap();
+            assert_eq!(index.find_by_name("door"), Some(door));
+            assert_eq!(index.find_all_by_name("door"), &[door, other_door]);
+            assert_eq!(index.find_by_name("key"), None);
+        }
+
+        resources
+            .fetch_mut::<EventQueue<TestEvent>>()
+            .unwrap()
+            .single_write(GameEvent::Delete(door));
+        system.update(&world, &resources);
+        let index = resources.fetch::<NameIndex>().unwrap();
+        assert_eq!(index.find_by_name("door"), Some(other_door));
+    }
+}
@@ -0,0 +1,21 @@
+//! Who spawned an entity (e.g. the shooter of a projectile).
+
+/// The entity that owns this one. Used for scoring or to ignore the collisions with the owner.
+/// The collision events between the entity and its owner are ignored. Add the `Owner` before
+/// spawning the body; for a body that already exists, call `CollisionWorld::ignore_pair`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Owner(pub hecs::Entity);
+
+/// Entities with an `Owner` and this component are deleted with their owner.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OwnedLifetime;
+
+/// Entities owned by `owner` that should be deleted with it.
+pub fn owned_with_lifetime(world: &hecs::World, owner: hecs::Entity) -> Vec<hecs::Entity> {
+    world
+        .query::<(&Owner, &OwnedLifetime)>()
+        .iter()
+        .filter(|(_, (o, _))| o.0 == owner)
+        .map(|(e, _)| e)
+        .collect()
+}
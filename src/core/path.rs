@@ -0,0 +1,117 @@
+//! Move entities along waypoints (patrols, moving platforms...).
+use crate::core::curve::Curve;
+use crate::core::timer::scaled_dt;
+use crate::core::transform::Transform;
+use crate::geom2::Vector2f;
+use crate::resources::Resources;
+use serde_derive::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Polyline through waypoints. The parameter is normalized by the length so that the speed is
+/// constant along the path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Path2D {
+    curve: Curve<Vector2f>,
+    length: f32,
+}
+
+impl Path2D {
+    pub fn new(mut waypoints: Vec<Vector2f>) -> Self {
+        assert!(!waypoints.is_empty(), "A path needs at least one waypoint");
+        // Zero-length segments would break the interpolation.
+        waypoints.dedup();
+
+        let mut distances = vec![0.0];
+        let mut length = 0.0;
+        for segment in waypoints.windows(2) {
+            length += (segment[1] - segment[0]).norm();
+            distances.push(length);
+        }
+
+        let xs = if length > 0.0 {
+            distances.iter().map(|d| d / length).collect()
+        } else {
+            distances
+        };
+
+        Self {
+            curve: Curve::new(xs, waypoints),
+            length,
+        }
+    }
+
+    /// Position at t (between 0 and 1, clamped).
+    pub fn position_at(&self, t: f32) -> Vector2f {
+        self.curve.y(t)
+    }
+
+    pub fn length(&self) -> f32 {
+        self.length
+    }
+}
+
+/// Move the entity along the path at `speed` units per second.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FollowPath {
+    pub path: Path2D,
+    pub speed: f32,
+    /// Start again from the beginning at the end of the path.
+    #[serde(default)]
+    pub looping: bool,
+    /// Distance travelled along the path.
+    #[serde(default)]
+    pub distance: f32,
+}
+
+impl FollowPath {
+    pub fn new(path: Path2D, speed: f32) -> Self {
+        Self {
+            path,
+            speed,
+            looping: false,
+            distance: 0.0,
+        }
+    }
+
+    pub fn looping(mut self) -> Self {
+        self.looping = true;
+        self
+    }
+
+    /// True when the entity reached the last waypoint (never for looping paths).
+    pub fn finished(&self) -> bool {
+        !self.looping && self.distance >= self.path.length()
+    }
+
+    fn advance(&mut self, dt: Duration) -> Vector2f {
+        let length = self.path.length();
+        self.distance += self.speed * dt.as_secs_f32();
+        if self.looping && length > 0.0 {
+            self.distance %= length;
+        } else {
+            self.distance = self.distance.min(length);
+        }
+
+        let t = if length > 0.0 {
+            self.distance / length
+        } else {
+            1.0
+        };
+        self.path.position_at(t)
+    }
+}
+
+pub struct FollowPathSystem;
+
+impl FollowPathSystem {
+    pub fn update(&mut self, world: &mut hecs::World, dt: Duration, resources: &Resources) {
+        let dt = scaled_dt(resources, dt);
+        for (_, (transform, follow)) in world.query::<(&mut Transform, &mut FollowPath)>().iter() {
+            if follow.finished() {
+                continue;
+            }
+            transform.translation = follow.advance(dt);
+            transform.dirty = true;
+        }
+    }
+}
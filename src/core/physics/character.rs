@@ -0,0 +1,294 @@
+//! Move a kinematic body with precise platformer controls: the body slides along the walls,
+//! climbs small steps and knows when it is on the ground.
+use super::CollisionWorld;
+use crate::geom2::Vector2f;
+use rapier2d::dynamics::RigidBodyHandle;
+use rapier2d::geometry::Cuboid;
+use rapier2d::ncollide::na::Isometry2;
+use rapier2d::ncollide::query::{self, TOIStatus};
+use serde_derive::{Deserialize, Serialize};
+use std::time::Duration;
+
+const EPSILON: f32 = 1.0e-4;
+
+/// Settings of the character controller. Use it with a kinematic body
+/// (`RigidBodyComponent::new_kinematic_cuboid`).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CharacterController {
+    /// Obstacles lower than this will be climbed automatically.
+    pub max_step_height: f32,
+    /// Surfaces with a slope lower than this angle (in radians) are considered as ground.
+    pub max_slope_angle: f32,
+    /// Distance kept between the body and the obstacles.
+    pub skin_width: f32,
+    /// Maximum number of slides per move.
+    pub max_iterations: usize,
+}
+
+impl Default for CharacterController {
+    fn default() -> Self {
+        Self {
+            max_step_height: 4.0,
+            max_slope_angle: std::f32::consts::FRAC_PI_4,
+            skin_width: 0.01,
+            max_iterations: 4,
+        }
+    }
+}
+
+/// Result of `CollisionWorld::move_character`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CharacterMovement {
+    /// Movement that was actually applied to the body.
+    pub translation: Vector2f,
+    /// True if the body is standing on the ground after the move.
+    pub grounded: bool,
+}
+
+/// Let the character jump a short time after walking off a ledge. Update it every frame with the
+/// result of `CollisionWorld::move_character`.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct CoyoteTime {
+    /// Seconds since the character was last on the ground. None if it never was, or if it
+    /// jumped since.
+    #[serde(skip)]
+    since_grounded: Option<f32>,
+}
+
+impl CoyoteTime {
+    pub fn update(&mut self, grounded: bool, dt: Duration) {
+        if grounded {
+            self.since_grounded = Some(0.0);
+        } else if let Some(ref mut since) = self.since_grounded {
+            *since += dt.as_secs_f32();
+        }
+    }
+
+    /// Returns true if the character is on the ground or left it less than `window` ago.
+    pub fn can_coyote_jump(&self, window: Duration) -> bool {
+        self.since_grounded
+            .map(|since| since <= window.as_secs_f32())
+            .unwrap_or(false)
+    }
+
+    /// Close the window after a jump so that the character cannot jump again in the air.
+    pub fn jumped(&mut self) {
+        self.since_grounded = None;
+    }
+}
+
+impl CharacterController {
+    fn is_ground(&self, normal: &Vector2f) -> bool {
+        normal.y >= self.max_slope_angle.cos()
+    }
+}
+
+impl CollisionWorld {
+    /// Move the body by `desired`, sliding along the obstacles. Sensors are ignored.
+    pub fn move_character(
+        &mut self,
+        h: RigidBodyHandle,
+        controller: &CharacterController,
+        desired: Vector2f,
+    ) -> CharacterMovement {
+        let (start, half_extents) = match (self.bodies.get(h), self.shapes.get(&h)) {
+            (Some(rb), Some(shape)) => (rb.position().translation.vector, shape.half_extents),
+            _ => return CharacterMovement::default(),
+        };
+
+        let mut position = start;
+        let mut remaining = desired;
+        let mut grounded = false;
+        for _ in 0..controller.max_iterations {
+            if remaining.norm() < EPSILON {
+                break;
+            }
+
+            match self.sweep(h, &position, &half_extents, &remaining) {
+                None => {
+                    position += remaining;
+                    break;
+                }
+                Some((t, normal)) => {
+                    position += remaining * t + normal * controller.skin_width;
+                    let leftover = remaining * (1.0 - t);
+
+                    if controller.is_ground(&normal) {
+                        grounded = true;
+                    } else if normal.y.abs() < EPSILON {
+                        // Hit a wall. Maybe it is just a small step.
+                        if let Some(stepped) =
+                            self.try_step(h, controller, &position, &half_extents, &leftover)
+                        {
+                            position = stepped;
+                            grounded = true;
+                            break;
+                        }
+                    }
+
+                    // Slide along the obstacle.
+                    remaining = leftover - normal * leftover.dot(&normal);
+                }
+            }
+        }
+
+        // Check if there is ground just below the body.
+        if !grounded {
+            let probe = Vector2f::new(0.0, -2.0 * controller.skin_width);
+            if let Some((_, normal)) = self.sweep(h, &position, &half_extents, &probe) {
+                grounded = controller.is_ground(&normal);
+            }
+        }
+
+        self.set_position(h, &position);
+        CharacterMovement {
+            translation: position - start,
+            grounded,
+        }
+    }
+
+    /// Go up, forward then down. Returns the new position if the body lands on the ground.
+    fn try_step(
+        &self,
+        h: RigidBodyHandle,
+        controller: &CharacterController,
+        position: &Vector2f,
+        half_extents: &Vector2f,
+        forward: &Vector2f,
+    ) -> Option<Vector2f> {
+        let forward = Vector2f::new(forward.x, 0.0);
+        if controller.max_step_height <= 0.0 || forward.norm() < EPSILON {
+            return None;
+        }
+
+        let up = Vector2f::new(0.0, controller.max_step_height);
+        let up = match self.sweep(h, position, half_extents, &up) {
+            Some((t, _)) => up * t,
+            None => up,
+        };
+        let raised = position + up;
+
+        if self.sweep(h, &raised, half_extents, &forward).is_some() {
+            // Still blocked, this is a real wall.
+            return None;
+        }
+        let moved = raised + forward;
+
+        let down = -up;
+        match self.sweep(h, &moved, half_extents, &down) {
+            Some((t, normal)) if controller.is_ground(&normal) => {
+                Some(moved + down * t + normal * controller.skin_width)
+            }
+            _ => None,
+        }
+    }
+
+    /// Sweep the box of the body at `position` along `displacement`. Returns the fraction of the
+    /// displacement before the first hit and the normal of the obstacle. The obstacles are the
+    /// colliders of the query pipeline that can interact with the groups of the body, except the
+    /// body itself and the sensors. Obstacles that already overlap the box are ignored so that
+    /// the body can get out of them.
+    fn sweep(
+        &self,
+        h: RigidBodyHandle,
+        position: &Vector2f,
+        half_extents: &Vector2f,
+        displacement: &Vector2f,
+    ) -> Option<(f32, Vector2f)> {
+        let center = position
+            + self
+                .shapes
+                .get(&h)
+                .map(|shape| shape.offset)
+                .unwrap_or_else(Vector2f::zeros);
+        let moving = Cuboid::new(*half_extents);
+        let start = Isometry2::translation(center.x, center.y);
+
+        // `cast_shape` only returns the first hit, which would be the body itself, so the
+        // candidates are the colliders touching the box that covers the whole movement.
+        let swept_center = center + displacement / 2.0;
+        let swept = Cuboid::new(half_extents + displacement.abs() / 2.0);
+
+        let mut closest: Option<(f32, Vector2f)> = None;
+        self.query_pipeline.intersections_with_shape(
+            &self.colliders,
+            &Isometry2::translation(swept_center.x, swept_center.y),
+            &swept,
+            self.body_groups(h),
+            |_, collider| {
+                let other_h = collider.parent();
+                if other_h == h || collider.is_sensor() {
+                    return true;
+                }
+                let (other, shape) = match (self.bodies.get(other_h), self.shapes.get(&other_h)) {
+                    (Some(other), Some(shape)) => (other, shape),
+                    _ => return true,
+                };
+
+                let other_position = other.position().translation.vector + shape.offset;
+                let toi = query::time_of_impact(
+                    &start,
+                    displacement,
+                    &moving,
+                    &Isometry2::translation(other_position.x, other_position.y),
+                    &Vector2f::zeros(),
+                    &Cuboid::new(shape.half_extents),
+                    1.0,
+                    0.0,
+                );
+                if let Some(toi) = toi {
+                    if toi.status != TOIStatus::Penetrating
+                        && closest.map(|(t, _)| toi.toi < t).unwrap_or(true)
+                    {
+                        // The box is not rotated so its local normal is also the world normal.
+                        closest = Some((toi.toi, -toi.normal1.into_inner()));
+                    }
+                }
+                true
+            },
+        );
+        closest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::physics::RigidBodyComponent;
+    use crate::core::transform::Transform;
+    use crate::event::{CustomGameEvent, EventQueue};
+    use crate::resources::Resources;
+    use rapier2d::geometry::InteractionGroups;
+
+    #[derive(Debug, Clone)]
+    struct TestEvent;
+    impl CustomGameEvent for TestEvent {}
+
+    #[test]
+    fn slide_along_a_wall() {
+        let mut physics = CollisionWorld::default();
+        let mut character = RigidBodyComponent::new_kinematic_cuboid(1.0, 1.0);
+        character.interaction_group = InteractionGroups::all();
+        let h = physics.add_body(&Transform::default(), &mut character);
+        // Too high to be climbed as a step.
+        let mut wall = RigidBodyComponent::new_static_cuboid(0.5, 10.0);
+        wall.interaction_group = InteractionGroups::all();
+        physics.add_body(
+            &Transform::from_translation(Vector2f::new(3.0, 0.0)),
+            &mut wall,
+        );
+        // Update the query pipeline.
+        let mut resources = Resources::default();
+        resources.insert(EventQueue::<TestEvent>::new());
+        physics.step::<TestEvent>(&resources);
+
+        let controller = CharacterController::default();
+        let movement = physics.move_character(h, &controller, Vector2f::new(5.0, 1.0));
+
+        // Stopped by the wall along x but the movement along y is kept.
+        let expected_x = 3.0 - 0.5 - 1.0 - controller.skin_width;
+        assert!((movement.translation.x - expected_x).abs() < 1.0e-3);
+        assert!((movement.translation.y - 1.0).abs() < 1.0e-3);
+        assert!(!movement.grounded);
+    }
+}
@@ -0,0 +1,174 @@
+//! Named collision layers, so that prefabs can use `"memberships": ["player"]` instead of raw
+//! bitmasks for the interaction groups.
+//!
+//! Layers must be registered before loading the prefabs, from the thread that loads them.
+use rapier2d::geometry::InteractionGroups;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cell::RefCell;
+
+/// Rapier supports 16 groups.
+const MAX_LAYERS: usize = 16;
+
+thread_local! {
+    static LAYERS: RefCell<Vec<String>> = RefCell::new(vec![]);
+}
+
+/// Register a layer and get its bit. Registering the same name twice returns the same bit.
+/// Returns None if all the layers are used.
+pub fn register_layer(name: &str) -> Option<u16> {
+    LAYERS.with(|layers| {
+        let mut layers = layers.borrow_mut();
+        if let Some(index) = layers.iter().position(|l| l == name) {
+            return Some(1 << index);
+        }
+        if layers.len() >= MAX_LAYERS {
+            error!(
+                "Cannot register layer {}, only {} layers are supported",
+                name, MAX_LAYERS
+            );
+            return None;
+        }
+        layers.push(name.to_string());
+        Some(1 << (layers.len() - 1))
+    })
+}
+
+/// Bit of a registered layer.
+pub fn layer_bit(name: &str) -> Option<u16> {
+    LAYERS.with(|layers| {
+        layers
+            .borrow()
+            .iter()
+            .position(|l| l == name)
+            .map(|index| 1 << index)
+    })
+}
+
+/// Names of the layers in the bitmask. None if one of the bits is not a registered layer.
+fn layer_names(bits: u16) -> Option<Vec<String>> {
+    LAYERS.with(|layers| {
+        let layers = layers.borrow();
+        let mut names = vec![];
+        for index in 0..MAX_LAYERS {
+            if bits & (1 << index) != 0 {
+                names.push(layers.get(index)?.clone());
+            }
+        }
+        Some(names)
+    })
+}
+
+fn layers_to_bits(names: &[String]) -> Result<u16, String> {
+    names.iter().try_fold(0, |bits, name| {
+        layer_bit(name)
+            .map(|bit| bits | bit)
+            .ok_or_else(|| format!("Unknown collision layer {}", name))
+    })
+}
+
+/// Interaction groups from layer names. Unknown layers are ignored.
+pub fn layer_groups(memberships: &[&str], collides_with: &[&str]) -> InteractionGroups {
+    let to_bits = |names: &[&str]| {
+        names.iter().fold(0, |bits, name| match layer_bit(name) {
+            Some(bit) => bits | bit,
+            None => {
+                warn!("Unknown collision layer {}", name);
+                bits
+            }
+        })
+    };
+    InteractionGroups::new(to_bits(memberships), to_bits(collides_with))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum GroupsRepr {
+    Named {
+        memberships: Vec<String>,
+        collides_with: Vec<String>,
+    },
+    /// Raw bitmask, groups in the 16 high bits and mask in the 16 low bits.
+    Raw(u32),
+}
+
+/// Use with `#[serde(with = "named_groups")]`. Groups are written with the layer names when all
+/// the bits are registered layers, otherwise as the raw bitmask.
+pub mod named_groups {
+    use super::*;
+
+    pub fn serialize<S>(groups: &InteractionGroups, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let memberships = (groups.0 >> 16) as u16;
+        let collides_with = (groups.0 & 0xffff) as u16;
+        let repr = match (layer_names(memberships), layer_names(collides_with)) {
+            (Some(memberships), Some(collides_with)) => GroupsRepr::Named {
+                memberships,
+                collides_with,
+            },
+            _ => GroupsRepr::Raw(groups.0),
+        };
+        repr.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<InteractionGroups, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match GroupsRepr::deserialize(deserializer)? {
+            GroupsRepr::Named {
+                memberships,
+                collides_with,
+            } => {
+                let memberships = layers_to_bits(&memberships).map_err(serde::de::Error::custom)?;
+                let collides_with =
+                    layers_to_bits(&collides_with).map_err(serde::de::Error::custom)?;
+                Ok(InteractionGroups::new(memberships, collides_with))
+            }
+            GroupsRepr::Raw(bits) => Ok(InteractionGroups(bits)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct Body {
+        #[serde(with = "named_groups")]
+        groups: InteractionGroups,
+    }
+
+    #[test]
+    fn named_groups_round_trip() {
+        register_layer("player");
+        register_layer("enemy");
+        let body = Body {
+            groups: layer_groups(&["player"], &["enemy"]),
+        };
+
+        let json = serde_json::to_value(&body).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "groups": {"memberships": ["player"], "collides_with": ["enemy"]}
+            })
+        );
+        let read: Body = serde_json::from_value(json).unwrap();
+        assert_eq!(read.groups.0, body.groups.0);
+
+        // Bits that are not registered layers are written as the raw bitmask.
+        let raw = Body {
+            groups: InteractionGroups::all(),
+        };
+        let json = serde_json::to_value(&raw).unwrap();
+        assert_eq!(json, serde_json::json!({ "groups": std::u32::MAX }));
+
+        let unknown = serde_json::json!({
+            "groups": {"memberships": ["ghost"], "collides_with": []}
+        });
+        assert!(serde_json::from_value::<Body>(unknown).is_err());
+    }
+}
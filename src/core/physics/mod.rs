@@ -1,3 +1,6 @@
+use crate::core::disable::Disabled;
+use crate::core::owner::Owner;
+use crate::core::timer::{is_paused, TimeScale};
 use crate::core::transform::Transform;
 use crate::event::{CustomGameEvent, EventQueue, GameEvent};
 use crate::geom2::Vector2f;
@@ -5,25 +8,55 @@ use crate::resources::Resources;
 use bitflags::_core::cell::RefCell;
 use downcast_rs::__std::sync::Mutex;
 use rapier2d::dynamics::{
-    BodyStatus, IntegrationParameters, JointSet, RigidBodyBuilder, RigidBodyHandle, RigidBodySet,
+    ActivationStatus, BallJoint, BodyStatus, FixedJoint, IntegrationParameters, JointHandle,
+    JointSet, RigidBodyBuilder, RigidBodyHandle, RigidBodySet,
 };
 use rapier2d::geometry::{
-    BroadPhase, Collider, ColliderBuilder, ColliderSet, ContactEvent, InteractionGroups,
-    NarrowPhase, ProximityEvent,
+    Ball, BroadPhase, Collider, ColliderBuilder, ColliderHandle, ColliderSet, ContactEvent,
+    InteractionGroups, NarrowPhase, ProximityEvent,
 };
-use rapier2d::ncollide::na::Isometry2;
+use rapier2d::ncollide::na::{Isometry2, Point2};
 use rapier2d::ncollide::query::Proximity;
-use rapier2d::pipeline::{EventHandler, PhysicsPipeline};
+use rapier2d::pipeline::{EventHandler, PhysicsPipeline, QueryPipeline};
 use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
+mod character;
+pub mod layers;
+pub use character::{CharacterController, CharacterMovement, CoyoteTime};
+
+/// Settings of the simulation.
+///
+/// For lockstep multiplayer, every machine must use the same configuration. Identical inputs
+/// then give identical states on the same platform. Across platforms, enable the
+/// `deterministic-physics` feature as well; it makes rapier use software implementations of the
+/// math functions, which is slower. The `TimeScale` changes the time step so it should also be
+/// the same everywhere.
 pub struct PhysicConfiguration {
     pub gravity: f32,
+    /// Dynamic bodies with a kinetic energy below this threshold will fall asleep and stop
+    /// being simulated until something wakes them up. A negative value disables sleeping.
+    pub sleep_threshold: f32,
+    /// Time step of the simulation in seconds. The fixed update of the game uses the same
+    /// time step.
+    pub dt: f32,
+    /// Iterations of the constraint solver per step. More iterations are more precise but slower.
+    pub velocity_iterations: usize,
+    /// Iterations of the position correction per step.
+    pub position_iterations: usize,
 }
 
 impl Default for PhysicConfiguration {
     fn default() -> Self {
-        Self { gravity: -9.81 }
+        let parameters = IntegrationParameters::default();
+        Self {
+            gravity: -9.81,
+            sleep_threshold: ActivationStatus::default_threshold(),
+            dt: parameters.dt(),
+            velocity_iterations: parameters.max_velocity_iterations,
+            position_iterations: parameters.max_position_iterations,
+        }
     }
 }
 
@@ -39,7 +72,27 @@ pub struct RigidBodyComponent {
     #[serde(skip)]
     pub handle: Option<RigidBodyHandle>,
     pub damping: f32,
+    /// Written with the names of the layers in the prefabs. See `layers`.
+    #[serde(with = "layers::named_groups")]
     pub interaction_group: InteractionGroups,
+
+    /// Position of the collider relative to the center of the body.
+    #[serde(default = "Vector2f::zeros")]
+    pub collider_offset: Vector2f,
+
+    /// Multiply the collider half-extents and offset by the scale of the `Transform` when the
+    /// body is added. See `CollisionWorld::update_collider_scales` for scales that change.
+    #[serde(default)]
+    pub scale_collider: bool,
+
+    /// If false, the body can rotate (e.g. with a revolute joint) and its rotation is written
+    /// to the `Transform`. True by default.
+    #[serde(default = "default_lock_rotations")]
+    pub lock_rotations: bool,
+}
+
+fn default_lock_rotations() -> bool {
+    true
 }
 
 impl RigidBodyComponent {
@@ -52,6 +105,26 @@ impl RigidBodyComponent {
             handle: None,
             damping: 0.0,
             interaction_group: InteractionGroups::none(),
+            collider_offset: Vector2f::zeros(),
+            scale_collider: false,
+            lock_rotations: true,
+        }
+    }
+
+    /// Static trigger zone. It does not block other bodies but will generate proximity events
+    /// (enter and exit) when a body goes through it.
+    pub fn new_sensor_cuboid(hx: f32, hy: f32) -> Self {
+        Self {
+            status: BodyStatus::Static,
+            collider: ColliderComponent::Aabb(hx, hy),
+            should_sync: true,
+            sensor: true,
+            handle: None,
+            damping: 0.0,
+            interaction_group: InteractionGroups::all(),
+            collider_offset: Vector2f::zeros(),
+            scale_collider: false,
+            lock_rotations: true,
         }
     }
 
@@ -64,6 +137,9 @@ impl RigidBodyComponent {
             handle: None,
             damping: 0.0,
             interaction_group: InteractionGroups::none(),
+            collider_offset: Vector2f::zeros(),
+            scale_collider: false,
+            lock_rotations: true,
         }
     }
 
@@ -77,10 +153,88 @@ impl RigidBodyComponent {
             handle: None,
             damping: 0.0,
             interaction_group: InteractionGroups::none(),
+            collider_offset: Vector2f::zeros(),
+            scale_collider: false,
+            lock_rotations: true,
         }
     }
+
+    /// Move the collider relative to the body, e.g. a feet sensor below the center.
+    pub fn with_collider_offset(mut self, offset: Vector2f) -> Self {
+        self.collider_offset = offset;
+        self
+    }
+
+    /// Scale the collider with the entity's transform.
+    pub fn with_scaled_collider(mut self) -> Self {
+        self.scale_collider = true;
+        self
+    }
+
+    /// Let the body rotate. Needed for the bodies attached with a revolute joint.
+    pub fn with_rotations(mut self) -> Self {
+        self.lock_rotations = false;
+        self
+    }
+
+    /// Half-extents and offset of the collider for this scale.
+    fn collider_dimensions(&self, scale: &Vector2f) -> (Vector2f, Vector2f) {
+        let ColliderComponent::Aabb(hx, hy) = self.collider;
+        let half_extents = Vector2f::new(hx, hy);
+        if self.scale_collider {
+            (
+                half_extents.component_mul(scale).abs(),
+                self.collider_offset.component_mul(scale),
+            )
+        } else {
+            (half_extents, self.collider_offset)
+        }
+    }
+}
+
+/// Where two colliders touch, in world coordinates.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ContactDetails {
+    pub point: Vector2f,
+    /// Normal of the contact, going from the first collider to the second one.
+    pub normal: Vector2f,
 }
 
+/// Linear velocity of a rigid body. It is written to the body before each physics step and
+/// read back after so that gameplay code can use it as plain data. Ignored for static bodies.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct Velocity(pub Vector2f);
+
+/// Replace the global gravity for the dynamic bodies inside the zone (water, low gravity
+/// caves...). Add this to an entity that has a sensor rigid body. When zones overlap, the one
+/// with the highest priority is used.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct GravityZone {
+    pub gravity: Vector2f,
+    #[serde(default)]
+    pub priority: i32,
+}
+
+impl GravityZone {
+    pub fn new(gravity: Vector2f) -> Self {
+        Self {
+            gravity,
+            priority: 0,
+        }
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+/// Force that pushes the particles and the dynamic bodies (wind...). Insert it as a resource to
+/// enable it. Light bodies are pushed more than heavy ones. Change it every frame to make gusts,
+/// for example with a `Curve<Vector2f>`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct GlobalForce(pub Vector2f);
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum ColliderComponent {
     /// Half-extend
@@ -88,15 +242,73 @@ pub enum ColliderComponent {
 }
 
 impl ColliderComponent {
-    pub fn to_collider(&self, interaction_groups: InteractionGroups, is_sensor: bool) -> Collider {
+    pub fn to_collider(
+        &self,
+        interaction_groups: InteractionGroups,
+        is_sensor: bool,
+        offset: &Vector2f,
+    ) -> Collider {
         let ColliderComponent::Aabb(hx, hy) = self;
-        ColliderBuilder::cuboid(*hx, *hy)
-            .collision_groups(interaction_groups)
-            .sensor(is_sensor)
-            .build()
+        cuboid_collider(
+            &Vector2f::new(*hx, *hy),
+            offset,
+            interaction_groups,
+            is_sensor,
+        )
+    }
+}
+
+fn cuboid_collider(
+    half_extents: &Vector2f,
+    offset: &Vector2f,
+    interaction_groups: InteractionGroups,
+    is_sensor: bool,
+) -> Collider {
+    ColliderBuilder::cuboid(half_extents.x, half_extents.y)
+        .translation(offset.x, offset.y)
+        .collision_groups(interaction_groups)
+        .sensor(is_sensor)
+        .build()
+}
+
+fn ordered_pair(a: hecs::Entity, b: hecs::Entity) -> (hecs::Entity, hecs::Entity) {
+    if a.to_bits() <= b.to_bits() {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+/// Ordered collider pair then kind of event. Collider handles are the same for identical
+/// simulations.
+fn event_sort_key<GE>(event: &GameEvent<GE>) -> ((usize, u64), (usize, u64), u8)
+where
+    GE: CustomGameEvent,
+{
+    let (c1, c2, kind) = match event {
+        GameEvent::ProximityEvent(c1, c2) => (c1, c2, 0),
+        GameEvent::ProximityExited(c1, c2) => (c1, c2, 1),
+        GameEvent::ContactEvent(c1, c2, _) => (c1, c2, 2),
+        _ => return ((0, 0), (0, 0), u8::MAX),
+    };
+    let (c1, c2) = (c1.into_raw_parts(), c2.into_raw_parts());
+    if c1 <= c2 {
+        (c1, c2, kind)
+    } else {
+        (c2, c1, kind)
     }
 }
 
+/// Shape of the collider attached to a body.
+#[derive(Debug, Clone, Copy)]
+struct BodyShape {
+    half_extents: Vector2f,
+    offset: Vector2f,
+    sensor: bool,
+    /// Transform scale the collider was built with.
+    scale: Vector2f,
+}
+
 pub struct CollisionWorld {
     config: PhysicConfiguration,
     colliders: ColliderSet,
@@ -106,12 +318,42 @@ pub struct CollisionWorld {
     broad_phase: BroadPhase,
     narrow_phase: NarrowPhase,
     joints: JointSet,
+    /// Acceleration structure for the scene queries. Updated after each step so the queries see
+    /// the colliders as they were at the end of the last step.
+    query_pipeline: QueryPipeline,
+
+    /// If true, step will not do anything.
+    paused: bool,
+
+    /// Time step of the simulation before applying the time scale.
+    base_dt: f32,
+
+    /// Entities that are currently intersecting (sensors). Both directions are stored.
+    overlaps: HashMap<hecs::Entity, HashSet<hecs::Entity>>,
+
+    /// Shape of the bodies, used by the character controller.
+    shapes: HashMap<RigidBodyHandle, BodyShape>,
+
+    /// Pairs of entities that do not generate collision events (e.g. a bullet and its shooter).
+    /// The entity with the lowest bits is first.
+    ignored_pairs: HashSet<(hecs::Entity, hecs::Entity)>,
+
+    /// Interaction groups of the disabled colliders, restored when they are enabled again.
+    disabled_colliders: HashMap<RigidBodyHandle, InteractionGroups>,
+
+    /// Body of the entities added with `add_body_with_entity`.
+    entity_bodies: HashMap<hecs::Entity, RigidBodyHandle>,
+
+    /// Bodies of the disabled entities. The value is true if the collider was enabled before
+    /// suspending the body.
+    suspended_bodies: HashMap<RigidBodyHandle, bool>,
 }
 
 impl Default for CollisionWorld {
     fn default() -> Self {
         let pipeline = PhysicsPipeline::new();
         let integration_parameters = IntegrationParameters::default();
+        let base_dt = integration_parameters.dt();
         let broad_phase = BroadPhase::new();
         let narrow_phase = NarrowPhase::new();
         let joints = JointSet::new();
@@ -122,16 +364,31 @@ impl Default for CollisionWorld {
             broad_phase,
             narrow_phase,
             pipeline,
+            query_pipeline: QueryPipeline::new(),
             integration_parameters,
             colliders: ColliderSet::new(),
             bodies: RigidBodySet::new(),
+            paused: false,
+            base_dt,
+            overlaps: HashMap::new(),
+            shapes: HashMap::new(),
+            ignored_pairs: HashSet::new(),
+            disabled_colliders: HashMap::new(),
+            entity_bodies: HashMap::new(),
+            suspended_bodies: HashMap::new(),
         }
     }
 }
 
 impl CollisionWorld {
     pub fn new(config: PhysicConfiguration) -> Self {
+        let mut integration_parameters = IntegrationParameters::default();
+        integration_parameters.set_dt(config.dt);
+        integration_parameters.max_velocity_iterations = config.velocity_iterations;
+        integration_parameters.max_position_iterations = config.position_iterations;
         Self {
+            base_dt: config.dt,
+            integration_parameters,
             config,
             ..Default::default()
         }
@@ -139,25 +396,45 @@ impl CollisionWorld {
 
     pub fn add_body(
         &mut self,
-        translation: &Vector2f,
+        transform: &Transform,
         c: &mut RigidBodyComponent,
     ) -> RigidBodyHandle {
         if let Some(h) = c.handle {
             h
         } else {
-            let body = RigidBodyBuilder::new(c.status)
+            let translation = transform.translation;
+            let (half_extents, offset) = c.collider_dimensions(&transform.scale);
+            let builder = RigidBodyBuilder::new(c.status)
                 .translation(translation.x, translation.y)
                 .mass(1.0, false)
-                .linear_damping(c.damping)
-                .lock_rotations()
-                .build();
+                .linear_damping(c.damping);
+            let builder = if c.lock_rotations {
+                builder.lock_rotations()
+            } else {
+                // Inertia of a box of mass 1.
+                let inertia = (half_extents.x.powi(2) + half_extents.y.powi(2)) / 3.0;
+                builder
+                    .rotation(transform.rotation)
+                    .principal_angular_inertia(inertia, false)
+            };
+            let mut body = builder.build();
+            body.activation.threshold = self.config.sleep_threshold;
 
             let handle = self.bodies.insert(body);
             self.colliders.insert(
-                c.collider.to_collider(c.interaction_group, c.sensor),
+                cuboid_collider(&half_extents, &offset, c.interaction_group, c.sensor),
                 handle,
                 &mut self.bodies,
             );
+            self.shapes.insert(
+                handle,
+                BodyShape {
+                    half_extents,
+                    offset,
+                    sensor: c.sensor,
+                    scale: transform.scale,
+                },
+            );
             c.handle = Some(handle);
             handle
         }
@@ -165,21 +442,539 @@ impl CollisionWorld {
 
     pub fn add_body_with_entity(
         &mut self,
-        translation: &Vector2f,
+        transform: &Transform,
         c: &mut RigidBodyComponent,
         e: hecs::Entity,
     ) -> RigidBodyHandle {
-        let h = self.add_body(translation, c);
+        let h = self.add_body(transform, c);
         if let Some(mut rb) = self.bodies.get_mut(h) {
             rb.user_data = e.to_bits() as u128;
         }
+        self.entity_bodies.insert(e, h);
         h
     }
 
+    /// Add the rigid body of the entity to the simulation, if the entity has a `Transform` and a
+    /// `RigidBodyComponent` that is not registered yet. If the entity has an `Owner`, the
+    /// collision events with the owner are ignored.
+    pub fn add_entity(&mut self, world: &hecs::World, e: hecs::Entity) {
+        if let Ok(t) = world.get::<Transform>(e) {
+            if let Ok(mut rbc) = world.get_mut::<RigidBodyComponent>(e) {
+                self.add_body_with_entity(&t, &mut rbc, e);
+                if let Ok(owner) = world.get::<Owner>(e) {
+                    self.ignore_pair(e, owner.0);
+                }
+            }
+        }
+    }
+
+    /// Rebuild the colliders of the bodies with `scale_collider` whose transform scale changed
+    /// since the collider was built. Call it after changing the scale of such entities. Each
+    /// rebuild removes and inserts a collider, which resets its contacts and is much more
+    /// expensive than moving a body, so avoid animating the scale of many bodies.
+    pub fn update_collider_scales(&mut self, world: &hecs::World) {
+        for (_, (t, rbc)) in world.query::<(&Transform, &RigidBodyComponent)>().iter() {
+            if !rbc.scale_collider {
+                continue;
+            }
+            let h = match rbc.handle {
+                Some(h) => h,
+                None => continue,
+            };
+            match self.shapes.get(&h) {
+                Some(shape) if shape.scale != t.scale => (),
+                _ => continue,
+            }
+
+            let (half_extents, offset) = rbc.collider_dimensions(&t.scale);
+            let shape = BodyShape {
+                half_extents,
+                offset,
+                sensor: rbc.sensor,
+                scale: t.scale,
+            };
+            // A disabled collider stays disabled.
+            let groups = if self.disabled_colliders.contains_key(&h) {
+                InteractionGroups::none()
+            } else {
+                rbc.interaction_group
+            };
+            self.replace_collider(h, shape, groups);
+        }
+    }
+
+    /// Remove the colliders of the body and attach a new cuboid collider.
+    fn replace_collider(
+        &mut self,
+        h: RigidBodyHandle,
+        shape: BodyShape,
+        groups: InteractionGroups,
+    ) {
+        let colliders = match self.bodies.get(h) {
+            Some(body) => body.colliders().to_vec(),
+            None => return,
+        };
+        for collider in colliders {
+            self.colliders.remove(collider, &mut self.bodies, true);
+        }
+
+        self.colliders.insert(
+            cuboid_collider(&shape.half_extents, &shape.offset, groups, shape.sensor),
+            h,
+            &mut self.bodies,
+        );
+        self.shapes.insert(h, shape);
+    }
+
+    /// Interaction groups of the collider of the body, even if it is disabled.
+    fn body_groups(&self, h: RigidBodyHandle) -> InteractionGroups {
+        if let Some(groups) = self.disabled_colliders.get(&h) {
+            return *groups;
+        }
+        self.bodies
+            .get(h)
+            .and_then(|body| body.colliders().first())
+            .and_then(|c| self.colliders.get(*c))
+            .map(|collider| collider.collision_groups())
+            .unwrap_or_else(InteractionGroups::none)
+    }
+
+    /// Body of an entity added with `add_body_with_entity`.
+    fn body_handle(&self, e: hecs::Entity) -> Option<RigidBodyHandle> {
+        self.entity_bodies.get(&e).copied()
+    }
+
+    /// Turn the collider of the entity on or off (hitboxes, invulnerability...). A disabled
+    /// collider does not collide nor generate collision events but the body is kept in the
+    /// simulation. The collider is rebuilt so avoid toggling it every frame. No exit event is sent
+    /// for the current overlaps when disabling.
+    pub fn set_collider_enabled(&mut self, e: hecs::Entity, enabled: bool) {
+        let h = match self.body_handle(e) {
+            Some(h) => h,
+            None => return,
+        };
+        let shape = match self.shapes.get(&h) {
+            Some(shape) => *shape,
+            None => return,
+        };
+
+        if enabled {
+            if let Some(groups) = self.disabled_colliders.remove(&h) {
+                self.replace_collider(h, shape, groups);
+                self.wake_body(h);
+            }
+        } else if !self.disabled_colliders.contains_key(&h) {
+            let groups = match self
+                .bodies
+                .get(h)
+                .and_then(|body| body.colliders().first())
+                .and_then(|c| self.colliders.get(*c))
+            {
+                Some(collider) => collider.collision_groups(),
+                None => return,
+            };
+            self.disabled_colliders.insert(h, groups);
+            self.replace_collider(h, shape, InteractionGroups::none());
+            self.forget_overlaps(e);
+            self.wake_body(h);
+        }
+    }
+
+    pub fn is_collider_enabled(&self, e: hecs::Entity) -> bool {
+        self.body_handle(e)
+            .map(|h| !self.disabled_colliders.contains_key(&h))
+            .unwrap_or(false)
+    }
+
+    /// Keep the body of the entity in the simulation but stop it: its collider is disabled and
+    /// the body is put to sleep. Used when disabling an entity so that its handle and joints
+    /// stay valid. A joint with an active body can still wake it up.
+    pub fn suspend_body(&mut self, e: hecs::Entity) {
+        let h = match self.body_handle(e) {
+            Some(h) => h,
+            None => return,
+        };
+        if self.suspended_bodies.contains_key(&h) {
+            return;
+        }
+
+        let collider_enabled = !self.disabled_colliders.contains_key(&h);
+        self.set_collider_enabled(e, false);
+        if let Some(rb) = self.bodies.get_mut(h) {
+            rb.set_linvel(Vector2f::zeros(), false);
+            rb.set_angvel(0.0, false);
+            rb.sleep();
+        }
+        self.suspended_bodies.insert(h, collider_enabled);
+    }
+
+    /// Resume a body stopped with `suspend_body`, at the given position. The collider is enabled
+    /// again unless it was already disabled before suspending the body.
+    pub fn resume_body(&mut self, e: hecs::Entity, transform: &Transform) {
+        let h = match self.body_handle(e) {
+            Some(h) => h,
+            None => return,
+        };
+        let collider_enabled = match self.suspended_bodies.remove(&h) {
+            Some(enabled) => enabled,
+            None => return,
+        };
+
+        if let Some(rb) = self.bodies.get_mut(h) {
+            let position = Isometry2::new(transform.translation, transform.rotation);
+            rb.set_position(position, true);
+        }
+        if collider_enabled {
+            self.set_collider_enabled(e, true);
+        }
+        self.wake_body(h);
+    }
+
+    pub fn is_suspended(&self, h: RigidBodyHandle) -> bool {
+        self.suspended_bodies.contains_key(&h)
+    }
+
+    /// Attach two bodies rigidly. Anchors are in the local space of each body. The joint is
+    /// removed when one of the bodies is removed.
+    pub fn add_fixed_joint(
+        &mut self,
+        a: RigidBodyHandle,
+        b: RigidBodyHandle,
+        anchors: (Vector2f, Vector2f),
+    ) -> JointHandle {
+        let joint = FixedJoint::new(
+            Isometry2::translation(anchors.0.x, anchors.0.y),
+            Isometry2::translation(anchors.1.x, anchors.1.y),
+        );
+        self.joints.insert(&mut self.bodies, a, b, joint)
+    }
+
+    /// Attach two bodies with a hinge so that they can rotate around the anchor. Anchors are in
+    /// the local space of each body. The joint is removed when one of the bodies is removed.
+    /// The bodies must be created with `RigidBodyComponent::with_rotations`, otherwise they
+    /// cannot turn around the hinge.
+    pub fn add_revolute_joint(
+        &mut self,
+        a: RigidBodyHandle,
+        b: RigidBodyHandle,
+        anchors: (Vector2f, Vector2f),
+    ) -> JointHandle {
+        // In 2D, a ball joint only allows rotation around the anchor.
+        let joint = BallJoint::new(
+            Point2::new(anchors.0.x, anchors.0.y),
+            Point2::new(anchors.1.x, anchors.1.y),
+        );
+        self.joints.insert(&mut self.bodies, a, b, joint)
+    }
+
+    pub fn remove_joint(&mut self, h: JointHandle) {
+        self.joints.remove(h, &mut self.bodies, true);
+    }
+
+    /// Remove the body, its colliders and its joints from the simulation.
+    pub fn remove_body(&mut self, h: RigidBodyHandle) {
+        // No exit event will be generated so forget about the overlaps here.
+        if let Some(e) = self.body_entity(h) {
+            self.forget_overlaps(e);
+            self.ignored_pairs.retain(|(a, b)| *a != e && *b != e);
+            self.entity_bodies.remove(&e);
+        }
+        self.shapes.remove(&h);
+        self.disabled_colliders.remove(&h);
+        self.suspended_bodies.remove(&h);
+        self.bodies.remove(h, &mut self.colliders, &mut self.joints);
+    }
+
+    /// Get the entity attached to the body of the collider. None if the body was not added with
+    /// `add_body_with_entity`.
+    pub fn collider_entity(&self, h: ColliderHandle) -> Option<hecs::Entity> {
+        self.colliders
+            .get(h)
+            .and_then(|collider| self.body_entity(collider.parent()))
+    }
+
+    /// Entity of a body added with `add_body_with_entity`. The user data of the bodies added
+    /// with `add_body` is not an entity so None is returned for them.
+    fn body_entity(&self, h: RigidBodyHandle) -> Option<hecs::Entity> {
+        let rb = self.bodies.get(h)?;
+        let e = hecs::Entity::from_bits(rb.user_data as u64);
+        if self.entity_bodies.get(&e) == Some(&h) {
+            Some(e)
+        } else {
+            None
+        }
+    }
+
+    /// Find the entities whose collider is within `radius` of `center`. Only the colliders that
+    /// can interact with `groups` are considered. Returns the entities and their distance to the
+    /// center, closest first. Uses the colliders as they were after the last physics step.
+    pub fn query_radius(
+        &self,
+        center: Vector2f,
+        radius: f32,
+        groups: InteractionGroups,
+    ) -> Vec<(hecs::Entity, f32)> {
+        let mut found: HashMap<hecs::Entity, f32> = HashMap::new();
+        self.query_pipeline.intersections_with_shape(
+            &self.colliders,
+            &Isometry2::translation(center.x, center.y),
+            &Ball::new(radius),
+            groups,
+            |_, collider| {
+                // Colliders are cuboids without rotation so the AABB is the exact shape.
+                let aabb = collider.compute_aabb();
+                let closest = Vector2f::new(
+                    center.x.max(aabb.mins.x).min(aabb.maxs.x),
+                    center.y.max(aabb.mins.y).min(aabb.maxs.y),
+                );
+                let distance = (closest - center).norm();
+
+                if let Some(e) = self.body_entity(collider.parent()) {
+                    let d = found.entry(e).or_insert(distance);
+                    *d = d.min(distance);
+                }
+                true
+            },
+        );
+
+        let mut found: Vec<_> = found.into_iter().collect();
+        found.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        found
+    }
+
+    /// Get the contact point and normal between two colliders that are touching.
+    pub fn contact_details(
+        &self,
+        h1: ColliderHandle,
+        h2: ColliderHandle,
+    ) -> Option<ContactDetails> {
+        let (_, _, pair) = self
+            .narrow_phase
+            .contacts_with(h1)?
+            .find(|(c1, c2, _)| (*c1 == h1 && *c2 == h2) || (*c1 == h2 && *c2 == h1))?;
+        let manifold = pair.manifolds.iter().find(|m| !m.points.is_empty())?;
+        let contact = &manifold.points[0];
+
+        // Contact data is local to the first collider of the pair.
+        let first = self.colliders.get(pair.pair.collider1)?;
+        let point = first.position() * contact.local_p1;
+        let mut normal = first.position() * manifold.local_n1;
+        if pair.pair.collider1 != h1 {
+            normal = -normal;
+        }
+
+        Some(ContactDetails {
+            point: point.coords,
+            normal,
+        })
+    }
+
+    /// Entities that are intersecting the given entity (e.g. everything inside a trigger).
+    pub fn currently_overlapping(&self, e: hecs::Entity) -> Vec<hecs::Entity> {
+        self.overlaps
+            .get(&e)
+            .map(|others| others.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    fn update_overlaps<GE>(&mut self, event: &GameEvent<GE>)
+    where
+        GE: CustomGameEvent,
+    {
+        match event {
+            GameEvent::ProximityEvent(c1, c2) => {
+                if let (Some(e1), Some(e2)) = (self.collider_entity(*c1), self.collider_entity(*c2))
+                {
+                    self.overlaps.entry(e1).or_default().insert(e2);
+                    self.overlaps.entry(e2).or_default().insert(e1);
+                }
+            }
+            GameEvent::ProximityExited(c1, c2) => {
+                if let (Some(e1), Some(e2)) = (self.collider_entity(*c1), self.collider_entity(*c2))
+                {
+                    self.remove_overlap(e1, e2);
+                    self.remove_overlap(e2, e1);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn remove_overlap(&mut self, e: hecs::Entity, other: hecs::Entity) {
+        if let Some(others) = self.overlaps.get_mut(&e) {
+            others.remove(&other);
+            if others.is_empty() {
+                self.overlaps.remove(&e);
+            }
+        }
+    }
+
+    /// Do not send collision events between these two entities. The bodies still collide
+    /// physically unless one of them is a sensor; use the interaction groups for that. The pair
+    /// is forgotten when one of the bodies is removed.
+    pub fn ignore_pair(&mut self, a: hecs::Entity, b: hecs::Entity) {
+        self.ignored_pairs.insert(ordered_pair(a, b));
+    }
+
+    pub fn unignore_pair(&mut self, a: hecs::Entity, b: hecs::Entity) {
+        self.ignored_pairs.remove(&ordered_pair(a, b));
+    }
+
+    pub fn is_pair_ignored(&self, a: hecs::Entity, b: hecs::Entity) -> bool {
+        self.ignored_pairs.contains(&ordered_pair(a, b))
+    }
+
+    /// True if the event is between two entities of an ignored pair.
+    fn is_event_ignored<GE>(&self, event: &GameEvent<GE>) -> bool
+    where
+        GE: CustomGameEvent,
+    {
+        if self.ignored_pairs.is_empty() {
+            return false;
+        }
+        let (c1, c2) = match event {
+            GameEvent::ProximityEvent(c1, c2)
+            | GameEvent::ProximityExited(c1, c2)
+            | GameEvent::ContactEvent(c1, c2, _) => (*c1, *c2),
+            _ => return false,
+        };
+        match (self.collider_entity(c1), self.collider_entity(c2)) {
+            (Some(e1), Some(e2)) => self.is_pair_ignored(e1, e2),
+            _ => false,
+        }
+    }
+
+    fn forget_overlaps(&mut self, e: hecs::Entity) {
+        if let Some(others) = self.overlaps.remove(&e) {
+            for other in others {
+                self.remove_overlap(other, e);
+            }
+        }
+    }
+
+    /// Freeze the simulation. The bodies keep their state so the simulation resumes exactly
+    /// where it stopped. No events are generated while paused.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Time step of the simulation, before applying the time scale.
+    pub fn dt(&self) -> f32 {
+        self.base_dt
+    }
+
+    /// Parameters used by the last step.
+    pub fn integration_parameters(&self) -> &IntegrationParameters {
+        &self.integration_parameters
+    }
+
+    /// Write the `Velocity` components to the rigid bodies. Should be called before `step`.
+    pub fn apply_velocities(&mut self, world: &hecs::World) {
+        for (_, (rbc, velocity)) in world
+            .query::<(&RigidBodyComponent, &Velocity)>()
+            .without::<Disabled>()
+            .iter()
+        {
+            if rbc.status == BodyStatus::Static {
+                continue;
+            }
+
+            if let Some(h) = rbc.handle {
+                if let Some(rb) = self.bodies.get_mut(h) {
+                    // Do not wake up sleeping bodies if nothing changed.
+                    if *rb.linvel() != velocity.0 {
+                        rb.set_linvel(velocity.0, true);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Duration of the next step. None if the simulation is paused.
+    fn step_dt(&self, resources: &Resources) -> Option<f32> {
+        if self.paused || is_paused(resources) {
+            return None;
+        }
+
+        let time_scale = resources.fetch::<TimeScale>().map(|s| s.0).unwrap_or(1.0);
+        if !time_scale.is_finite() || time_scale <= 0.0 {
+            // Frozen. Same as paused.
+            return None;
+        }
+        Some(self.base_dt * time_scale)
+    }
+
+    /// Apply the gravity of the `GravityZone`s to the bodies inside them. Rapier only has a
+    /// global gravity so the difference with the global gravity is added to the velocity.
+    /// Should be called before `step`.
+    pub fn apply_gravity_zones(&mut self, world: &hecs::World, resources: &Resources) {
+        let dt = match self.step_dt(resources) {
+            Some(dt) => dt,
+            None => return,
+        };
+
+        // Zone applied to each entity. Ties are broken by entity so that the result does not
+        // depend on the iteration order.
+        let mut zones: HashMap<hecs::Entity, (i32, u64, Vector2f)> = HashMap::new();
+        for (zone_entity, zone) in world.query::<&GravityZone>().without::<Disabled>().iter() {
+            let others = match self.overlaps.get(&zone_entity) {
+                Some(others) => others,
+                None => continue,
+            };
+            let key = (zone.priority, zone_entity.to_bits(), zone.gravity);
+            for other in others {
+                let entry = zones.entry(*other).or_insert(key);
+                if (key.0, key.1) > (entry.0, entry.1) {
+                    *entry = key;
+                }
+            }
+        }
+
+        let global_gravity = Vector2f::new(0.0, self.config.gravity);
+        for (e, (_, _, gravity)) in zones {
+            let handle = match world.get::<RigidBodyComponent>(e) {
+                Ok(rbc) if rbc.status == BodyStatus::Dynamic => rbc.handle,
+                _ => continue,
+            };
+            if let Some(rb) = handle.and_then(|h| self.bodies.get_mut(h)) {
+                let linvel = *rb.linvel() + (gravity - global_gravity) * dt;
+                rb.set_linvel(linvel, true);
+            }
+        }
+    }
+
+    /// Apply the `GlobalForce` resource to the dynamic bodies for the next step. Should be called
+    /// before `step`.
+    pub fn apply_global_force(&mut self, resources: &Resources) {
+        let force = match resources.fetch::<GlobalForce>() {
+            Some(force) if force.0 != Vector2f::zeros() => force.0,
+            _ => return,
+        };
+        if self.step_dt(resources).is_none() {
+            return;
+        }
+
+        for (_, rb) in self.bodies.iter_mut() {
+            if rb.is_dynamic() {
+                rb.apply_force(force, true);
+            }
+        }
+    }
+
     pub fn step<GE>(&mut self, resources: &Resources)
     where
         GE: CustomGameEvent,
     {
+        let dt = match self.step_dt(resources) {
+            Some(dt) => dt,
+            None => return,
+        };
+        self.integration_parameters.set_dt(dt);
+
         let gravity = rapier2d::na::Vector2::new(0.0, self.config.gravity);
         let pipeline = &mut self.pipeline;
         let mut channel = resources.fetch_mut::<EventQueue<GE>>().unwrap();
@@ -200,32 +995,56 @@ impl CollisionWorld {
                 &event_handler,
             );
         }
+        self.query_pipeline.update(&self.bodies, &self.colliders);
 
-        {
-            let locked = events.lock();
-            if let Ok(events) = locked {
-                for ev in events.iter() {
-                    channel.single_write(ev.clone());
-                }
+        let mut events = match events.lock() {
+            Ok(mut events) => events.drain(..).collect::<Vec<_>>(),
+            Err(_) => vec![],
+        };
+        // The callbacks are not called in a guaranteed order. Sort the events so that replays
+        // get the same events in the same order.
+        events.sort_by_key(event_sort_key);
+        for ev in events {
+            if self.is_event_ignored(&ev) {
+                continue;
             }
+            let ev = match ev {
+                GameEvent::ContactEvent(h1, h2, _) => GameEvent::ContactEvent(
+                    h1,
+                    h2,
+                    self.contact_details(h1, h2).unwrap_or_default(),
+                ),
+                ev => ev,
+            };
+            self.update_overlaps(&ev);
+            channel.single_write(ev);
         }
     }
 
     pub fn synchronize(&self, world: &hecs::World) {
-        for (_, (transform, rbc)) in world
-            .query::<(&mut Transform, &RigidBodyComponent)>()
+        for (_, (transform, rbc, velocity)) in world
+            .query::<(&mut Transform, &RigidBodyComponent, Option<&mut Velocity>)>()
+            .without::<Disabled>()
             .iter()
         {
-            if !rbc.should_sync {
-                continue;
+            let rigid_body = match rbc.handle.and_then(|h| self.bodies.get(h)) {
+                Some(rigid_body) => rigid_body,
+                None => continue,
+            };
+
+            if let Some(velocity) = velocity {
+                if rbc.status != BodyStatus::Static {
+                    velocity.0 = *rigid_body.linvel();
+                }
             }
 
-            if let Some(h) = rbc.handle {
-                if let Some(rigid_body) = self.bodies.get(h) {
-                    // Update transform with new coordinates.
-                    let pos: [f32; 2] = rigid_body.position().translation.vector.into();
-                    transform.translation.x = pos[0];
-                    transform.translation.y = pos[1];
+            if rbc.should_sync {
+                // Update transform with new coordinates.
+                let pos: [f32; 2] = rigid_body.position().translation.vector.into();
+                transform.translation.x = pos[0];
+                transform.translation.y = pos[1];
+                if !rbc.lock_rotations {
+                    transform.rotation = rigid_body.position().rotation.angle();
                 }
             }
         }
@@ -243,18 +1062,42 @@ impl CollisionWorld {
         &mut self.bodies
     }
 
+    /// Force the body to be simulated again, e.g. after moving it from gameplay code.
+    pub fn wake_body(&mut self, h: RigidBodyHandle) {
+        if let Some(rb) = self.bodies.get_mut(h) {
+            rb.wake_up(true);
+        }
+    }
+
+    /// Stop simulating the body until something touches it or it is woken up.
+    pub fn sleep_body(&mut self, h: RigidBodyHandle) {
+        if let Some(rb) = self.bodies.get_mut(h) {
+            rb.sleep();
+        }
+    }
+
+    pub fn is_sleeping(&self, h: RigidBodyHandle) -> bool {
+        self.bodies
+            .get(h)
+            .map(|rb| rb.is_sleeping())
+            .unwrap_or(false)
+    }
+
+    /// Apply an impulse to the body. Wakes up the body.
     pub fn apply_impulse(&mut self, h: RigidBodyHandle, impulse: Vector2f) {
         if let Some(rb) = self.bodies.get_mut(h) {
             rb.apply_impulse(impulse, true);
         }
     }
 
+    /// Set the linear velocity of the body. Wakes up the body.
     pub fn set_velocity(&mut self, h: RigidBodyHandle, velocity: Vector2f) {
         if let Some(rb) = self.bodies.get_mut(h) {
             rb.set_linvel(velocity, true);
         }
     }
 
+    /// Teleport the body. Wakes up the body.
     pub fn set_position(&mut self, h: RigidBodyHandle, position: &Vector2f) {
         if let Some(rb) = self.bodies.get_mut(h) {
             rb.set_position(Isometry2::translation(position.x, position.y), true);
@@ -271,16 +1114,375 @@ where
     GE: CustomGameEvent,
 {
     fn handle_proximity_event(&self, event: ProximityEvent) {
-        if let Proximity::Intersecting = event.new_status {
+        let game_event = match (event.prev_status, event.new_status) {
+            (Proximity::Intersecting, Proximity::Intersecting) => None,
+            (_, Proximity::Intersecting) => {
+                Some(GameEvent::ProximityEvent(event.collider1, event.collider2))
+            }
+            (Proximity::Intersecting, _) => {
+                Some(GameEvent::ProximityExited(event.collider1, event.collider2))
+            }
+            _ => None,
+        };
+
+        if let Some(game_event) = game_event {
             if let Ok(mut events) = self.0.lock() {
-                events.push(GameEvent::ProximityEvent(event.collider1, event.collider2));
+                events.push(game_event);
             }
         }
     }
 
-    fn handle_contact_event(&self, _event: ContactEvent) {
-        println!("CONTACT!!");
-        // self.0
-        //     .push(GameEvent::ContactEvent(event.collider1, event.collider2));
+    fn handle_contact_event(&self, event: ContactEvent) {
+        if let ContactEvent::Started(h1, h2) = event {
+            // The details are filled after the step, from the narrow phase.
+            if let Ok(mut events) = self.0.lock() {
+                events.push(GameEvent::ContactEvent(h1, h2, ContactDetails::default()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventReader;
+
+    #[derive(Debug, Clone)]
+    struct TestEvent;
+    impl CustomGameEvent for TestEvent {}
+
+    fn body_position(physics: &CollisionWorld, h: RigidBodyHandle) -> Vector2f {
+        physics
+            .rigid_bodies()
+            .get(h)
+            .unwrap()
+            .position()
+            .translation
+            .vector
+    }
+
+    #[test]
+    fn paused_step_keeps_the_positions() {
+        let mut resources = Resources::default();
+        resources.insert(EventQueue::<TestEvent>::new());
+        let mut reader = EventReader::<TestEvent>::new(&mut resources);
+
+        let mut physics = CollisionWorld::default();
+        let mut body = RigidBodyComponent::new_dynamic_cuboid(1.0, 1.0);
+        body.interaction_group = InteractionGroups::all();
+        let h = physics.add_body(&Transform::default(), &mut body);
+        let mut sensor = RigidBodyComponent::new_sensor_cuboid(1.0, 1.0);
+        physics.add_body(&Transform::default(), &mut sensor);
+        physics.set_velocity(h, Vector2f::new(10.0, 0.0));
+
+        physics.set_paused(true);
+        for _ in 0..10 {
+            physics.step::<TestEvent>(&resources);
+        }
+        assert_eq!(body_position(&physics, h), Vector2f::zeros());
+        assert_eq!(reader.read(&resources).count(), 0);
+
+        physics.set_paused(false);
+        physics.step::<TestEvent>(&resources);
+        assert!(body_position(&physics, h).x > 0.0);
+        assert_eq!(reader.read(&resources).count(), 1);
+    }
+
+    /// A sensor and a dynamic body overlapping at the origin. `setup` runs before the first
+    /// step. Returns the number of proximity events sent by the step.
+    fn proximity_events<F>(setup: F) -> usize
+    where
+        F: FnOnce(&mut CollisionWorld, hecs::Entity, hecs::Entity),
+    {
+        let mut resources = Resources::default();
+        resources.insert(EventQueue::<TestEvent>::new());
+        let mut reader = EventReader::<TestEvent>::new(&mut resources);
+
+        let mut world = hecs::World::new();
+        let mut body = RigidBodyComponent::new_dynamic_cuboid(1.0, 1.0);
+        body.interaction_group = InteractionGroups::all();
+        let sensor = world.spawn((
+            Transform::default(),
+            RigidBodyComponent::new_sensor_cuboid(1.0, 1.0),
+        ));
+        let body = world.spawn((Transform::default(), body));
+
+        let mut physics = CollisionWorld::default();
+        physics.add_entity(&world, sensor);
+        physics.add_entity(&world, body);
+        setup(&mut physics, sensor, body);
+        physics.step::<TestEvent>(&resources);
+
+        reader
+            .read(&resources)
+            .filter(|ev| matches!(ev, GameEvent::ProximityEvent(..)))
+            .count()
+    }
+
+    #[test]
+    fn suspended_body_sends_no_event() {
+        let count = proximity_events(|physics, _, body| {
+            physics.suspend_body(body);
+        });
+        assert_eq!(count, 0);
+
+        let count = proximity_events(|physics, _, body| {
+            physics.suspend_body(body);
+            physics.resume_body(body, &Transform::default());
+        });
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn velocity_moves_the_dynamic_bodies() {
+        let mut resources = Resources::default();
+        resources.insert(EventQueue::<TestEvent>::new());
+        let mut world = hecs::World::new();
+        let dynamic = world.spawn((
+            Transform::default(),
+            RigidBodyComponent::new_dynamic_cuboid(1.0, 1.0),
+            Velocity(Vector2f::new(5.0, 0.0)),
+        ));
+        let wall = world.spawn((
+            Transform::from_translation(Vector2f::new(0.0, 100.0)),
+            RigidBodyComponent::new_static_cuboid(1.0, 1.0),
+            Velocity(Vector2f::new(5.0, 0.0)),
+        ));
+
+        let mut physics = CollisionWorld::default();
+        physics.add_entity(&world, dynamic);
+        physics.add_entity(&world, wall);
+        physics.apply_velocities(&world);
+        physics.step::<TestEvent>(&resources);
+        physics.synchronize(&world);
+
+        assert!(world.get::<Transform>(dynamic).unwrap().translation.x > 0.0);
+        // The gravity is read back in the component.
+        let velocity = world.get::<Velocity>(dynamic).unwrap().0;
+        assert!((velocity.x - 5.0).abs() < 1e-4);
+        assert!(velocity.y < 0.0);
+
+        assert_eq!(
+            world.get::<Transform>(wall).unwrap().translation,
+            Vector2f::new(0.0, 100.0)
+        );
+        assert_eq!(
+            world.get::<Velocity>(wall).unwrap().0,
+            Vector2f::new(5.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn body_without_entity_has_no_entity() {
+        let mut world = hecs::World::new();
+        // Its bits are the default user data of a body.
+        let first = world.spawn((Transform::default(),));
+        assert_eq!(first.to_bits(), 0);
+
+        let mut physics = CollisionWorld::default();
+        let h = physics.add_body(
+            &Transform::default(),
+            &mut RigidBodyComponent::new_static_cuboid(1.0, 1.0),
+        );
+        let collider = physics.rigid_bodies().get(h).unwrap().colliders()[0];
+        assert_eq!(physics.collider_entity(collider), None);
+    }
+
+    #[test]
+    fn body_goes_through_a_sensor() {
+        let mut resources = Resources::default();
+        resources.insert(EventQueue::<TestEvent>::new());
+        let mut world = hecs::World::new();
+        let sensor = world.spawn((
+            Transform::default(),
+            RigidBodyComponent::new_sensor_cuboid(1.0, 1.0),
+        ));
+        let mut body = RigidBodyComponent::new_dynamic_cuboid(1.0, 1.0);
+        body.interaction_group = InteractionGroups::all();
+        let body = world.spawn((Transform::default(), body));
+
+        let mut physics = CollisionWorld::default();
+        physics.add_entity(&world, sensor);
+        physics.add_entity(&world, body);
+        physics.step::<TestEvent>(&resources);
+        assert_eq!(physics.currently_overlapping(sensor), vec![body]);
+        assert_eq!(physics.currently_overlapping(body), vec![sensor]);
+
+        let h = physics.body_handle(body).unwrap();
+        physics.set_position(h, &Vector2f::new(100.0, 0.0));
+        physics.step::<TestEvent>(&resources);
+        assert!(physics.currently_overlapping(sensor).is_empty());
+        assert!(physics.currently_overlapping(body).is_empty());
+    }
+
+    #[test]
+    fn revolute_joint_rotates_about_the_anchor() {
+        let mut resources = Resources::default();
+        resources.insert(EventQueue::<TestEvent>::new());
+        let mut physics = CollisionWorld::default();
+        let mut anchor = RigidBodyComponent::new_static_cuboid(0.1, 0.1);
+        let a = physics.add_body(&Transform::default(), &mut anchor);
+        let mut arm = RigidBodyComponent::new_dynamic_cuboid(0.5, 0.1);
+        arm.lock_rotations = false;
+        let b = physics.add_body(
+            &Transform::from_translation(Vector2f::new(2.0, 0.0)),
+            &mut arm,
+        );
+        physics.add_revolute_joint(a, b, (Vector2f::zeros(), Vector2f::new(-2.0, 0.0)));
+
+        for _ in 0..60 {
+            physics.step::<TestEvent>(&resources);
+        }
+
+        // The arm swings down under gravity but stays at the same distance of the anchor.
+        let position = body_position(&physics, b);
+        assert!((position.norm() - 2.0).abs() < 0.05);
+        assert!(position.y < -0.5);
+        let rotation = physics
+            .rigid_bodies()
+            .get(b)
+            .unwrap()
+            .position()
+            .rotation
+            .angle();
+        assert!((rotation - position.y.atan2(position.x)).abs() < 0.05);
+    }
+
+    #[test]
+    fn query_radius_sorted_by_distance() {
+        let mut resources = Resources::default();
+        resources.insert(EventQueue::<TestEvent>::new());
+        let mut world = hecs::World::new();
+        let mut physics = CollisionWorld::default();
+        let mut spawn_at = |x: f32| {
+            let e = world.spawn(());
+            let mut body = RigidBodyComponent::new_static_cuboid(0.5, 0.5);
+            body.interaction_group = InteractionGroups::all();
+            let transform = Transform::from_translation(Vector2f::new(x, 0.0));
+            physics.add_body_with_entity(&transform, &mut body, e);
+            e
+        };
+        let far = spawn_at(4.0);
+        let close = spawn_at(1.0);
+        spawn_at(10.0);
+        // Update the query pipeline.
+        physics.step::<TestEvent>(&resources);
+
+        let found = physics.query_radius(Vector2f::zeros(), 5.0, InteractionGroups::all());
+        assert_eq!(found.len(), 2);
+        assert_eq!((found[0].0, found[1].0), (close, far));
+        assert!((found[0].1 - 0.5).abs() < 1.0e-5);
+        assert!((found[1].1 - 3.5).abs() < 1.0e-5);
+    }
+
+    #[test]
+    fn scaled_collider_dimensions() {
+        let body = RigidBodyComponent::new_static_cuboid(1.0, 2.0)
+            .with_collider_offset(Vector2f::new(0.0, -1.0));
+        let scale = Vector2f::new(-2.0, 3.0);
+        assert_eq!(
+            body.collider_dimensions(&scale),
+            (Vector2f::new(1.0, 2.0), Vector2f::new(0.0, -1.0))
+        );
+
+        let body = body.with_scaled_collider();
+        assert_eq!(
+            body.collider_dimensions(&scale),
+            (Vector2f::new(2.0, 6.0), Vector2f::new(0.0, -3.0))
+        );
+    }
+
+    #[test]
+    fn overlapping_bodies_send_a_proximity_event() {
+        assert_eq!(proximity_events(|_, _, _| ()), 1);
+    }
+
+    #[test]
+    fn disabled_collider_sends_no_event() {
+        let count = proximity_events(|physics, sensor, _| {
+            physics.set_collider_enabled(sensor, false);
+            assert!(!physics.is_collider_enabled(sensor));
+        });
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn enabled_again_collider_sends_events() {
+        let count = proximity_events(|physics, sensor, _| {
+            physics.set_collider_enabled(sensor, false);
+            physics.set_collider_enabled(sensor, true);
+        });
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn ignored_pair_sends_no_event() {
+        let count = proximity_events(|physics, sensor, body| {
+            physics.ignore_pair(body, sensor);
+            assert!(physics.is_pair_ignored(sensor, body));
+        });
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn ordered_pair_is_symmetric() {
+        let mut world = hecs::World::new();
+        let a = world.spawn(());
+        let b = world.spawn(());
+        assert_eq!(ordered_pair(a, b), ordered_pair(b, a));
+    }
+
+    #[test]
+    fn enter_then_exit_a_sensor() {
+        let mut resources = Resources::default();
+        resources.insert(EventQueue::<TestEvent>::new());
+        let mut reader = EventReader::<TestEvent>::new(&mut resources);
+
+        let mut physics = CollisionWorld::default();
+        let mut body = RigidBodyComponent::new_dynamic_cuboid(1.0, 1.0);
+        body.interaction_group = InteractionGroups::all();
+        let h = physics.add_body(
+            &Transform::from_translation(Vector2f::new(100.0, 0.0)),
+            &mut body,
+        );
+        let mut sensor = RigidBodyComponent::new_sensor_cuboid(1.0, 1.0);
+        physics.add_body(&Transform::default(), &mut sensor);
+        physics.step::<TestEvent>(&resources);
+        assert_eq!(reader.read(&resources).count(), 0);
+
+        physics.set_position(h, &Vector2f::zeros());
+        physics.step::<TestEvent>(&resources);
+        let events: Vec<_> = reader.read(&resources).collect();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], GameEvent::ProximityEvent(..)));
+
+        physics.set_position(h, &Vector2f::new(100.0, 0.0));
+        physics.step::<TestEvent>(&resources);
+        let events: Vec<_> = reader.read(&resources).collect();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], GameEvent::ProximityExited(..)));
+    }
+
+    #[test]
+    fn events_are_sorted_by_collider_pair() {
+        let c0 = ColliderHandle::from_raw_parts(0, 0);
+        let c1 = ColliderHandle::from_raw_parts(1, 0);
+        let c2 = ColliderHandle::from_raw_parts(2, 0);
+        let mut events: Vec<GameEvent<TestEvent>> = vec![
+            GameEvent::ProximityExited(c2, c0),
+            GameEvent::ProximityEvent(c1, c0),
+            GameEvent::ProximityEvent(c0, c2),
+        ];
+        events.sort_by_key(event_sort_key);
+
+        let keys: Vec<_> = events.iter().map(event_sort_key).collect();
+        assert_eq!(
+            keys,
+            vec![
+                ((0, 0), (1, 0), 0),
+                ((0, 0), (2, 0), 0),
+                ((0, 0), (2, 0), 1)
+            ]
+        );
     }
 }
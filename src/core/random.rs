@@ -4,6 +4,23 @@ use rand::SeedableRng;
 #[derive(Debug, Copy, Clone)]
 pub struct Seed(pub(crate) [u8; 32]);
 
+impl Seed {
+    pub fn new(seed: [u8; 32]) -> Self {
+        Self(seed)
+    }
+
+    /// Expand a number to a full seed. Same number will give the same seed.
+    pub fn from_u64(seed: u64) -> Self {
+        let mut bytes = [0u8; 32];
+        for chunk in bytes.chunks_mut(8) {
+            chunk.copy_from_slice(&seed.to_le_bytes());
+        }
+        Self(bytes)
+    }
+}
+
+/// Seedable random number generator. Systems that need randomness should use this resource
+/// so that a run can be reproduced with the same seed.
 pub struct RandomGenerator {
     rand: StdRng,
 }
@@ -25,3 +42,22 @@ impl RandomGenerator {
         &mut self.rand
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn same_seed_same_numbers() {
+        let mut first = RandomGenerator::new(Seed::from_u64(42));
+        let mut second = RandomGenerator::new(Seed::from_u64(42));
+        let first: Vec<u32> = (0..10).map(|_| first.rng().gen()).collect();
+        let second: Vec<u32> = (0..10).map(|_| second.rng().gen()).collect();
+        assert_eq!(first, second);
+
+        let mut other = RandomGenerator::new(Seed::from_u64(43));
+        let other: Vec<u32> = (0..10).map(|_| other.rng().gen()).collect();
+        assert_ne!(first, other);
+    }
+}
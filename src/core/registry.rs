@@ -0,0 +1,196 @@
+//! Read and write the components of an entity by name, as JSON. Can be used by an in-game
+//! inspector or a level editor.
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+type ReadFn = Box<dyn Fn(&hecs::World, hecs::Entity) -> Option<Value> + Send + Sync>;
+type WriteFn =
+    Box<dyn Fn(&mut hecs::World, hecs::Entity, Value) -> Result<(), anyhow::Error> + Send + Sync>;
+
+struct RegisteredComponent {
+    name: String,
+    read: ReadFn,
+    write: WriteFn,
+}
+
+/// Components that can be inspected. Register the component types once, e.g.
+/// `registry.register::<Transform>("transform")`.
+#[derive(Default)]
+pub struct ComponentRegistry {
+    components: Vec<RegisteredComponent>,
+}
+
+impl ComponentRegistry {
+    /// Register a component type. If the name is already used, the previous registration is
+    /// replaced.
+    pub fn register<T>(&mut self, name: &str)
+    where
+        T: Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        self.register_with::<T, _>(name, |_, _| {});
+    }
+
+    /// Register a component type that has runtime state which is not serialized, e.g. the
+    /// physics handle of a `RigidBodyComponent`. When the entity already has the component,
+    /// `keep` copies that state from the current component to the one written back, e.g.
+    /// `|old, new| new.handle = old.handle` for a rigid body.
+    pub fn register_with<T, F>(&mut self, name: &str, keep: F)
+    where
+        T: Serialize + DeserializeOwned + Send + Sync + 'static,
+        F: Fn(&T, &mut T) + Send + Sync + 'static,
+    {
+        let read: ReadFn = Box::new(|world, e| {
+            let component = world.get::<T>(e).ok()?;
+            match serde_json::to_value(&*component) {
+                Ok(value) => Some(value),
+                Err(err) => {
+                    error!("Cannot serialize component = {:?}", err);
+                    None
+                }
+            }
+        });
+
+        let write: WriteFn = Box::new(move |world, e, value| {
+            let mut component: T = serde_json::from_value(value)?;
+            if let Ok(mut existing) = world.get_mut::<T>(e) {
+                keep(&*existing, &mut component);
+                *existing = component;
+                return Ok(());
+            }
+            world.insert_one(e, component)?;
+            Ok(())
+        });
+
+        self.components.retain(|c| c.name != name);
+        self.components.push(RegisteredComponent {
+            name: name.to_string(),
+            read,
+            write,
+        });
+    }
+
+    /// Names of the registered components.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.components.iter().map(|c| c.name.as_str())
+    }
+
+    /// Get all the registered components of the entity, as a JSON object.
+    pub fn read(&self, world: &hecs::World, e: hecs::Entity) -> Map<String, Value> {
+        self.components
+            .iter()
+            .filter_map(|c| (c.read)(world, e).map(|value| (c.name.clone(), value)))
+            .collect()
+    }
+
+    /// Get one component of the entity. None if the entity does not have it or if the name
+    /// is not registered.
+    pub fn read_component(
+        &self,
+        world: &hecs::World,
+        e: hecs::Entity,
+        name: &str,
+    ) -> Option<Value> {
+        self.find(name).and_then(|c| (c.read)(world, e))
+    }
+
+    /// Write back the components of a JSON object created with `read`.
+    pub fn write(
+        &self,
+        world: &mut hecs::World,
+        e: hecs::Entity,
+        components: Map<String, Value>,
+    ) -> Result<(), anyhow::Error> {
+        for (name, value) in components {
+            self.write_component(world, e, &name, value)?;
+        }
+        Ok(())
+    }
+
+    /// Replace the component of the entity. The component is added if the entity does not have
+    /// it yet.
+    pub fn write_component(
+        &self,
+        world: &mut hecs::World,
+        e: hecs::Entity,
+        name: &str,
+        value: Value,
+    ) -> Result<(), anyhow::Error> {
+        let component = self
+            .find(name)
+            .ok_or_else(|| anyhow::anyhow!("Component {} is not registered", name))?;
+        (component.write)(world, e, value)
+    }
+
+    /// Modify a component in its JSON form and write it back.
+    pub fn edit<F>(
+        &self,
+        world: &mut hecs::World,
+        e: hecs::Entity,
+        name: &str,
+        f: F,
+    ) -> Result<(), anyhow::Error>
+    where
+        F: FnOnce(&mut Value),
+    {
+        let mut value = self
+            .read_component(world, e, name)
+            .ok_or_else(|| anyhow::anyhow!("Entity does not have component {}", name))?;
+        f(&mut value);
+        self.write_component(world, e, name, value)
+    }
+
+    fn find(&self, name: &str) -> Option<&RegisteredComponent> {
+        self.components.iter().find(|c| c.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::physics::RigidBodyComponent;
+    use crate::core::transform::Transform;
+    use crate::geom2::Vector2f;
+
+    #[test]
+    fn transform_round_trip() {
+        let mut registry = ComponentRegistry::default();
+        registry.register::<Transform>("transform");
+        let mut world = hecs::World::new();
+        let e = world.spawn((Transform::from_translation(Vector2f::new(1.0, 2.0)),));
+
+        registry
+            .edit(&mut world, e, "transform", |value| {
+                value["translation"] = serde_json::json!([3.0, 4.0]);
+            })
+            .unwrap();
+        let components = registry.read(&world, e);
+        assert!(components.contains_key("transform"));
+        registry.write(&mut world, e, components).unwrap();
+
+        let t = world.get::<Transform>(e).unwrap();
+        assert_eq!(t.translation, Vector2f::new(3.0, 4.0));
+    }
+
+    #[test]
+    fn write_keeps_the_body_handle() {
+        let mut registry = ComponentRegistry::default();
+        registry.register_with::<RigidBodyComponent, _>("rigid_body", |old, new| {
+            new.handle = old.handle
+        });
+        let mut world = hecs::World::new();
+        let mut bodies = rapier2d::dynamics::RigidBodySet::new();
+        let handle = bodies.insert(rapier2d::dynamics::RigidBodyBuilder::new_dynamic().build());
+        let mut rbc = RigidBodyComponent::new_dynamic_cuboid(1.0, 1.0);
+        rbc.handle = Some(handle);
+        let e = world.spawn((rbc,));
+
+        let components = registry.read(&world, e);
+        registry.write(&mut world, e, components).unwrap();
+
+        assert_eq!(
+            world.get::<RigidBodyComponent>(e).unwrap().handle,
+            Some(handle)
+        );
+    }
+}
@@ -153,6 +153,11 @@ where
     fn update(&mut self, dt: Duration, world: &mut World, resources: &Resources)
         -> SceneResult<GE>;
 
+    /// Update the systems that should run at a fixed rate (AI...). Can be called zero or
+    /// several times per frame, before the animations and the physic step of the engine. `dt` is
+    /// the duration of the fixed step.
+    fn fixed_update(&mut self, _dt: Duration, _world: &mut World, _resources: &Resources) {}
+
     fn prepare_gui(
         &mut self,
         _dt: Duration,
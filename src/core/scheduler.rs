@@ -0,0 +1,122 @@
+//! Separate the systems that need to run at a fixed rate (physics, AI...) from the ones that
+//! run once per frame (rendering, UI...).
+use std::time::Duration;
+
+/// Accumulate the frame durations and tell how many fixed steps should run this frame.
+/// This is available as a resource so that rendering code can get the interpolation alpha.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedTimestep {
+    /// Duration of one fixed step.
+    step: Duration,
+    /// Time that has not been consumed by the fixed steps yet.
+    accumulator: Duration,
+    /// Maximum number of steps per frame. Avoid spending all the frame catching up
+    /// if the game is too slow.
+    max_steps: u32,
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self::new(Duration::from_secs_f32(1.0 / 60.0))
+    }
+}
+
+impl FixedTimestep {
+    pub fn new(step: Duration) -> Self {
+        Self {
+            step,
+            accumulator: Duration::from_secs(0),
+            max_steps: 5,
+        }
+    }
+
+    pub fn with_max_steps(mut self, max_steps: u32) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Duration of one fixed step.
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    /// Add the frame duration and return the number of fixed steps to run.
+    pub fn advance(&mut self, dt: Duration) -> u32 {
+        if self.step.as_secs_f32() <= 0.0 {
+            return 0;
+        }
+
+        self.accumulator += dt;
+        let mut steps = 0;
+        while self.accumulator >= self.step {
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+
+        if steps > self.max_steps {
+            warn!(
+                "Running late, dropping {} fixed steps",
+                steps - self.max_steps
+            );
+            steps = self.max_steps;
+        }
+        steps
+    }
+
+    /// How far we are between the last fixed step and the next one (between 0 and 1). Use it to
+    /// interpolate between the previous and current state when rendering.
+    pub fn alpha(&self) -> f32 {
+        if self.step.as_secs_f32() <= 0.0 {
+            return 0.0;
+        }
+        self.accumulator.as_secs_f32() / self.step.as_secs_f32()
+    }
+}
+
+/// Cap the frame rate by telling the main loop how long it should sleep after a frame.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameLimiter {
+    /// Minimum duration of a frame. None if the frame rate is not capped.
+    target: Option<Duration>,
+}
+
+impl FrameLimiter {
+    pub fn new(max_fps: Option<u32>) -> Self {
+        let target = max_fps
+            .filter(|fps| *fps > 0)
+            .map(|fps| Duration::from_secs_f64(1.0 / fps as f64));
+        Self { target }
+    }
+
+    /// Time to sleep after a frame that took `frame_duration`.
+    pub fn sleep_duration(&self, frame_duration: Duration) -> Option<Duration> {
+        self.target
+            .filter(|target| frame_duration < *target)
+            .map(|target| target - frame_duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_produce_fixed_steps() {
+        let mut timestep = FixedTimestep::new(Duration::from_millis(10));
+        let steps: Vec<u32> = (0..4)
+            .map(|_| timestep.advance(Duration::from_millis(15)))
+            .collect();
+
+        // 60ms of frames are 6 steps of 10ms.
+        assert_eq!(steps, vec![1, 2, 1, 2]);
+        assert_eq!(timestep.alpha(), 0.0);
+        timestep.advance(Duration::from_millis(5));
+        assert!((timestep.alpha() - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn slow_frame_is_capped() {
+        let mut timestep = FixedTimestep::new(Duration::from_millis(10)).with_max_steps(3);
+        assert_eq!(timestep.advance(Duration::from_millis(100)), 3);
+    }
+}
@@ -1,12 +1,5 @@
 //! Provide a macro to create SerializableEntity that can be saved, sent over network and so on...
 
-// fn get_component<T>(world: &hecs::World, e: hecs::Entity) -> Option<T>
-// where
-//     T: Clone + Send + Sync + 'static,
-// {
-//     world.get::<T>(e).ok().map(|c| (*c).clone())
-// }
-
 #[macro_export]
 macro_rules! serialize {
     ($(($name:ident, $component:ty)),+) => {
@@ -24,6 +17,15 @@ macro_rules! serialize {
 
         impl SerializedEntity {
 
+            /// Copy the registered components of an entity.
+            pub fn from_entity(world: &hecs::World, e: hecs::Entity) -> Self {
+                Self {
+                    $(
+                        $name: world.get::<$component>(e).ok().map(|c| (*c).clone()),
+                    )+
+                }
+            }
+
             pub fn spawn(&self, world: &mut hecs::World, resources: &Resources) -> hecs::Entity {
                 let mut builder = hecs::EntityBuilder::new();
 
@@ -37,13 +39,15 @@ macro_rules! serialize {
 
                 // If there is a physic component, let's register some stuff !
                 if let Some(mut physics) = resources.fetch_mut::<CollisionWorld>() {
-                    if let Ok(t) = world.get::<Transform>(e) {
-                        if let Ok(mut rbc) = world.get_mut::<RigidBodyComponent>(e) {
-                            physics.add_body_with_entity(&t.translation, &mut rbc, e);
-                        }
+                    if let Ok(mut rbc) = world.get_mut::<RigidBodyComponent>(e) {
+                        // The component might come from another entity, its body cannot be
+                        // shared.
+                        rbc.handle = None;
                     }
+                    physics.add_entity(world, e);
                 }
 
+                $crate::event::notify_spawned(resources, e);
                 e
             }
 
@@ -57,5 +61,11 @@ macro_rules! serialize {
                 e
             }
         }
+
+        /// Duplicate an entity with its registered components. The other components (such as
+        /// the parent/children links) are not copied. The clone gets its own rigid body.
+        pub fn clone_entity(world: &mut hecs::World, resources: &Resources, src: hecs::Entity) -> hecs::Entity {
+            SerializedEntity::from_entity(world, src).spawn(world, resources)
+        }
     };
 }
@@ -0,0 +1,117 @@
+//! Uniform grid to find the entities around a point (flocking, targeting...) without going
+//! through the physics colliders.
+use crate::core::transform::Transform;
+use crate::geom2::Vector2f;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Entities with this component are added to the `SpatialGrid`.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct SpatialIndexed;
+
+/// Entities bucketed by position. The grid is rebuilt by the game every frame, after the
+/// transforms are updated.
+#[derive(Debug, Clone)]
+pub struct SpatialGrid {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<(hecs::Entity, Vector2f)>>,
+}
+
+impl Default for SpatialGrid {
+    fn default() -> Self {
+        Self::new(64.0)
+    }
+}
+
+impl SpatialGrid {
+    /// Queries are faster when the cell size is close to the radius used in `neighbors`.
+    pub fn new(cell_size: f32) -> Self {
+        assert!(cell_size > 0.0, "Cell size should be positive");
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    fn cell(&self, point: &Vector2f) -> (i32, i32) {
+        (
+            (point.x / self.cell_size).floor() as i32,
+            (point.y / self.cell_size).floor() as i32,
+        )
+    }
+
+    pub fn clear(&mut self) {
+        // Keep the buckets to avoid allocating every frame.
+        for entities in self.cells.values_mut() {
+            entities.clear();
+        }
+    }
+
+    pub fn insert(&mut self, e: hecs::Entity, position: Vector2f) {
+        let cell = self.cell(&position);
+        self.cells.entry(cell).or_default().push((e, position));
+    }
+
+    /// Add all the entities with a `SpatialIndexed` component.
+    pub fn rebuild(&mut self, world: &hecs::World) {
+        self.clear();
+        for (e, (t, _)) in world.query::<(&Transform, &SpatialIndexed)>().iter() {
+            self.insert(e, t.translation);
+        }
+    }
+
+    /// Entities at most `radius` away from the point.
+    pub fn neighbors(&self, point: Vector2f, radius: f32) -> Vec<hecs::Entity> {
+        let offset = Vector2f::new(radius, radius);
+        let (min_x, min_y) = self.cell(&(point - offset));
+        let (max_x, max_y) = self.cell(&(point + offset));
+        let radius_squared = radius * radius;
+
+        let mut neighbors = vec![];
+        for x in min_x..=max_x {
+            for y in min_y..=max_y {
+                if let Some(entities) = self.cells.get(&(x, y)) {
+                    neighbors.extend(
+                        entities
+                            .iter()
+                            .filter(|(_, pos)| (pos - point).norm_squared() <= radius_squared)
+                            .map(|(e, _)| *e),
+                    );
+                }
+            }
+        }
+        neighbors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn neighbors_across_cells() {
+        let mut world = hecs::World::new();
+        let close = world.spawn(());
+        let other_cell = world.spawn(());
+        let far = world.spawn(());
+
+        let mut grid = SpatialGrid::new(10.0);
+        grid.insert(close, Vector2f::new(1.0, 1.0));
+        grid.insert(other_cell, Vector2f::new(-4.0, 0.0));
+        grid.insert(far, Vector2f::new(8.0, 8.0));
+
+        let mut neighbors = grid.neighbors(Vector2f::new(0.0, 0.0), 5.0);
+        neighbors.sort_by_key(|e| e.to_bits());
+        assert_eq!(neighbors, vec![close, other_cell]);
+    }
+
+    #[test]
+    fn clear_removes_the_entities() {
+        let mut world = hecs::World::new();
+        let e = world.spawn(());
+        let mut grid = SpatialGrid::default();
+        grid.insert(e, Vector2f::new(0.0, 0.0));
+        grid.clear();
+        assert!(grid.neighbors(Vector2f::new(0.0, 0.0), 100.0).is_empty());
+    }
+}
@@ -1,6 +1,65 @@
+use crate::resources::Resources;
 use serde_derive::{Deserialize, Serialize};
 use std::time::Duration;
 
+/// Global time scale applied to the gameplay systems (animations, particles, physics, timers).
+/// 1.0 is the normal speed, 0.0 freezes the gameplay.
+#[derive(Debug, Copy, Clone)]
+pub struct TimeScale(pub f32);
+
+impl Default for TimeScale {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+impl TimeScale {
+    /// Scale the frame duration. Negative or invalid scales are treated as 0.
+    pub fn scale(&self, dt: Duration) -> Duration {
+        if self.0.is_finite() && self.0 > 0.0 {
+            dt.mul_f32(self.0)
+        } else {
+            Duration::from_secs(0)
+        }
+    }
+}
+
+/// Pause the gameplay: physics, particles, animations, tweens... get a null frame duration. The
+/// UI does not use the scaled duration so menus keep working while paused.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct GamePaused(pub bool);
+
+/// True if the `GamePaused` resource exists and is set.
+pub fn is_paused(resources: &Resources) -> bool {
+    resources
+        .fetch::<GamePaused>()
+        .map(|p| p.0)
+        .unwrap_or(false)
+}
+
+/// Duration of the current frame, before applying the time scale. Updated once per frame.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct DeltaTime(pub Duration);
+
+/// Time since the game started, sum of all the frame durations. Used as the `time` uniform of
+/// the shaders.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ElapsedTime(pub Duration);
+
+/// Scale the frame duration with the `TimeScale` resource if it exists. Zero when the game is
+/// paused.
+pub fn scaled_dt(resources: &Resources, dt: Duration) -> Duration {
+    if is_paused(resources) {
+        return Duration::from_secs(0);
+    }
+    resources
+        .fetch::<TimeScale>()
+        .map(|scale| scale.scale(dt))
+        .unwrap_or(dt)
+}
+
+/// Countdown in seconds. The elapsed time is serialized so that a timer saved in the middle of
+/// its countdown finishes at the same point after loading.
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Timer {
     /// Deadline in seconds.
@@ -39,6 +98,17 @@ impl Timer {
         self.elapsed = 0.0;
     }
 
+    /// Start the next countdown, keeping the time elapsed after the deadline. Use it instead of
+    /// `reset` for repeating timers so that they do not drift.
+    pub fn restart(&mut self) {
+        self.elapsed = (self.elapsed - self.deadline).max(0.0);
+    }
+
+    /// Duration of the countdown in seconds.
+    pub fn deadline(&self) -> f32 {
+        self.deadline
+    }
+
     pub fn set_deadline(&mut self, deadline: f32) {
         self.deadline = deadline;
     }
@@ -48,8 +118,46 @@ impl Timer {
         self.elapsed += dt.as_secs_f32();
     }
 
+    /// Fraction of the deadline that has elapsed, between 0 and 1.
+    pub fn progress(&self) -> f32 {
+        if self.deadline <= 0.0 {
+            1.0
+        } else {
+            (self.elapsed / self.deadline).min(1.0)
+        }
+    }
+
     /// Returns true if the deadline has been reached.
     pub fn finished(&self) -> bool {
         self.elapsed >= self.deadline
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restart_keeps_the_overshoot() {
+        let mut timer = Timer::of_seconds(1.0);
+        timer.tick(Duration::from_millis(1250));
+        assert!(timer.finished());
+
+        timer.restart();
+        assert!(!timer.finished());
+        assert!((timer.remaining() - 0.75).abs() < 1e-5);
+    }
+
+    #[test]
+    fn reload_a_half_finished_timer() {
+        let mut timer = Timer::of_seconds(1.0);
+        timer.tick(Duration::from_millis(500));
+
+        let saved = serde_json::to_string(&timer).unwrap();
+        let mut timer: Timer = serde_json::from_str(&saved).unwrap();
+        timer.tick(Duration::from_millis(250));
+        assert!(!timer.finished());
+        timer.tick(Duration::from_millis(250));
+        assert!(timer.finished());
+    }
+}
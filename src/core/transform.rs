@@ -5,8 +5,9 @@ use rapier2d::na::{Matrix3, Vector3};
 use serde_derive::{Deserialize, Serialize};
 
 
-/// Transform of an element to place it on the screen
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
+/// Transform of an element to place it on the screen. The default transform is at the origin
+/// with a scale of 1.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Transform {
     /// Translation along x-y
     pub translation: Vector2f,
@@ -19,7 +20,27 @@ pub struct Transform {
     pub dirty: bool,
 }
 
+impl Default for Transform {
+    fn default() -> Self {
+        Self::from_translation(Vector2f::zeros())
+    }
+}
+
 impl Transform {
+    pub fn new(translation: Vector2f, rotation: f32, scale: Vector2f) -> Self {
+        Self {
+            translation,
+            scale,
+            rotation,
+            dirty: true,
+        }
+    }
+
+    /// Transform without rotation and with a scale of 1.
+    pub fn from_translation(translation: Vector2f) -> Self {
+        Self::new(translation, 0.0, Vector2f::new(1.0, 1.0))
+    }
+
     /// Get the model matrix for the transform
     pub fn to_model(&self) -> Matrix4f {
         let rot_mat = Matrix4f::new_rotation(Vector3::new(0.0, 0.0, self.rotation));
@@ -186,3 +207,37 @@ pub fn update_transforms(_world: &mut hecs::World) {
 //     let angle = mat.x_axis().y().atan2(mat.x_axis().x());
 //     (angle, translation)
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_scale_is_one() {
+        let t = Transform::default();
+        assert_eq!(t.translation, Vector2f::zeros());
+        assert_eq!(t.scale, Vector2f::new(1.0, 1.0));
+        assert_eq!(t.rotation, 0.0);
+    }
+
+    #[test]
+    fn constructors_give_an_invertible_matrix() {
+        let transforms = [
+            Transform::default(),
+            Transform::from_translation(Vector2f::new(1.0, 2.0)),
+            Transform::new(Vector2f::new(1.0, 2.0), 1.0, Vector2f::new(2.0, 0.5)),
+        ];
+        for t in &transforms {
+            assert!(t.to_mat().try_inverse().is_some());
+        }
+    }
+
+    #[test]
+    fn translate_marks_dirty() {
+        let mut t = Transform::from_translation(Vector2f::new(1.0, 2.0));
+        t.dirty = false;
+        t.translate(Vector2f::new(1.0, 1.0));
+        assert_eq!(t.translation, Vector2f::new(2.0, 3.0));
+        assert!(t.dirty);
+    }
+}
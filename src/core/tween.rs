@@ -0,0 +1,203 @@
+//! Animate a transform without spritesheet (slide a menu, scale a popup...).
+use crate::core::curve::Easing;
+use crate::core::timer::{scaled_dt, Timer};
+use crate::core::transform::Transform;
+use crate::event::{CustomGameEvent, EventQueue, GameEvent};
+use crate::geom2::Vector2f;
+use crate::resources::Resources;
+use serde_derive::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum TweenMode {
+    /// Stop when reaching the target.
+    Once,
+    /// Go back to the start and play again.
+    Loop,
+    /// Go back and forth between the start and the target.
+    PingPong,
+}
+
+impl Default for TweenMode {
+    fn default() -> Self {
+        TweenMode::Once
+    }
+}
+
+/// Start of the tween, taken from the transform the first time the tween is updated.
+#[derive(Debug, Clone, Copy)]
+struct TweenStart {
+    translation: Vector2f,
+    scale: Vector2f,
+    rotation: f32,
+}
+
+/// Interpolate the entity's transform to the target values. Values set to None are
+/// not touched.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tween {
+    pub translation: Option<Vector2f>,
+    pub scale: Option<Vector2f>,
+    pub rotation: Option<f32>,
+
+    pub timer: Timer,
+    #[serde(default)]
+    pub easing: Easing,
+    #[serde(default)]
+    pub mode: TweenMode,
+
+    #[serde(skip)]
+    start: Option<TweenStart>,
+    /// true when going from target to start (ping-pong)
+    #[serde(skip)]
+    reversed: bool,
+    #[serde(skip)]
+    finished: bool,
+}
+
+impl Tween {
+    pub fn new(duration: f32) -> Self {
+        Self {
+            translation: None,
+            scale: None,
+            rotation: None,
+            timer: Timer::of_seconds(duration),
+            easing: Easing::Linear,
+            mode: TweenMode::Once,
+            start: None,
+            reversed: false,
+            finished: false,
+        }
+    }
+
+    pub fn translate_to(mut self, translation: Vector2f) -> Self {
+        self.translation = Some(translation);
+        self
+    }
+
+    pub fn scale_to(mut self, scale: Vector2f) -> Self {
+        self.scale = Some(scale);
+        self
+    }
+
+    pub fn rotate_to(mut self, rotation: f32) -> Self {
+        self.rotation = Some(rotation);
+        self
+    }
+
+    pub fn with_easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    pub fn with_mode(mut self, mode: TweenMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Returns true if the tween has reached its target and will not update anymore.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Update the tween and the transform. Returns true if the tween just finished.
+    pub fn update(&mut self, transform: &mut Transform, dt: Duration) -> bool {
+        if self.finished {
+            return false;
+        }
+
+        let start = *self.start.get_or_insert(TweenStart {
+            translation: transform.translation,
+            scale: transform.scale,
+            rotation: transform.rotation,
+        });
+
+        self.timer.tick(dt);
+        let t = if self.reversed {
+            1.0 - self.timer.progress()
+        } else {
+            self.timer.progress()
+        };
+        let t = self.easing.apply(t);
+
+        if let Some(target) = self.translation {
+            transform.translation = start.translation + (target - start.translation) * t;
+        }
+        if let Some(target) = self.scale {
+            transform.scale = start.scale + (target - start.scale) * t;
+        }
+        if let Some(target) = self.rotation {
+            transform.rotation = start.rotation + (target - start.rotation) * t;
+        }
+        transform.dirty = true;
+
+        if self.timer.finished() {
+            match self.mode {
+                TweenMode::Once => {
+                    self.finished = true;
+                    return true;
+                }
+                // Keep the time elapsed after the target so that the loops do not drift.
+                TweenMode::Loop => self.timer.restart(),
+                TweenMode::PingPong => {
+                    self.reversed = !self.reversed;
+                    self.timer.restart();
+                }
+            }
+        }
+
+        false
+    }
+}
+
+pub struct TweenSystem;
+
+impl TweenSystem {
+    pub fn update<GE>(&mut self, world: &mut hecs::World, dt: Duration, resources: &Resources)
+    where
+        GE: CustomGameEvent,
+    {
+        let dt = scaled_dt(resources, dt);
+        let mut events = vec![];
+        for (e, (transform, tween)) in world.query::<(&mut Transform, &mut Tween)>().iter() {
+            if tween.update(transform, dt) {
+                events.push(GameEvent::TweenFinished(e));
+            }
+        }
+
+        {
+            let mut channel = resources.fetch_mut::<EventQueue<GE>>().unwrap();
+            channel.drain_vec_write(&mut events);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn midpoint() {
+        let mut transform = Transform::default();
+        let mut tween = Tween::new(1.0).translate_to(Vector2f::new(10.0, 0.0));
+        assert!(!tween.update(&mut transform, Duration::from_millis(500)));
+        assert_eq!(transform.translation, Vector2f::new(5.0, 0.0));
+
+        assert!(tween.update(&mut transform, Duration::from_millis(500)));
+        assert_eq!(transform.translation, Vector2f::new(10.0, 0.0));
+        assert!(tween.finished());
+    }
+
+    #[test]
+    fn loop_keeps_the_overshoot() {
+        let mut transform = Transform::default();
+        let mut tween = Tween::new(1.0)
+            .translate_to(Vector2f::new(10.0, 0.0))
+            .with_mode(TweenMode::Loop);
+        assert!(!tween.update(&mut transform, Duration::from_millis(1250)));
+        assert!(!tween.finished());
+
+        tween.update(&mut transform, Duration::from_millis(250));
+        assert_eq!(transform.translation, Vector2f::new(5.0, 0.0));
+    }
+}
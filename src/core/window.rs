@@ -1,4 +1,6 @@
 use crate::geom2::Vector2f;
+use crate::render::Context;
+use serde_derive::{Deserialize, Serialize};
 
 #[derive(Debug, Copy, Clone)]
 pub struct WindowDim {
@@ -20,3 +22,181 @@ impl WindowDim {
         Vector2f::new(self.width as f32, self.height as f32)
     }
 }
+
+/// How the game is displayed on the screen.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WindowMode {
+    Windowed,
+    /// Fullscreen at the resolution of the monitor.
+    BorderlessFullscreen,
+    /// Fullscreen at the size of the window. The resolution of the monitor is changed.
+    ExclusiveFullscreen,
+}
+
+impl Default for WindowMode {
+    fn default() -> Self {
+        WindowMode::Windowed
+    }
+}
+
+/// Current window mode. Changes are applied by the game at the start of the next frame, then the
+/// `WindowDim` is updated with the new size.
+#[derive(Debug, Default)]
+pub struct WindowSettings {
+    mode: WindowMode,
+    requested: Option<WindowMode>,
+    /// Position and size of the window before going fullscreen.
+    windowed_rect: Option<(i32, i32, u32, u32)>,
+}
+
+impl WindowSettings {
+    pub fn mode(&self) -> WindowMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: WindowMode) {
+        self.requested = Some(mode);
+    }
+
+    /// Switch between windowed and borderless fullscreen.
+    pub fn toggle_fullscreen(&mut self) {
+        let current = self.requested.unwrap_or(self.mode);
+        self.requested = Some(if current == WindowMode::Windowed {
+            WindowMode::BorderlessFullscreen
+        } else {
+            WindowMode::Windowed
+        });
+    }
+
+    /// Apply the requested mode to the window and update the window dimensions with its new
+    /// size when it is known. Returns true if the mode has changed.
+    pub(crate) fn apply(&mut self, surface: &mut Context, window_dim: &mut WindowDim) -> bool {
+        match self.requested.take() {
+            Some(mode) if mode != self.mode => {
+                info!("Change window mode to {:?}", mode);
+                if let Some((width, height)) = apply_window_mode(surface, self, mode) {
+                    window_dim.resize(width, height);
+                }
+                self.mode = mode;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Returns the new size of the window.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn apply_window_mode(
+    surface: &mut Context,
+    settings: &mut WindowSettings,
+    mode: WindowMode,
+) -> Option<(u32, u32)> {
+    use glfw::WindowMode as GlfwMode;
+    let window = &mut surface.window;
+
+    if settings.mode == WindowMode::Windowed {
+        let (x, y) = window.get_pos();
+        let (w, h) = window.get_size();
+        settings.windowed_rect = Some((x, y, w as u32, h as u32));
+    }
+
+    match mode {
+        WindowMode::Windowed => {
+            let (x, y, w, h) = settings.windowed_rect.unwrap_or_else(|| {
+                let (w, h) = window.get_size();
+                (100, 100, w as u32, h as u32)
+            });
+            window.set_monitor(GlfwMode::Windowed, x, y, w, h, None);
+            Some((w, h))
+        }
+        fullscreen => {
+            let mut glfw = window.glfw.clone();
+            glfw.with_primary_monitor(|_, monitor| {
+                let monitor = match monitor {
+                    Some(monitor) => monitor,
+                    None => {
+                        error!("No monitor found, cannot go fullscreen");
+                        return None;
+                    }
+                };
+
+                let video_mode = monitor.get_video_mode();
+                let (w, h, refresh_rate) = match (fullscreen, video_mode) {
+                    (WindowMode::BorderlessFullscreen, Some(video_mode)) => (
+                        video_mode.width,
+                        video_mode.height,
+                        Some(video_mode.refresh_rate),
+                    ),
+                    (_, video_mode) => {
+                        let (w, h) = window.get_size();
+                        (w as u32, h as u32, video_mode.map(|m| m.refresh_rate))
+                    }
+                };
+                window.set_monitor(GlfwMode::FullScreen(monitor), 0, 0, w, h, refresh_rate);
+                Some((w, h))
+            })
+        }
+    }
+}
+
+/// The browser decides the size of the fullscreen canvas so both fullscreen modes are the same.
+/// The size is not known yet, the game reads it from the canvas at the next frame.
+#[cfg(target_arch = "wasm32")]
+fn apply_window_mode(
+    _surface: &mut Context,
+    _settings: &mut WindowSettings,
+    mode: WindowMode,
+) -> Option<(u32, u32)> {
+    let document = match web_sys::window().and_then(|w| w.document()) {
+        Some(document) => document,
+        None => {
+            error!("No document, cannot change the window mode");
+            return None;
+        }
+    };
+
+    if mode == WindowMode::Windowed {
+        document.exit_fullscreen();
+    } else if let Some(element) = document.document_element() {
+        if let Err(e) = element.request_fullscreen() {
+            error!("Cannot go fullscreen = {:?}", e);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::test_surface;
+
+    #[test]
+    #[ignore] // needs an OpenGL context and a monitor
+    fn toggle_fullscreen_updates_the_window_dim() {
+        let mut surface = test_surface(200, 100);
+        let mut window_dim = WindowDim::new(200, 100);
+        let mut settings = WindowSettings::default();
+
+        settings.toggle_fullscreen();
+        assert!(settings.apply(&mut surface, &mut window_dim));
+        assert_eq!(settings.mode(), WindowMode::BorderlessFullscreen);
+        let video_mode = surface
+            .window
+            .glfw
+            .with_primary_monitor(|_, monitor| monitor.and_then(|m| m.get_video_mode()))
+            .unwrap();
+        assert_eq!(
+            (window_dim.width, window_dim.height),
+            (video_mode.width, video_mode.height)
+        );
+
+        // Back to the previous size.
+        settings.toggle_fullscreen();
+        assert!(settings.apply(&mut surface, &mut window_dim));
+        assert_eq!(settings.mode(), WindowMode::Windowed);
+        assert_eq!((window_dim.width, window_dim.height), (200, 100));
+        // Nothing requested.
+        assert!(!settings.apply(&mut surface, &mut window_dim));
+    }
+}
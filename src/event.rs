@@ -1,7 +1,13 @@
+use crate::config::Setting;
+use crate::core::physics::ContactDetails;
 use crate::core::timer::Timer;
+use crate::resources::Resources;
 use bitflags::_core::time::Duration;
 use rapier2d::geometry::ColliderHandle;
+use serde_derive::{Deserialize, Serialize};
 pub use shrev::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
 
 #[derive(Debug, Clone)]
 pub enum GameEvent<GE>
@@ -10,28 +16,141 @@ where
 {
     Delete(hecs::Entity),
 
-    /// Play the background music.
+    /// An entity was spawned from a prefab or a serialized entity. Sent at the end of the scene
+    /// update.
+    Spawned(hecs::Entity),
+
+    /// Play the background music. It loops until another music is played.
     PlayBackgroundMusic(String),
 
+    /// Play the background music once. `MusicFinished` is sent at the end.
+    PlayBackgroundMusicOnce(String),
+
+    /// A background music played with `PlayBackgroundMusicOnce` has finished.
+    MusicFinished(String),
+
     /// Play some sound
     PlaySound(String),
 
+    /// A value of the `Settings` resource was changed.
+    SettingChanged(Setting),
+
     /// Collision between entities
     ProximityEvent(ColliderHandle, ColliderHandle),
-    ContactEvent(ColliderHandle, ColliderHandle),
+    /// Entities that were intersecting are not intersecting anymore.
+    ProximityExited(ColliderHandle, ColliderHandle),
+    /// Two colliders started touching. Contains the contact point and normal.
+    ContactEvent(ColliderHandle, ColliderHandle, ContactDetails),
+
+    /// A non-looping tween has reached its target.
+    TweenFinished(hecs::Entity),
+
+    /// A camera has reached the target of `Camera::move_to`.
+    CameraArrived(hecs::Entity),
 
     /// Custom event, varies depending on the game.
     GameEvent(GE),
 }
 
+/// Entities spawned during the frame. The spawn functions do not know the type of the game events
+/// so the `Spawned` events are sent later by the game.
+#[derive(Debug, Default)]
+pub struct SpawnedEntities(Vec<hecs::Entity>);
+
+impl SpawnedEntities {
+    pub fn push(&mut self, e: hecs::Entity) {
+        self.0.push(e);
+    }
+
+    pub fn drain(&mut self) -> std::vec::Drain<hecs::Entity> {
+        self.0.drain(..)
+    }
+}
+
+/// Send a `GameEvent::Spawned` for an entity that was created outside of the prefabs.
+pub fn notify_spawned(resources: &Resources, e: hecs::Entity) {
+    if let Some(mut spawned) = resources.fetch_mut::<SpawnedEntities>() {
+        spawned.push(e);
+    }
+}
+
+/// Owns a reader id on the `EventQueue` so that systems do not have to fetch the queue
+/// themselves.
+pub struct EventReader<GE>
+where
+    GE: CustomGameEvent,
+{
+    rdr_id: EventReaderId<GE>,
+}
+
+impl<GE> EventReader<GE>
+where
+    GE: CustomGameEvent,
+{
+    /// Register a new reader. Only the events written after this call will be read.
+    pub fn new(resources: &mut Resources) -> Self {
+        let mut chan = resources
+            .fetch_mut::<EventQueue<GE>>()
+            .expect("Should have an EventQueue");
+        let rdr_id = chan.register_reader();
+        Self { rdr_id }
+    }
+
+    /// Events written since the last read.
+    pub fn read(&mut self, resources: &Resources) -> impl Iterator<Item = GameEvent<GE>> {
+        let chan = resources
+            .fetch::<EventQueue<GE>>()
+            .expect("Should have an EventQueue");
+        chan.read(&mut self.rdr_id)
+            .cloned()
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
 pub trait CustomGameEvent: std::fmt::Debug + Clone + Send + Sync + 'static {}
 
+/// Custom event waiting for its timer, as stored in a save. See
+/// `EventQueue::save_deferred_events`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedDeferredEvent<GE> {
+    pub event: GE,
+    /// Keeps its elapsed time so the event is sent at the same point after loading.
+    pub timer: Timer,
+}
+
+/// Number of unread events above which a warning is logged.
+const DEFAULT_BACKLOG_THRESHOLD: usize = 1024;
+
+/// Reader of an `EventQueue`, created with `EventQueue::register_reader`. The queue keeps track
+/// of the events that each reader has not read yet.
+pub struct EventReaderId<GE>
+where
+    GE: CustomGameEvent,
+{
+    id: ReaderId<GameEvent<GE>>,
+    /// Number of events written to the queue at the last read. Shared with the queue.
+    read_count: Arc<AtomicUsize>,
+}
+
 pub struct EventQueue<GE>
 where
     GE: CustomGameEvent,
 {
     chan: EventChannel<GameEvent<GE>>,
-    deferred_events: Option<Vec<(GameEvent<GE>, Timer)>>,
+    deferred_events: Vec<(GameEvent<GE>, Timer)>,
+    /// Buffers reused by `update_deferred` to avoid allocating every frame.
+    not_yet: Vec<(GameEvent<GE>, Timer)>,
+    to_send: Vec<GameEvent<GE>>,
+
+    /// Events written since the queue was created.
+    written: usize,
+    /// Read counts of the readers. Dropped readers are removed in `update_deferred`.
+    readers: Vec<Weak<AtomicUsize>>,
+    backlog_threshold: usize,
+    /// True when the last `update_deferred` found a reader over the threshold, so that the
+    /// warning is logged once and not every frame.
+    backed_up: bool,
 }
 
 impl<GE> EventQueue<GE>
@@ -39,47 +158,230 @@ where
     GE: CustomGameEvent,
 {
     pub fn new() -> Self {
+        Self::with_capacity(64)
+    }
+
+    /// Preallocate the channel for `capacity` events. The channel still grows when the readers
+    /// are late.
+    pub fn with_capacity(capacity: usize) -> Self {
         Self {
-            chan: EventChannel::new(),
-            deferred_events: Some(vec![]),
+            chan: EventChannel::with_capacity(capacity),
+            deferred_events: vec![],
+            not_yet: vec![],
+            to_send: vec![],
+            written: 0,
+            readers: vec![],
+            backlog_threshold: DEFAULT_BACKLOG_THRESHOLD,
+            backed_up: false,
         }
     }
 
+    /// Log a warning when a reader has more than `threshold` events to read. The channel keeps
+    /// the events until all the readers have read them, so a reader that is registered but
+    /// never reads makes it grow forever.
+    pub fn set_backlog_threshold(&mut self, threshold: usize) {
+        self.backlog_threshold = threshold;
+    }
+
+    /// Largest number of events that a reader has not read yet.
+    pub fn max_backlog(&self) -> usize {
+        self.readers
+            .iter()
+            .filter_map(Weak::upgrade)
+            .map(|read_count| self.written - read_count.load(Ordering::Relaxed))
+            .max()
+            .unwrap_or(0)
+    }
+
     /// Drain a vector of events into storage.
     pub fn drain_vec_write(&mut self, events: &mut Vec<GameEvent<GE>>) {
+        self.written += events.len();
         self.chan.drain_vec_write(events);
     }
 
     /// Write a single event into storage.
     pub fn single_write(&mut self, event: GameEvent<GE>) {
+        self.written += 1;
         self.chan.single_write(event);
     }
 
-    pub fn read(&self, reader_id: &mut ReaderId<GameEvent<GE>>) -> EventIterator<GameEvent<GE>> {
-        self.chan.read(reader_id)
+    pub fn read(&self, reader_id: &mut EventReaderId<GE>) -> EventIterator<GameEvent<GE>> {
+        reader_id.read_count.store(self.written, Ordering::Relaxed);
+        self.chan.read(&mut reader_id.id)
     }
 
-    pub fn register_reader(&mut self) -> ReaderId<GameEvent<GE>> {
-        self.chan.register_reader()
+    /// Only the events written after this call will be read.
+    pub fn register_reader(&mut self) -> EventReaderId<GE> {
+        let read_count = Arc::new(AtomicUsize::new(self.written));
+        self.readers.push(Arc::downgrade(&read_count));
+        EventReaderId {
+            id: self.chan.register_reader(),
+            read_count,
+        }
+    }
+
+    /// Send the `Spawned` events of the entities created since the last flush.
+    pub fn flush_spawned(&mut self, spawned: &mut SpawnedEntities) {
+        let mut events = spawned.drain().map(GameEvent::Spawned).collect();
+        self.drain_vec_write(&mut events);
     }
 
     pub fn add_deferred_event(&mut self, event: GameEvent<GE>, timer: Timer) {
+        self.deferred_events.push((event, timer));
+    }
+
+    /// Events waiting for their timer.
+    pub fn deferred_events(&self) -> &[(GameEvent<GE>, Timer)] {
+        &self.deferred_events
+    }
+
+    /// Custom events waiting for their timer, in a form that can be serialized. The engine
+    /// events are not saved: they refer to entities or colliders that do not exist anymore
+    /// after loading.
+    pub fn save_deferred_events(&self) -> Vec<SavedDeferredEvent<GE>> {
         self.deferred_events
-            .as_mut()
-            .expect("EventQueue should have a deferred vec.")
-            .push((event, timer));
+            .iter()
+            .filter_map(|(ev, timer)| match ev {
+                GameEvent::GameEvent(ev) => Some(SavedDeferredEvent {
+                    event: ev.clone(),
+                    timer: *timer,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Add back the events returned by `save_deferred_events`. They are sent when their
+    /// remaining time is elapsed.
+    pub fn load_deferred_events(&mut self, saved: Vec<SavedDeferredEvent<GE>>) {
+        for SavedDeferredEvent { event, timer } in saved {
+            self.add_deferred_event(GameEvent::GameEvent(event), timer);
+        }
     }
 
+    /// Send the deferred events whose timer is finished, then warn if a reader is late. Called
+    /// once per frame.
     pub fn update_deferred(&mut self, dt: Duration) {
-        let mut events = self
-            .deferred_events
-            .take()
-            .expect("EventQueue should have a deferred vec.");
-        events.iter_mut().for_each(|ev| ev.1.tick(dt));
-        let (mut to_send, not_yet) = events.drain(..).partition(|(ev, timer)| timer.finished());
-        self.deferred_events = Some(not_yet);
-
-        let mut to_send = to_send.drain(..).map(|(ev, _)| ev).collect();
-        self.chan.drain_vec_write(&mut to_send);
+        for (ev, mut timer) in self.deferred_events.drain(..) {
+            timer.tick(dt);
+            if timer.finished() {
+                self.to_send.push(ev);
+            } else {
+                self.not_yet.push((ev, timer));
+            }
+        }
+        std::mem::swap(&mut self.deferred_events, &mut self.not_yet);
+
+        self.written += self.to_send.len();
+        self.chan.drain_vec_write(&mut self.to_send);
+
+        self.readers.retain(|reader| reader.strong_count() > 0);
+        let backlog = self.max_backlog();
+        let backed_up = backlog > self.backlog_threshold;
+        if backed_up && !self.backed_up {
+            warn!(
+                "A reader has {} events to read (threshold is {}), is it missing a call to read?",
+                backlog, self.backlog_threshold
+            );
+        }
+        self.backed_up = backed_up;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    enum TestEvent {
+        Explode,
+    }
+    impl CustomGameEvent for TestEvent {}
+
+    #[test]
+    fn reload_deferred_events() {
+        let mut queue = EventQueue::<TestEvent>::new();
+        let mut world = hecs::World::new();
+        let e = world.spawn(());
+        queue.add_deferred_event(
+            GameEvent::GameEvent(TestEvent::Explode),
+            Timer::of_seconds(1.0),
+        );
+        queue.add_deferred_event(GameEvent::Delete(e), Timer::of_seconds(1.0));
+        queue.update_deferred(Duration::from_millis(500));
+
+        let saved = serde_json::to_string(&queue.save_deferred_events()).unwrap();
+        let mut queue = EventQueue::<TestEvent>::new();
+        let mut rdr_id = queue.register_reader();
+        queue.load_deferred_events(serde_json::from_str(&saved).unwrap());
+        assert_eq!(queue.deferred_events().len(), 1);
+
+        queue.update_deferred(Duration::from_millis(250));
+        assert_eq!(queue.read(&mut rdr_id).count(), 0);
+        queue.update_deferred(Duration::from_millis(250));
+        let sent: Vec<_> = queue.read(&mut rdr_id).cloned().collect();
+        assert!(matches!(
+            sent.as_slice(),
+            [GameEvent::GameEvent(TestEvent::Explode)]
+        ));
+    }
+
+    #[test]
+    fn update_deferred_reuses_the_buffers() {
+        let mut queue = EventQueue::<TestEvent>::new();
+        for seconds in 1..=3 {
+            queue.add_deferred_event(
+                GameEvent::GameEvent(TestEvent::Explode),
+                Timer::of_seconds(seconds as f32),
+            );
+        }
+        let buffers = |queue: &EventQueue<TestEvent>| {
+            let mut deferred = [
+                queue.deferred_events.as_ptr() as usize,
+                queue.not_yet.as_ptr() as usize,
+            ];
+            deferred.sort_unstable();
+            (deferred, queue.to_send.as_ptr() as usize)
+        };
+
+        queue.update_deferred(Duration::from_millis(500));
+        queue.update_deferred(Duration::from_millis(1000));
+        let allocated = buffers(&queue);
+        queue.update_deferred(Duration::from_millis(1000));
+        assert_eq!(buffers(&queue), allocated);
+        assert_eq!(queue.deferred_events().len(), 1);
+    }
+
+    #[test]
+    fn warn_when_a_reader_is_late() {
+        let mut queue = EventQueue::<TestEvent>::new();
+        queue.set_backlog_threshold(10);
+        let mut late = queue.register_reader();
+        let mut on_time = queue.register_reader();
+
+        for _ in 0..11 {
+            queue.single_write(GameEvent::GameEvent(TestEvent::Explode));
+        }
+        assert_eq!(queue.read(&mut on_time).count(), 11);
+        queue.update_deferred(Duration::from_millis(250));
+        assert_eq!(queue.max_backlog(), 11);
+        assert!(queue.backed_up);
+
+        assert_eq!(queue.read(&mut late).count(), 11);
+        queue.update_deferred(Duration::from_millis(250));
+        assert_eq!(queue.max_backlog(), 0);
+        assert!(!queue.backed_up);
+
+        // Dropped readers do not count.
+        let dropped = queue.register_reader();
+        for _ in 0..11 {
+            queue.single_write(GameEvent::GameEvent(TestEvent::Explode));
+        }
+        drop(dropped);
+        assert_eq!(queue.read(&mut late).count(), 11);
+        assert_eq!(queue.read(&mut on_time).count(), 11);
+        queue.update_deferred(Duration::from_millis(250));
+        assert_eq!(queue.max_backlog(), 0);
+        assert!(!queue.backed_up);
     }
 }
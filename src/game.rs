@@ -1,33 +1,49 @@
 #[cfg(feature = "hot-reload")]
 use crate::assets::HotReloader;
-use crate::config::AudioConfig;
-use crate::core::audio::AudioSystem;
-use crate::core::camera::{Camera, ProjectionMatrix, VirtualDim};
+use crate::config::{AudioConfig, DisplayConfig, GameEngineConfig, Settings};
+use crate::core::animation::AnimationSystem;
+use crate::core::audio::{AudioSystem, Playlist};
+use crate::core::camera::{
+    cursor_to_world, update_view_projection, Camera, MouseWorldPosition, ProjectionMatrix,
+    ProjectionOptions, ViewProjection, VirtualDim,
+};
 use crate::core::input::ser::{InputEvent, VirtualButton, VirtualKey};
 use crate::core::input::{Input, InputAction};
+use crate::core::name::{NameIndex, NameIndexSystem};
 use crate::core::random::{RandomGenerator, Seed};
 use crate::core::scene::{Scene, SceneResult, SceneStack};
+use crate::core::scheduler::{FixedTimestep, FrameLimiter};
+use crate::core::spatial::SpatialGrid;
+use crate::core::timer::{scaled_dt, DeltaTime, ElapsedTime, GamePaused, TimeScale};
 use crate::core::transform::update_transforms;
-use crate::core::window::WindowDim;
-use crate::event::{CustomGameEvent, EventQueue, GameEvent};
+use crate::core::window::{WindowDim, WindowSettings};
+use crate::event::{CustomGameEvent, EventQueue, EventReaderId, SpawnedEntities};
 //use crate::gameplay::collision::CollisionWorld;
 use crate::core::physics::{CollisionWorld, PhysicConfiguration};
-use crate::gameplay::delete::GarbageCollector;
-use crate::render::path::debug::DebugQueue;
+use crate::gameplay::delete::{GarbageCollector, OrphanPolicy};
+use crate::geom2::Vector2f;
+use crate::render::mesh::LayerVisibility;
+use crate::render::particle::update_particles;
+use crate::render::path::debug::{DebugQueue, Gizmos};
 use crate::render::ui::gui::GuiContext;
-use crate::render::{Context, Renderer};
+use crate::render::ui::world_text::add_world_texts;
+use crate::render::ui::{ui_area, UiAnchor};
+use crate::render::{ClearColor, Context, Renderer};
 use crate::resources::Resources;
 use crate::{HEIGHT, WIDTH};
 use log::info;
 use luminance_front::framebuffer::Framebuffer;
 use luminance_front::texture::Dim2;
-use shrev::ReaderId;
 use std::any::Any;
 use std::collections::HashMap;
 use std::marker::PhantomData;
 use std::thread;
 use std::time::{Duration, Instant};
 
+/// Longest delta time given to a frame. Longer frames (window dragged, breakpoint...) are
+/// slowed down instead of making the simulation jump.
+const MAX_FRAME_DURATION: Duration = Duration::from_millis(250);
+
 /// GameBuilder is used to create a new game. Game struct has a lot of members that do not need to be
 /// exposed so gamebuilder provides a simpler way to get started.
 pub struct GameBuilder<A, GE>
@@ -44,6 +60,7 @@ where
     input_config: Option<(HashMap<VirtualKey, A>, HashMap<VirtualButton, A>)>,
     gui_context: GuiContext,
     audio_config: AudioConfig,
+    display_config: DisplayConfig,
 }
 
 impl<A, GE> GameBuilder<A, GE>
@@ -65,6 +82,22 @@ where
         resources.insert(window_dim);
         resources.insert(virtual_dim);
         resources.insert(DebugQueue::default());
+        resources.insert(Gizmos::default());
+        resources.insert(TimeScale::default());
+        resources.insert(GamePaused::default());
+        resources.insert(DeltaTime::default());
+        resources.insert(SpawnedEntities::default());
+        resources.insert(NameIndex::default());
+        resources.insert(OrphanPolicy::default());
+        resources.insert(LayerVisibility::default());
+        resources.insert(UiAnchor::default());
+        resources.insert(Playlist::default());
+        resources.insert(SpatialGrid::default());
+        resources.insert(ElapsedTime::default());
+        resources.insert(ClearColor::default());
+        resources.insert(MouseWorldPosition::default());
+        resources.insert(ViewProjection::default());
+        resources.insert(WindowSettings::default());
 
         Self {
             physic_config: None,
@@ -76,6 +109,7 @@ where
             phantom_event: PhantomData::default(),
             seed: None,
             audio_config: AudioConfig::default(),
+            display_config: DisplayConfig::default(),
         }
     }
 
@@ -105,51 +139,142 @@ where
         self
     }
 
+    /// Vsync and frame-rate cap.
+    pub fn with_display_config(mut self, display_config: DisplayConfig) -> Self {
+        self.display_config = display_config;
+        self
+    }
+
     /// Add custom resources.
     pub fn with_resource<T: Any>(mut self, r: T) -> Self {
         self.resources.insert(r);
         self
     }
 
+    /// Add a font for the GUI. Get its id with `GuiContext::font` when preparing the GUI.
+    pub fn with_font(mut self, name: &str, font_data: &'static [u8]) -> Self {
+        self.gui_context.register_font(name, font_data);
+        self
+    }
+
+    /// Add a font file for the GUI.
+    pub fn with_font_file<P: AsRef<std::path::Path>>(mut self, name: &str, path: P) -> Self {
+        if let Err(e) = self.gui_context.load_font(name, path) {
+            error!("Cannot load font {} = {:?}", name, e);
+        }
+        self
+    }
+
+    /// Change the Y axis direction or the near/far planes of the projection.
+    pub fn with_projection_options(mut self, options: ProjectionOptions) -> Self {
+        let virtual_dim = *self.resources.fetch::<VirtualDim>().unwrap();
+        self.resources.insert(ProjectionMatrix::with_options(
+            virtual_dim.0 as f32,
+            virtual_dim.1 as f32,
+            options,
+        ));
+        self
+    }
+
     pub fn with_seed(mut self, seed: Seed) -> Self {
         self.seed = Some(seed);
         self
     }
 
-    pub fn build(mut self, surface: &mut Context) -> Game<A, GE> {
-        info!("Building Renderer");
-        let renderer = Renderer::new(surface, &self.gui_context);
-
-        if let Some(c) = self.physic_config {
+    /// Resources needed to run the simulation, with or without a graphics context.
+    fn insert_simulation_resources(&mut self) {
+        if let Some(c) = self.physic_config.take() {
             self.resources.insert(CollisionWorld::new(c));
         } else {
             self.resources.insert(CollisionWorld::default());
         }
+        // The physics is stepped once per fixed update so both should use the same time step.
+        let physics_dt = self.resources.fetch::<CollisionWorld>().unwrap().dt();
+        self.resources
+            .insert(FixedTimestep::new(Duration::from_secs_f32(physics_dt)));
 
         // and some asset manager;
         info!("Creating asset managers");
-        crate::assets::create_asset_managers(surface, &mut self.resources);
+        crate::assets::insert_asset_managers(&mut self.resources);
 
         // Need some input :D
         info!("Mapping inputs");
         let input: Input<A> = {
             let (key_mapping, btn_mapping) = self
                 .input_config
+                .take()
                 .unwrap_or((A::get_default_key_mapping(), A::get_default_mouse_mapping()));
             Input::new(key_mapping, btn_mapping)
         };
         self.resources.insert(input);
 
-        info!("Creating world");
-        let mut world = hecs::World::new();
+        let engine_config = self
+            .resources
+            .fetch::<GameEngineConfig>()
+            .map(|conf| *conf)
+            .unwrap_or_default();
+        self.resources
+            .insert(Settings::new(engine_config, self.audio_config));
 
         info!("Random seed");
         // if a seed is provided, let's add it to the resources.
-        if let Some(seed) = self.seed {
+        if let Some(seed) = self.seed.take() {
             self.resources.insert(RandomGenerator::new(seed));
         } else {
             self.resources.insert(RandomGenerator::from_entropy());
         }
+    }
+
+    /// Build the game without window, renderer nor audio. See `HeadlessGame`.
+    pub fn build_headless(mut self) -> HeadlessGame<A, GE> {
+        self.insert_simulation_resources();
+        let mut world = hecs::World::new();
+
+        let scene_stack = {
+            let mut scenes = SceneStack::default();
+            if let Some(scene) = self.scene {
+                scenes.push(scene, &mut world, &mut self.resources);
+            }
+            scenes
+        };
+
+        let rdr_id = {
+            let mut chan = self.resources.fetch_mut::<EventQueue<GE>>().unwrap();
+            chan.register_reader()
+        };
+        let garbage_collector = GarbageCollector::new(&mut self.resources);
+        let name_index = NameIndexSystem::new(&mut self.resources);
+        world.spawn((Camera::new(),));
+
+        HeadlessGame {
+            scene_stack,
+            world,
+            resources: self.resources,
+            rdr_id,
+            garbage_collector,
+            name_index,
+            phantom: self.phantom,
+        }
+    }
+
+    pub fn build(mut self, surface: &mut Context) -> Game<A, GE> {
+        info!("Building Renderer");
+        let renderer = Renderer::new(surface, &self.gui_context);
+
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            let interval = if self.display_config.vsync {
+                glfw::SwapInterval::Sync(1)
+            } else {
+                glfw::SwapInterval::None
+            };
+            surface.window.glfw.set_swap_interval(interval);
+        }
+
+        self.insert_simulation_resources();
+
+        info!("Creating world");
+        let mut world = hecs::World::new();
 
         info!("Creating scene stack");
         let scene_stack = {
@@ -167,6 +292,7 @@ where
         };
         info!("Creating garbage collector");
         let garbage_collector = GarbageCollector::new(&mut self.resources);
+        let name_index = NameIndexSystem::new(&mut self.resources);
 
         // we need a camera :)
         info!("Creating camera");
@@ -183,6 +309,8 @@ where
             )
         };
 
+        let virtual_dim = *self.resources.fetch::<VirtualDim>().unwrap();
+
         info!("Finished building game");
 
         Game {
@@ -191,10 +319,13 @@ where
             world,
             audio_system,
             audio_config: self.audio_config,
+            frame_limiter: FrameLimiter::new(self.display_config.max_fps),
             resources: self.resources,
             rdr_id,
             garbage_collector,
+            name_index,
             phantom: self.phantom,
+            virtual_dim,
             gui_context: self.gui_context,
             #[cfg(feature = "hot-reload")]
             hot_reloader: HotReloader::new(),
@@ -226,6 +357,9 @@ where
     audio_config: AudioConfig,
     audio_system: Option<AudioSystem<GE>>,
 
+    /// Sleep at the end of the frame to cap the frame rate.
+    frame_limiter: FrameLimiter,
+
     /// Resources (assets, inputs...)
     pub(crate) resources: Resources,
 
@@ -233,11 +367,17 @@ where
     world: hecs::World,
 
     /// Read events from the systems
-    rdr_id: ReaderId<GameEvent<GE>>,
+    rdr_id: EventReaderId<GE>,
 
     /// Clean up the dead entities.
     garbage_collector: GarbageCollector<GE>,
 
+    /// Find entities by name.
+    name_index: NameIndexSystem<GE>,
+
+    /// Virtual dimensions used for the current projection.
+    virtual_dim: VirtualDim,
+
     gui_context: GuiContext,
 
     phantom: PhantomData<A>,
@@ -255,8 +395,14 @@ where
     /// of browsers)
     pub fn create_audio_system(&mut self) {
         if self.audio_system.is_none() {
+            // The volume might have been changed in the settings since the game was built.
+            let audio_config = self
+                .resources
+                .fetch::<Settings>()
+                .map(|settings| *settings.audio())
+                .unwrap_or(self.audio_config);
             self.audio_system = Some(
-                AudioSystem::new(&self.resources, self.audio_config)
+                AudioSystem::new(&self.resources, audio_config)
                     .expect("Cannot create audio system"),
             );
         }
@@ -270,10 +416,11 @@ where
         );
     }
 
-    /// Run the game. This is the main loop.
+    /// Run the game. This is the main loop. The delta time of a frame is the measured duration
+    /// of the previous frame, clamped to `MAX_FRAME_DURATION`.
     pub fn run(&mut self, surface: &mut Context) {
         let mut current_time = Instant::now();
-        let dt = Duration::from_millis(16);
+        let mut dt = Duration::from_millis(16);
         let mut back_buffer = surface.back_buffer().unwrap();
 
         'app: loop {
@@ -285,11 +432,12 @@ where
                 break 'app;
             }
 
-            let now = Instant::now();
-            let frame_duration = now - current_time;
-            if frame_duration < dt {
-                thread::sleep(dt - frame_duration);
+            let frame_duration = Instant::now() - current_time;
+            if let Some(sleep) = self.frame_limiter.sleep_duration(frame_duration) {
+                thread::sleep(sleep);
             }
+            let now = Instant::now();
+            dt = (now - current_time).min(MAX_FRAME_DURATION);
             current_time = now;
         }
 
@@ -302,6 +450,46 @@ where
     //     self.gui_context.reset_inputs();
     // }
 
+    /// Convert the cursor position to world coordinates once for the frame.
+    fn update_mouse_world_position(&mut self) {
+        let cursor = {
+            let input = self.resources.fetch::<Input<A>>().unwrap();
+            let pos = input.raw_mouse_position();
+            Vector2f::new(pos.x(), pos.y())
+        };
+        if let Some(pos) = cursor_to_world(cursor, &self.world, &self.resources) {
+            self.resources.fetch_mut::<MouseWorldPosition>().unwrap().0 = pos;
+        }
+    }
+
+    /// Update the window dimensions after a resize. The projection is rebuilt from the virtual
+    /// dimensions so that the world keeps its aspect ratio; the renderer adds letterboxes around
+    /// the game viewport. Does nothing if the size has not changed.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if let Some(virtual_dim) = resize_window(&self.resources, width, height) {
+            self.virtual_dim = virtual_dim;
+        }
+    }
+
+    /// Rebuild the projection if the `VirtualDim` resource was changed.
+    fn check_virtual_dim(&mut self) {
+        let virtual_dim = *self.resources.fetch::<VirtualDim>().unwrap();
+        if virtual_dim != self.virtual_dim {
+            debug!("Virtual dimensions changed to {:?}", virtual_dim);
+            self.rebuild_projection();
+        }
+    }
+
+    /// The UI is laid out in the window or in the letterboxed viewport, depending on `UiAnchor`.
+    fn update_ui_area(&mut self) {
+        let (window_dim, offset) = ui_area(&self.resources);
+        self.gui_context.set_area(window_dim, offset);
+    }
+
+    fn rebuild_projection(&mut self) {
+        self.virtual_dim = rebuild_projection(&self.resources);
+    }
+
     pub fn process_input(&mut self, input_event: InputEvent) {
         let mut input = self.resources.fetch_mut::<Input<A>>().unwrap();
 
@@ -318,9 +506,13 @@ where
         mut back_buffer: &mut Framebuffer<Dim2, (), ()>,
         dt: Duration,
     ) -> bool {
+        self.resources.fetch_mut::<DeltaTime>().unwrap().0 = dt;
+        self.resources.fetch_mut::<ElapsedTime>().unwrap().0 += dt;
+
         {
+            let game_dt = scaled_dt(&self.resources, dt);
             let mut input = self.resources.fetch_mut::<Input<A>>().unwrap();
-            input.prepare();
+            input.prepare(game_dt);
             self.gui_context.reset_inputs();
         }
 
@@ -343,22 +535,36 @@ where
             }
         }
 
+        // The back buffer is recreated below, with the other resize events.
+        {
+            let mut window_dim = self.resources.fetch_mut::<WindowDim>().unwrap();
+            let mut settings = self.resources.fetch_mut::<WindowSettings>().unwrap();
+            if settings.apply(surface, &mut window_dim) {
+                resize = true;
+            }
+        }
+
+        self.check_virtual_dim();
+        self.update_ui_area();
+        update_view_projection(&self.world, &self.resources);
+        self.update_mouse_world_position();
+
         // 2. Update the scene.
         // ------------------------------------------------
         trace!("Update scene");
 
-        let scene_result = if let Some(scene) = self.scene_stack.current_mut() {
-            let scene_res = scene.update(dt, &mut self.world, &self.resources);
-
-            {
-                let chan = self.resources.fetch::<EventQueue<GE>>().unwrap();
-                for ev in chan.read(&mut self.rdr_id) {
-                    scene.process_event(&mut self.world, ev.clone(), &self.resources);
-                }
-            }
-
+        let scene_result = update_scene(
+            &mut self.scene_stack,
+            &mut self.world,
+            &self.resources,
+            &mut self.rdr_id,
+            dt,
+        );
+        if let Some(scene) = self.scene_stack.current_mut() {
             let maybe_gui =
                 scene.prepare_gui(dt, &mut self.world, &self.resources, &mut self.gui_context);
+            let maybe_gui =
+                add_world_texts(maybe_gui, &self.gui_context, &self.world, &self.resources);
 
             self.renderer.prepare_ui(
                 surface,
@@ -366,52 +572,31 @@ where
                 &self.resources,
                 &mut *self.gui_context.fonts.borrow_mut(),
             );
-
-            Some(scene_res)
-        } else {
-            None
-        };
-
-        // Update deferred events.
-        {
-            let mut chan = self.resources.fetch_mut::<EventQueue<GE>>().unwrap();
-            chan.update_deferred(dt);
         }
 
-        // Update children transforms:
-        // -----------------------------
-        update_transforms(&mut self.world);
-
-        // Physic step
-        // ------------------
-        {
-            let mut collision_world = self
-                .resources
-                .fetch_mut::<CollisionWorld>()
-                .expect("Should have a CollisionWorld");
-            collision_world.step::<GE>(&self.resources);
-            collision_world.synchronize(&self.world);
-        }
-
-        // 3. Clean up dead entities.
+        // Fixed update (animations, physic step, particles and scene fixed systems) then clean up
+        // the dead entities.
         // ------------------------------------------------
-        self.garbage_collector
-            .collect(&mut self.world, &self.resources);
+        simulate(
+            &mut self.scene_stack,
+            &mut self.world,
+            &self.resources,
+            &mut self.name_index,
+            &mut self.garbage_collector,
+            dt,
+        );
 
         // 4. Render to screen
         // ------------------------------------------------
-        self.renderer
-            .update::<GE>(surface, &self.world, dt, &self.resources);
         if resize {
             *back_buffer = surface.back_buffer().unwrap();
-            let new_size = back_buffer.size();
-            // let mut proj = self.resources.fetch_mut::<ProjectionMatrix>().unwrap();
-            // proj.resize(new_size[0] as f32, new_size[1] as f32);
-
-            let mut dim = self.resources.fetch_mut::<WindowDim>().unwrap();
-            dim.resize(new_size[0], new_size[1]);
-            self.gui_context.window_dim = *dim;
         }
+        // On the web there is no resize event, the canvas size is checked every frame.
+        let [width, height] = back_buffer.size();
+        self.resize(width, height);
+        // The scenes might have changed the resolution during the update.
+        self.check_virtual_dim();
+        self.update_ui_area();
 
         trace!("Render");
         let render = self
@@ -453,3 +638,291 @@ where
         true
     }
 }
+
+/// Game without window, renderer nor audio, for a server or for integration tests. The scenes,
+/// the physics, the animations, the particle simulation and the events run as in `Game`; the
+/// rendering, the GUI and the asset uploads are skipped.
+pub struct HeadlessGame<A, GE>
+where
+    GE: CustomGameEvent,
+{
+    scene_stack: SceneStack<GE>,
+    resources: Resources,
+    world: hecs::World,
+    rdr_id: EventReaderId<GE>,
+    garbage_collector: GarbageCollector<GE>,
+    name_index: NameIndexSystem<GE>,
+    phantom: PhantomData<A>,
+}
+
+impl<A, GE> HeadlessGame<A, GE>
+where
+    A: InputAction + 'static,
+    GE: CustomGameEvent + 'static,
+{
+    pub fn world(&self) -> &hecs::World {
+        &self.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut hecs::World {
+        &mut self.world
+    }
+
+    pub fn resources(&self) -> &Resources {
+        &self.resources
+    }
+
+    /// Inputs have to be sent by the caller as there is no window.
+    pub fn process_input(&mut self, input_event: InputEvent) {
+        let mut input = self.resources.fetch_mut::<Input<A>>().unwrap();
+        if let Some(scene) = self.scene_stack.current_mut() {
+            scene.process_input(&mut self.world, input_event.clone(), &self.resources);
+        }
+        input.process_event(input_event)
+    }
+
+    /// Run one frame of simulation.
+    pub fn run_frame(&mut self, dt: Duration) {
+        self.resources.fetch_mut::<DeltaTime>().unwrap().0 = dt;
+        self.resources.fetch_mut::<ElapsedTime>().unwrap().0 += dt;
+        let game_dt = scaled_dt(&self.resources, dt);
+        self.resources
+            .fetch_mut::<Input<A>>()
+            .unwrap()
+            .prepare(game_dt);
+
+        update_view_projection(&self.world, &self.resources);
+
+        let scene_result = update_scene(
+            &mut self.scene_stack,
+            &mut self.world,
+            &self.resources,
+            &mut self.rdr_id,
+            dt,
+        );
+        simulate(
+            &mut self.scene_stack,
+            &mut self.world,
+            &self.resources,
+            &mut self.name_index,
+            &mut self.garbage_collector,
+            dt,
+        );
+
+        if let Some(res) = scene_result {
+            self.scene_stack
+                .apply_result(res, &mut self.world, &mut self.resources);
+        }
+    }
+}
+
+/// Update the `WindowDim` and rebuild the projection. Returns the virtual dimensions used for
+/// the projection, None if the size has not changed.
+fn resize_window(resources: &Resources, width: u32, height: u32) -> Option<VirtualDim> {
+    {
+        let mut dim = resources.fetch_mut::<WindowDim>().unwrap();
+        if dim.width == width && dim.height == height {
+            return None;
+        }
+        debug!("Resize window to {}x{}", width, height);
+        dim.resize(width, height);
+    }
+
+    Some(rebuild_projection(resources))
+}
+
+/// Set the `ProjectionMatrix` to the current `VirtualDim` and return it.
+fn rebuild_projection(resources: &Resources) -> VirtualDim {
+    let virtual_dim = *resources.fetch::<VirtualDim>().unwrap();
+    resources
+        .fetch_mut::<ProjectionMatrix>()
+        .unwrap()
+        .resize(virtual_dim.0 as f32, virtual_dim.1 as f32);
+    virtual_dim
+}
+
+/// Update the current scene and let it process the events of the frame. The `ViewProjection`
+/// is updated after as the camera might have moved. None if there is no scene.
+fn update_scene<GE>(
+    scene_stack: &mut SceneStack<GE>,
+    world: &mut hecs::World,
+    resources: &Resources,
+    rdr_id: &mut EventReaderId<GE>,
+    dt: Duration,
+) -> Option<SceneResult<GE>>
+where
+    GE: CustomGameEvent + 'static,
+{
+    let scene = scene_stack.current_mut()?;
+    let scene_res = scene.update(dt, world, resources);
+
+    {
+        let mut spawned = resources.fetch_mut::<SpawnedEntities>().unwrap();
+        let mut chan = resources.fetch_mut::<EventQueue<GE>>().unwrap();
+        chan.flush_spawned(&mut spawned);
+    }
+
+    {
+        let chan = resources.fetch::<EventQueue<GE>>().unwrap();
+        for ev in chan.read(rdr_id) {
+            scene.process_event(world, ev.clone(), resources);
+        }
+    }
+
+    update_view_projection(world, resources);
+    Some(scene_res)
+}
+
+/// Simulation step of a frame, after the scene update. Shared by `Game` and `HeadlessGame`.
+fn simulate<GE>(
+    scene_stack: &mut SceneStack<GE>,
+    world: &mut hecs::World,
+    resources: &Resources,
+    name_index: &mut NameIndexSystem<GE>,
+    garbage_collector: &mut GarbageCollector<GE>,
+    dt: Duration,
+) where
+    GE: CustomGameEvent + 'static,
+{
+    // Update deferred events.
+    {
+        let mut chan = resources.fetch_mut::<EventQueue<GE>>().unwrap();
+        chan.update_deferred(scaled_dt(resources, dt));
+    }
+
+    // Update children transforms:
+    // -----------------------------
+    update_transforms(world);
+    if let Some(mut grid) = resources.fetch_mut::<SpatialGrid>() {
+        grid.rebuild(world);
+    }
+
+    // Fixed update: animations, physic step, particles and scene fixed systems.
+    // ------------------
+    let (steps, fixed_dt) = {
+        let mut timestep = resources.fetch_mut::<FixedTimestep>().unwrap();
+        (timestep.advance(dt), timestep.step())
+    };
+    for _ in 0..steps {
+        if let Some(scene) = scene_stack.current_mut() {
+            scene.fixed_update(fixed_dt, world, resources);
+        }
+
+        // Before the physic step as the keyframes enable the animation driven colliders.
+        AnimationSystem.animate::<GE>(world, fixed_dt, resources);
+        AnimationSystem.flipbooks(world, fixed_dt, resources);
+        AnimationSystem.update_colliders(world, resources);
+
+        {
+            let mut collision_world = resources
+                .fetch_mut::<CollisionWorld>()
+                .expect("Should have a CollisionWorld");
+            collision_world.apply_velocities(world);
+            collision_world.apply_gravity_zones(world, resources);
+            collision_world.apply_global_force(resources);
+            collision_world.step::<GE>(resources);
+        }
+
+        update_particles::<GE>(world, fixed_dt, resources);
+    }
+    {
+        let collision_world = resources
+            .fetch::<CollisionWorld>()
+            .expect("Should have a CollisionWorld");
+        collision_world.synchronize(world);
+    }
+
+    // Clean up dead entities.
+    // ------------------------------------------------
+    name_index.update(world, resources);
+    garbage_collector.collect(world, resources);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::animation::SpriteFlipbook;
+    use crate::core::timer::Timer;
+    use crate::gameplay::Action;
+    use crate::render::mesh::{Material, MeshRender, RenderLayer};
+
+    #[derive(Debug, Clone)]
+    struct TestEvent;
+    impl CustomGameEvent for TestEvent {}
+
+    fn headless_game() -> HeadlessGame<Action, TestEvent> {
+        GameBuilder::new(WindowDim::new(800, 600), VirtualDim(800, 600)).build_headless()
+    }
+
+    fn sprite() -> MeshRender {
+        MeshRender {
+            enabled: true,
+            material: Material::Sprite {
+                sprite_id: "test".to_string(),
+                sprite_nb: 0,
+                columns: 8,
+                rows: 1,
+                alpha_cutoff: None,
+                rotation_steps: 0,
+            },
+            depth: 0,
+            layer: RenderLayer::World,
+        }
+    }
+
+    #[test]
+    fn animations_run_at_each_fixed_step() {
+        let mut game = headless_game();
+        *game.resources().fetch_mut::<FixedTimestep>().unwrap() =
+            FixedTimestep::new(Duration::from_millis(250));
+        let e = game.world_mut().spawn((
+            SpriteFlipbook::new((0..8).collect(), Timer::of_seconds(0.25), false),
+            sprite(),
+        ));
+
+        for _ in 0..4 {
+            game.run_frame(Duration::from_millis(375));
+        }
+
+        // 1.5s of frames are 6 fixed steps of 250ms.
+        assert_eq!(game.world().get::<SpriteFlipbook>(e).unwrap().current, 6);
+    }
+
+    #[test]
+    fn frame_times_are_injected() {
+        let mut game = headless_game();
+        game.run_frame(Duration::from_millis(250));
+        game.run_frame(Duration::from_millis(500));
+
+        assert_eq!(
+            game.resources().fetch::<DeltaTime>().unwrap().0,
+            Duration::from_millis(500)
+        );
+        assert_eq!(
+            game.resources().fetch::<ElapsedTime>().unwrap().0,
+            Duration::from_millis(750)
+        );
+    }
+
+    #[test]
+    fn resize_rebuilds_the_projection() {
+        let mut resources = Resources::default();
+        resources.insert(WindowDim::new(800, 600));
+        resources.insert(VirtualDim(400, 300));
+        resources.insert(ProjectionMatrix::new(800.0, 600.0));
+
+        assert_eq!(
+            resize_window(&resources, 1024, 768),
+            Some(VirtualDim(400, 300))
+        );
+        let dim = *resources.fetch::<WindowDim>().unwrap();
+        assert_eq!((dim.width, dim.height), (1024, 768));
+        assert_eq!(
+            resources.fetch::<ProjectionMatrix>().unwrap().matrix(),
+            ProjectionMatrix::new(400.0, 300.0).matrix()
+        );
+
+        // Same size.
+        assert_eq!(resize_window(&resources, 1024, 768), None);
+    }
+}
@@ -1,16 +1,32 @@
 //! Clean entities the right way. Done at the end of a frame.
 
-use crate::event::{CustomGameEvent, EventQueue, GameEvent};
+use crate::core::owner::owned_with_lifetime;
+use crate::core::transform::{HasChildren, HasParent};
+use crate::event::{CustomGameEvent, EventQueue, EventReader, GameEvent};
 use crate::resources::Resources;
 use log::{debug, info};
-use shrev::ReaderId;
+
+/// What happens to the children of a deleted entity. Insert it as a resource to change it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrphanPolicy {
+    /// Delete the whole subtree. A `Delete` event is sent for each deleted child.
+    DespawnChildren,
+    /// Keep the children, they become root entities.
+    Detach,
+}
+
+impl Default for OrphanPolicy {
+    fn default() -> Self {
+        Self::Detach
+    }
+}
 
 /// ahahaha what a confusing name.
 pub struct GarbageCollector<GE>
 where
     GE: CustomGameEvent,
 {
-    rdr_id: ReaderId<GameEvent<GE>>,
+    reader: EventReader<GE>,
 }
 
 impl<GE> GarbageCollector<GE>
@@ -18,26 +34,210 @@ where
     GE: CustomGameEvent + 'static,
 {
     pub fn new(resources: &mut Resources) -> Self {
-        let mut chan = resources.fetch_mut::<EventQueue<GE>>().unwrap();
-        let rdr_id = chan.register_reader();
-        Self { rdr_id }
+        Self {
+            reader: EventReader::new(resources),
+        }
     }
 
     pub fn collect(&mut self, world: &mut hecs::World, resources: &Resources) {
-        let chan = resources.fetch::<EventQueue<GE>>().unwrap();
-        for ev in chan.read(&mut self.rdr_id) {
+        let policy = resources
+            .fetch::<OrphanPolicy>()
+            .map(|policy| *policy)
+            .unwrap_or_default();
+        let mut cascaded = vec![];
+        for ev in self.reader.read(resources) {
             if let GameEvent::Delete(e) = ev {
-                log::debug!("Will delete {:?}", e);
+                // The entities deleted in a cascade are already gone when their event is read.
+                if !world.contains(e) {
+                    continue;
+                }
+                // Owned entities with an `OwnedLifetime` are deleted with their owner.
+                let mut to_delete = vec![e];
+                while let Some(e) = to_delete.pop() {
+                    let owned = owned_with_lifetime(world, e);
+                    cascaded.extend(owned.iter().copied());
+                    to_delete.extend(owned);
+                    cascaded.extend(delete_entity(world, e, policy));
+                }
+            }
+        }
+
+        // Let the other systems (e.g. the `NameIndex`) know about the cascade (children and owned
+        // entities).
+        if !cascaded.is_empty() {
+            let mut events = cascaded.into_iter().map(GameEvent::Delete).collect();
+            let mut chan = resources.fetch_mut::<EventQueue<GE>>().unwrap();
+            chan.drain_vec_write(&mut events);
+        }
+    }
+}
 
-                // TODO Remove the rigid body if it has one.
+/// Returns the children that were deleted with the entity.
+fn delete_entity(
+    world: &mut hecs::World,
+    e: hecs::Entity,
+    policy: OrphanPolicy,
+) -> Vec<hecs::Entity> {
+    log::debug!("Will delete {:?}", e);
 
-                // remove from world
-                if let Err(e) = world.despawn(*e) {
-                    info!("Entity was already deleted (or does not exist?) = {}", e);
-                } else {
-                    debug!("Entity successfully deleted.");
-                }
+    // TODO Remove the rigid body if it has one.
+
+    detach_from_parent(world, e);
+    let children = world
+        .get::<HasChildren>(e)
+        .map(|c| c.children.clone())
+        .unwrap_or_default();
+    let mut deleted = vec![];
+    match policy {
+        OrphanPolicy::DespawnChildren => {
+            for child in children {
+                deleted.extend(despawn_subtree(world, child));
+            }
+        }
+        OrphanPolicy::Detach => {
+            for child in children {
+                let _ = world.remove_one::<HasParent>(child);
             }
         }
     }
+
+    // remove from world
+    if let Err(e) = world.despawn(e) {
+        info!("Entity was already deleted (or does not exist?) = {}", e);
+    } else {
+        debug!("Entity successfully deleted.");
+    }
+    deleted
+}
+
+/// Remove the entity from the `HasChildren` of its parent.
+fn detach_from_parent(world: &mut hecs::World, e: hecs::Entity) {
+    let parent = match world.get::<HasParent>(e) {
+        Ok(parent) => parent.entity,
+        Err(_) => return,
+    };
+    if let Ok(mut siblings) = world.get_mut::<HasChildren>(parent) {
+        siblings.children.retain(|child| *child != e);
+    }
+}
+
+/// Returns the entities that were despawned.
+fn despawn_subtree(world: &mut hecs::World, root: hecs::Entity) -> Vec<hecs::Entity> {
+    let mut despawned = vec![];
+    let mut to_despawn = vec![root];
+    while let Some(e) = to_despawn.pop() {
+        if let Ok(children) = world.get::<HasChildren>(e) {
+            to_despawn.extend(children.children.iter().copied());
+        }
+        if world.despawn(e).is_err() {
+            debug!("Child {:?} was already deleted", e);
+        } else {
+            despawned.push(e);
+        }
+    }
+    despawned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone)]
+    struct TestEvent;
+    impl CustomGameEvent for TestEvent {}
+
+    /// Delete the root of a parent -> child -> grandchild hierarchy. Returns the world, the
+    /// entities and the entities of the `Delete` events sent by the garbage collector.
+    fn delete_root(policy: OrphanPolicy) -> (hecs::World, [hecs::Entity; 3], Vec<hecs::Entity>) {
+        let mut resources = Resources::default();
+        resources.insert(EventQueue::<TestEvent>::new());
+        resources.insert(policy);
+        let mut garbage_collector = GarbageCollector::<TestEvent>::new(&mut resources);
+        let mut reader = EventReader::<TestEvent>::new(&mut resources);
+
+        let mut world = hecs::World::new();
+        let parent = world.spawn((HasChildren { children: vec![] },));
+        let child = world.spawn((HasParent { entity: parent },));
+        let grandchild = world.spawn((HasParent { entity: child },));
+        world
+            .get_mut::<HasChildren>(parent)
+            .unwrap()
+            .children
+            .push(child);
+        world
+            .insert_one(
+                child,
+                HasChildren {
+                    children: vec![grandchild],
+                },
+            )
+            .unwrap();
+
+        resources
+            .fetch_mut::<EventQueue<TestEvent>>()
+            .unwrap()
+            .single_write(GameEvent::Delete(parent));
+        garbage_collector.collect(&mut world, &resources);
+
+        let deleted = reader
+            .read(&resources)
+            .filter_map(|ev| match ev {
+                GameEvent::Delete(e) if e != parent => Some(e),
+                _ => None,
+            })
+            .collect();
+        (world, [parent, child, grandchild], deleted)
+    }
+
+    #[test]
+    fn detach_keeps_the_children() {
+        let (world, [parent, child, grandchild], deleted) = delete_root(OrphanPolicy::Detach);
+
+        assert!(!world.contains(parent));
+        assert!(world.get::<HasParent>(child).is_err());
+        assert_eq!(world.get::<HasParent>(grandchild).unwrap().entity, child);
+        assert!(deleted.is_empty());
+    }
+
+    #[test]
+    fn despawn_children_deletes_the_subtree() {
+        let (world, [parent, child, grandchild], mut deleted) =
+            delete_root(OrphanPolicy::DespawnChildren);
+
+        assert!(!world.contains(parent));
+        assert!(!world.contains(child));
+        assert!(!world.contains(grandchild));
+        deleted.sort_by_key(|e| e.to_bits());
+        let mut expected = vec![child, grandchild];
+        expected.sort_by_key(|e| e.to_bits());
+        assert_eq!(deleted, expected);
+    }
+
+    #[test]
+    fn deleted_child_leaves_its_parent() {
+        let mut resources = Resources::default();
+        resources.insert(EventQueue::<TestEvent>::new());
+        let mut garbage_collector = GarbageCollector::<TestEvent>::new(&mut resources);
+
+        let mut world = hecs::World::new();
+        let parent = world.spawn((HasChildren { children: vec![] },));
+        let child = world.spawn((HasParent { entity: parent },));
+        world
+            .get_mut::<HasChildren>(parent)
+            .unwrap()
+            .children
+            .push(child);
+
+        resources
+            .fetch_mut::<EventQueue<TestEvent>>()
+            .unwrap()
+            .single_write(GameEvent::Delete(child));
+        garbage_collector.collect(&mut world, &resources);
+
+        assert!(world
+            .get::<HasChildren>(parent)
+            .unwrap()
+            .children
+            .is_empty());
+    }
 }
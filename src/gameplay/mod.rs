@@ -4,6 +4,9 @@ use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 pub mod delete;
+pub mod pool;
+pub mod state_machine;
+pub mod trigger;
 
 #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Action {
@@ -0,0 +1,142 @@
+//! Reuse entities instead of spawning and despawning them all the time (bullets, effects...).
+use crate::assets::json::Prefab;
+use crate::core::disable::{disable, enable, is_disabled};
+use crate::core::transform::Transform;
+use crate::resources::Resources;
+
+/// Pool of entities created from the same prefab. Entities that are not in use have the
+/// `Disabled` marker so they are ignored by the systems.
+pub struct Pool<P: Prefab> {
+    prefab: P,
+    free: Vec<hecs::Entity>,
+    /// Total number of entities created by the pool.
+    size: usize,
+}
+
+impl<P: Prefab> Pool<P> {
+    /// Create a pool and spawn `capacity` disabled entities.
+    pub fn new(prefab: P, capacity: usize, world: &mut hecs::World, resources: &Resources) -> Self {
+        let mut pool = Self {
+            prefab,
+            free: Vec::with_capacity(capacity),
+            size: 0,
+        };
+
+        for _ in 0..capacity {
+            let e = pool.spawn_disabled(world, resources);
+            pool.free.push(e);
+        }
+        pool
+    }
+
+    /// Get an entity from the pool and enable it at the given transform. If there is no free
+    /// entity, a new one is spawned.
+    pub fn checkout(
+        &mut self,
+        world: &mut hecs::World,
+        resources: &Resources,
+        transform: Transform,
+    ) -> hecs::Entity {
+        let e = match self.free.pop() {
+            Some(e) => e,
+            None => {
+                debug!("Pool is empty, spawning a new entity");
+                self.spawn_disabled(world, resources)
+            }
+        };
+
+        if let Ok(mut t) = world.get_mut::<Transform>(e) {
+            *t = transform;
+        } else if let Err(err) = world.insert_one(e, transform) {
+            error!("Cannot add Transform to pooled entity = {:?}", err);
+        }
+
+        // Will add the rigid body back at the new position.
+        enable(world, resources, e);
+        e
+    }
+
+    /// Give back an entity to the pool. It will be disabled and its rigid body suspended.
+    pub fn reclaim(&mut self, world: &mut hecs::World, resources: &Resources, e: hecs::Entity) {
+        if !world.contains(e) {
+            warn!("Cannot reclaim entity {:?}, it does not exist", e);
+            return;
+        }
+        if is_disabled(world, e) {
+            // Already in the pool.
+            return;
+        }
+
+        disable(world, resources, e);
+        self.free.push(e);
+    }
+
+    /// Number of entities ready to be checked out.
+    pub fn available(&self) -> usize {
+        self.free.len()
+    }
+
+    /// Number of entities created by the pool.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    fn spawn_disabled(&mut self, world: &mut hecs::World, resources: &Resources) -> hecs::Entity {
+        let e = self
+            .prefab
+            .spawn_with_transform(world, resources, Transform::default());
+        disable(world, resources, e);
+        self.size += 1;
+        e
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geom2::Vector2f;
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Bullet;
+
+    #[typetag::serde]
+    impl Prefab for Bullet {
+        fn spawn(&self, world: &mut hecs::World) -> hecs::Entity {
+            world.spawn((Transform::default(),))
+        }
+    }
+
+    #[test]
+    fn grow_and_reuse() {
+        let mut world = hecs::World::new();
+        let resources = Resources::default();
+        let mut pool = Pool::new(Bullet, 2, &mut world, &resources);
+        assert_eq!((pool.available(), pool.size()), (2, 2));
+
+        let first = pool.checkout(&mut world, &resources, Transform::default());
+        pool.checkout(&mut world, &resources, Transform::default());
+        let third = pool.checkout(&mut world, &resources, Transform::default());
+        assert_eq!((pool.available(), pool.size()), (0, 3));
+        assert!(!is_disabled(&world, third));
+
+        pool.reclaim(&mut world, &resources, first);
+        pool.reclaim(&mut world, &resources, first);
+        assert!(is_disabled(&world, first));
+        assert_eq!(pool.available(), 1);
+
+        let position = Vector2f::new(3.0, 4.0);
+        let reused = pool.checkout(
+            &mut world,
+            &resources,
+            Transform::from_translation(position),
+        );
+        assert_eq!(reused, first);
+        assert_eq!(pool.size(), 3);
+        assert!(!is_disabled(&world, reused));
+        assert_eq!(
+            world.get::<Transform>(reused).unwrap().translation,
+            position
+        );
+    }
+}
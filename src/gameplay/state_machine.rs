@@ -0,0 +1,135 @@
+//! Finite state machine for the behavior of an entity (enemy AI, player states...).
+//!
+//! Gameplay code fires triggers on the `StateMachine` component (e.g. when processing the game
+//! events) and the `StateMachineSystem` applies the transitions, calls the hooks and plays the
+//! animation associated to the new state.
+use crate::core::animation::AnimationController;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+/// Called with the entity when entering or exiting a state.
+pub type StateHook = fn(&mut hecs::World, hecs::Entity);
+
+/// Type that can be used as a state, usually a small enum.
+pub trait State: Clone + Eq + Hash + Debug + Send + Sync + 'static {}
+
+impl<T> State for T where T: Clone + Eq + Hash + Debug + Send + Sync + 'static {}
+
+pub struct StateMachine<S: State> {
+    current: S,
+    /// (from, trigger) -> to
+    transitions: HashMap<(S, String), S>,
+    /// Animation to play when entering a state.
+    animations: HashMap<S, String>,
+    on_enter: HashMap<S, StateHook>,
+    on_exit: HashMap<S, StateHook>,
+    /// Triggers fired since the last update.
+    pending: Vec<String>,
+}
+
+impl<S: State> StateMachine<S> {
+    pub fn new(initial: S) -> Self {
+        Self {
+            current: initial,
+            transitions: HashMap::new(),
+            animations: HashMap::new(),
+            on_enter: HashMap::new(),
+            on_exit: HashMap::new(),
+            pending: vec![],
+        }
+    }
+
+    pub fn with_transition(mut self, from: S, trigger: &str, to: S) -> Self {
+        self.transitions.insert((from, trigger.to_string()), to);
+        self
+    }
+
+    /// Play the animation of the `AnimationController` when entering the state.
+    pub fn with_animation(mut self, state: S, animation: &str) -> Self {
+        self.animations.insert(state, animation.to_string());
+        self
+    }
+
+    pub fn with_on_enter(mut self, state: S, hook: StateHook) -> Self {
+        self.on_enter.insert(state, hook);
+        self
+    }
+
+    pub fn with_on_exit(mut self, state: S, hook: StateHook) -> Self {
+        self.on_exit.insert(state, hook);
+        self
+    }
+
+    pub fn current(&self) -> &S {
+        &self.current
+    }
+
+    /// Fire a trigger. The transition is applied at the next update of the `StateMachineSystem`.
+    /// Triggers that do not match a transition from the current state are ignored.
+    pub fn fire(&mut self, trigger: &str) {
+        self.pending.push(trigger.to_string());
+    }
+
+    fn next_state(&self, trigger: &str) -> Option<S> {
+        self.transitions
+            .get(&(self.current.clone(), trigger.to_string()))
+            .cloned()
+    }
+}
+
+/// Apply the pending triggers of the `StateMachine<S>` components.
+pub struct StateMachineSystem<S> {
+    phantom: PhantomData<S>,
+}
+
+impl<S: State> Default for StateMachineSystem<S> {
+    fn default() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<S: State> StateMachineSystem<S> {
+    pub fn update(&mut self, world: &mut hecs::World) {
+        // Hooks need the world so they are called after the query.
+        let mut hooks = vec![];
+        for (e, (machine, mut controller)) in world
+            .query::<(&mut StateMachine<S>, Option<&mut AnimationController>)>()
+            .iter()
+        {
+            let pending = std::mem::take(&mut machine.pending);
+            for trigger in pending {
+                let next = match machine.next_state(&trigger) {
+                    Some(next) => next,
+                    None => continue,
+                };
+
+                debug!(
+                    "{:?} goes from {:?} to {:?} ({})",
+                    e, machine.current, next, trigger
+                );
+                if let Some(hook) = machine.on_exit.get(&machine.current) {
+                    hooks.push((e, *hook));
+                }
+                machine.current = next;
+                if let Some(hook) = machine.on_enter.get(&machine.current) {
+                    hooks.push((e, *hook));
+                }
+
+                if let (Some(animation), Some(controller)) = (
+                    machine.animations.get(&machine.current),
+                    controller.as_mut(),
+                ) {
+                    controller.play(animation);
+                }
+            }
+        }
+
+        for (e, hook) in hooks {
+            hook(world, e);
+        }
+    }
+}
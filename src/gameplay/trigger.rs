@@ -0,0 +1,179 @@
+//! Fire an event when something enters a sensor, then wait for a cooldown before firing again
+//! (spike traps, pressure plates...).
+use crate::core::physics::CollisionWorld;
+use crate::core::timer::{scaled_dt, Timer};
+use crate::event::{CustomGameEvent, EventQueue, EventReader, GameEvent};
+use crate::resources::Resources;
+use serde_derive::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Add this to an entity that has a sensor rigid body.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trigger {
+    /// Sent with the event so that the game knows which trigger fired.
+    pub event_tag: String,
+    /// Time to wait before the trigger can fire again.
+    pub cooldown: Timer,
+    /// If false, the trigger is waiting for the cooldown.
+    #[serde(default = "default_armed")]
+    pub armed: bool,
+}
+
+fn default_armed() -> bool {
+    true
+}
+
+impl Trigger {
+    pub fn new(event_tag: &str, cooldown: f32) -> Self {
+        Self {
+            event_tag: event_tag.to_string(),
+            cooldown: Timer::of_seconds(cooldown),
+            armed: true,
+        }
+    }
+}
+
+/// Data sent when a trigger fires. The custom game event needs to implement
+/// `From<TriggerFired>` to receive it as `GameEvent::GameEvent`.
+#[derive(Debug, Clone)]
+pub struct TriggerFired {
+    /// Entity with the `Trigger` component.
+    pub trigger: hecs::Entity,
+    /// Entity that entered the trigger.
+    pub other: hecs::Entity,
+    pub event_tag: String,
+}
+
+pub struct TriggerSystem<GE>
+where
+    GE: CustomGameEvent,
+{
+    reader: EventReader<GE>,
+}
+
+impl<GE> TriggerSystem<GE>
+where
+    GE: CustomGameEvent + From<TriggerFired>,
+{
+    pub fn new(resources: &mut Resources) -> Self {
+        Self {
+            reader: EventReader::new(resources),
+        }
+    }
+
+    pub fn update(&mut self, world: &mut hecs::World, resources: &Resources, dt: Duration) {
+        let dt = scaled_dt(resources, dt);
+        for (_, trigger) in world.query::<&mut Trigger>().iter() {
+            if !trigger.armed {
+                trigger.cooldown.tick(dt);
+                if trigger.cooldown.finished() {
+                    trigger.armed = true;
+                }
+            }
+        }
+
+        let mut fired = vec![];
+        {
+            let collision_world = resources.fetch::<CollisionWorld>().unwrap();
+            for ev in self.reader.read(resources) {
+                if let GameEvent::ProximityEvent(h1, h2) = ev {
+                    let (e1, e2) = match (
+                        collision_world.collider_entity(h1),
+                        collision_world.collider_entity(h2),
+                    ) {
+                        (Some(e1), Some(e2)) => (e1, e2),
+                        _ => continue,
+                    };
+
+                    // Any of the two entities can be the trigger.
+                    for (trigger_entity, other) in &[(e1, e2), (e2, e1)] {
+                        if let Ok(mut trigger) = world.get_mut::<Trigger>(*trigger_entity) {
+                            if !trigger.armed {
+                                continue;
+                            }
+                            trigger.armed = false;
+                            trigger.cooldown.reset();
+                            fired.push(TriggerFired {
+                                trigger: *trigger_entity,
+                                other: *other,
+                                event_tag: trigger.event_tag.clone(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        if !fired.is_empty() {
+            let mut events = fired
+                .drain(..)
+                .map(|ev| GameEvent::GameEvent(ev.into()))
+                .collect();
+            let mut chan = resources.fetch_mut::<EventQueue<GE>>().unwrap();
+            chan.drain_vec_write(&mut events);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::physics::RigidBodyComponent;
+    use crate::core::transform::Transform;
+    use rapier2d::geometry::ColliderHandle;
+
+    #[derive(Debug, Clone)]
+    struct TestEvent(String);
+    impl CustomGameEvent for TestEvent {}
+    impl From<TriggerFired> for TestEvent {
+        fn from(fired: TriggerFired) -> Self {
+            Self(fired.event_tag)
+        }
+    }
+
+    fn collider(physics: &CollisionWorld, rbc: &RigidBodyComponent) -> ColliderHandle {
+        let body = physics.rigid_bodies().get(rbc.handle.unwrap()).unwrap();
+        body.colliders()[0]
+    }
+
+    #[test]
+    fn reentry_during_cooldown_fires_once() {
+        let mut world = hecs::World::new();
+        let mut resources = Resources::default();
+        resources.insert(EventQueue::<TestEvent>::new());
+        let mut physics = CollisionWorld::default();
+        let trap = world.spawn((Trigger::new("spikes", 1.0),));
+        let player = world.spawn(());
+        let mut sensor = RigidBodyComponent::new_sensor_cuboid(1.0, 1.0);
+        physics.add_body_with_entity(&Transform::default(), &mut sensor, trap);
+        let mut body = RigidBodyComponent::new_dynamic_cuboid(1.0, 1.0);
+        physics.add_body_with_entity(&Transform::default(), &mut body, player);
+        let enter =
+            GameEvent::ProximityEvent(collider(&physics, &sensor), collider(&physics, &body));
+        resources.insert(physics);
+
+        let mut system = TriggerSystem::<TestEvent>::new(&mut resources);
+        let mut reader = EventReader::<TestEvent>::new(&mut resources);
+        let mut fired = |system: &mut TriggerSystem<TestEvent>, world: &mut hecs::World, enters| {
+            for _ in 0..enters {
+                resources
+                    .fetch_mut::<EventQueue<TestEvent>>()
+                    .unwrap()
+                    .single_write(enter.clone());
+            }
+            system.update(world, &resources, Duration::from_millis(500));
+            reader
+                .read(&resources)
+                .filter(|ev| matches!(ev, GameEvent::GameEvent(TestEvent(tag)) if tag == "spikes"))
+                .count()
+        };
+
+        assert_eq!(fired(&mut system, &mut world, 1), 1);
+        // Leaves and enters again while the trap is cooling down.
+        assert_eq!(fired(&mut system, &mut world, 2), 0);
+        // The cooldown finishes at the start of this update.
+        assert_eq!(fired(&mut system, &mut world, 0), 0);
+        assert!(world.get::<Trigger>(trap).unwrap().armed);
+        assert_eq!(fired(&mut system, &mut world, 1), 1);
+    }
+}
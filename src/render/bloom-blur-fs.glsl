@@ -0,0 +1,18 @@
+in vec2 v_uv;
+out vec4 frag;
+
+uniform sampler2D u_tex_1;
+// One texel in the direction of the blur.
+uniform vec2 u_direction;
+
+const float WEIGHTS[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+void main() {
+    vec3 color = texture(u_tex_1, v_uv).rgb * WEIGHTS[0];
+    for (int i = 1; i < 5; i++) {
+        vec2 offset = u_direction * float(i);
+        color += texture(u_tex_1, v_uv + offset).rgb * WEIGHTS[i];
+        color += texture(u_tex_1, v_uv - offset).rgb * WEIGHTS[i];
+    }
+    frag = vec4(color, 1.0);
+}
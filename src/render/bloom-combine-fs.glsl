@@ -0,0 +1,12 @@
+in vec2 v_uv;
+out vec4 frag;
+
+uniform sampler2D u_tex_1;
+uniform sampler2D u_tex_2;
+uniform float u_intensity;
+
+void main() {
+    vec4 scene = texture(u_tex_1, v_uv);
+    vec3 bloom = texture(u_tex_2, v_uv).rgb;
+    frag = vec4(scene.rgb + bloom * u_intensity, scene.a);
+}
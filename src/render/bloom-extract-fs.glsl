@@ -0,0 +1,11 @@
+in vec2 v_uv;
+out vec4 frag;
+
+uniform sampler2D u_tex_1;
+uniform float u_threshold;
+
+void main() {
+    vec3 color = texture(u_tex_1, v_uv).rgb;
+    float luminance = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    frag = luminance > u_threshold ? vec4(color, 1.0) : vec4(0.0, 0.0, 0.0, 1.0);
+}
@@ -0,0 +1,10 @@
+in vec2 position;
+in vec2 uv;
+in vec4 color;
+
+out vec2 v_uv;
+
+void main() {
+    v_uv = uv;
+    gl_Position = vec4(position, 0.0, 1.0);
+}
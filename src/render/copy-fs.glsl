@@ -0,0 +1,8 @@
+in vec2 v_uv;
+out vec4 frag;
+
+uniform sampler2D u_tex_1;
+
+void main() {
+    frag = texture(u_tex_1, v_uv);
+}
@@ -0,0 +1,26 @@
+in vec2 position;
+in vec2 uv;
+in vec4 color;
+
+out vec4 v_color;
+
+uniform mat4 u_projection;
+uniform mat4 u_view;
+uniform mat4 u_model;
+
+// Colors of the corners of a ColoredQuad (one per column), used instead of the vertex color
+// when u_use_corner_colors is 1.
+uniform mat4 u_corner_colors;
+uniform float u_use_corner_colors;
+
+void main() {
+    // The corner is found from the uv of the quad: bottom-left, top-left, top-right, bottom-right.
+    vec4 corner_color;
+    if (uv.x < 0.5) {
+        corner_color = uv.y < 0.5 ? u_corner_colors[0] : u_corner_colors[1];
+    } else {
+        corner_color = uv.y < 0.5 ? u_corner_colors[3] : u_corner_colors[2];
+    }
+    v_color = mix(color, corner_color, u_use_corner_colors);
+    gl_Position = u_projection * u_view *  u_model  * vec4(position, 0.0, 1.0);
+}
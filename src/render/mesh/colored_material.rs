@@ -0,0 +1,15 @@
+use super::{compile_shader, ShaderUniform};
+use crate::render::mesh::VertexSemantics;
+use crate::render::Context;
+use luminance::shader::ProgramError;
+use luminance_front::shader::Program;
+
+const COLORED_VS: &'static str = include_str!("colored-vs.glsl");
+const COLORED_FS: &'static str = include_str!("colored-fs.glsl");
+
+/// Shader that only uses the vertex colors.
+pub fn new_shader(
+    surface: &mut Context,
+) -> Result<Program<VertexSemantics, (), ShaderUniform>, ProgramError> {
+    compile_shader(surface, COLORED_VS, COLORED_FS)
+}
@@ -2,24 +2,30 @@ use crate::assets::shader::ShaderManager;
 use crate::assets::sprite::SpriteAsset;
 use crate::assets::{AssetManager, Handle};
 use crate::core::colors::RgbaColor;
+use crate::core::disable::Disabled;
 use crate::core::transform::Transform;
-use crate::geom2::Matrix4f;
+use crate::geom2::{Matrix4f, Vector2f};
 use crate::render::Context;
-use instant::Instant;
 use luminance::blending::{Blending, Equation, Factor};
 use luminance::context::GraphicsContext;
 use luminance::pipeline::{PipelineError, TextureBinding};
-use luminance::pixel::NormUnsigned;
+use luminance::pixel::{NormRGBA8UI, NormUnsigned};
 use luminance::render_state::RenderState;
-use luminance::shader::Uniform;
+use luminance::shader::{ProgramError, Uniform};
 use luminance::tess::Mode;
-use luminance::texture::Dim2;
+use luminance::texture::{Dim2, GenMipmaps, MagFilter, MinFilter, Sampler};
 use luminance_derive::{Semantics, UniformInterface, Vertex};
 use luminance_front::shader::Program;
+use luminance_front::texture::Texture;
 use luminance_front::{pipeline::Pipeline, shading_gate::ShadingGate, tess::Tess};
+use rapier2d::na::Point2;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
 
 use serde_derive::{Deserialize, Serialize};
 
+mod colored_material;
 mod sprite_material;
 
 // Vertex definition
@@ -64,19 +70,19 @@ pub struct Vertex {
 pub struct ShaderUniform {
     /// PROJECTION matrix in MVP
     #[uniform(unbound, name = "u_projection")]
-    projection: Uniform<[[f32; 4]; 4]>,
+    pub(crate) projection: Uniform<[[f32; 4]; 4]>,
     /// VIEW matrix in MVP
     #[uniform(unbound, name = "u_view")]
-    view: Uniform<[[f32; 4]; 4]>,
+    pub(crate) view: Uniform<[[f32; 4]; 4]>,
     /// MODEL matrix in MVP
     #[uniform(unbound, name = "u_model")]
-    model: Uniform<[[f32; 4]; 4]>,
+    pub(crate) model: Uniform<[[f32; 4]; 4]>,
     /// Texture for the sprite.
     #[uniform(unbound, name = "u_tex_1")]
-    tex_1: Uniform<TextureBinding<Dim2, NormUnsigned>>,
+    pub(crate) tex_1: Uniform<TextureBinding<Dim2, NormUnsigned>>,
     /// true if should blink.
     #[uniform(unbound, name = "u_time")]
-    time: Uniform<f32>,
+    pub(crate) time: Uniform<f32>,
     /// Sprite number in the spritesheet
     #[uniform(unbound, name = "u_sprite_nb")]
     sprite_number: Uniform<f32>,
@@ -86,6 +92,18 @@ pub struct ShaderUniform {
     /// Sprite number in the spritesheet
     #[uniform(unbound, name = "u_rows")]
     spritesheet_rows: Uniform<f32>,
+    /// Fragments with a lower alpha are discarded.
+    #[uniform(unbound, name = "u_alpha_cutoff")]
+    alpha_cutoff: Uniform<f32>,
+    /// Number of 90° counter-clockwise rotations of the sprite cell.
+    #[uniform(unbound, name = "u_rotation_steps")]
+    rotation_steps: Uniform<f32>,
+    /// Colors of the corners of a colored quad, one per column.
+    #[uniform(unbound, name = "u_corner_colors")]
+    corner_colors: Uniform<[[f32; 4]; 4]>,
+    /// 1 to use the corner colors instead of the vertex colors.
+    #[uniform(unbound, name = "u_use_corner_colors")]
+    use_corner_colors: Uniform<f32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -104,13 +122,108 @@ pub enum Material {
         columns: u32,
         /// Number of rows for spritesheet
         rows: u32,
+        /// If set, the sprite is drawn without blending and the pixels with a lower alpha are
+        /// discarded. Opaque sprites with hard edges then do not depend on the draw order.
+        #[serde(default)]
+        alpha_cutoff: Option<f32>,
+        /// Rotate the sprite counter-clockwise by 90° per step (0 to 3). Useful for tiles.
+        #[serde(default)]
+        rotation_steps: u8,
     },
+    /// Quad without texture. The colors of the corners are blended. Order of the corners is
+    /// bottom-left, top-left, top-right, bottom-right. The colors are sent as uniforms so
+    /// animating them does not create any GPU buffer.
+    ColoredQuad { colors: [RgbaColor; 4] },
+    /// Custom geometry of a single color. Indices describe triangles. Identical meshes share the
+    /// same GPU buffers.
+    CustomMesh {
+        vertices: Vec<[f32; 2]>,
+        indices: Vec<u32>,
+        color: RgbaColor,
+    },
+}
+
+/// Number of columns and rows of a spritesheet made of cells of the same size. Incomplete cells
+/// at the right or bottom of the texture are ignored.
+pub fn grid_dimensions(texture_w: u32, texture_h: u32, cell_w: u32, cell_h: u32) -> (u32, u32) {
+    if cell_w == 0 || cell_h == 0 {
+        return (1, 1);
+    }
+    ((texture_w / cell_w).max(1), (texture_h / cell_h).max(1))
 }
 
 impl Material {
+    /// Sprite material where the columns and rows are computed from the size of the texture.
+    /// Returns None if the texture is not loaded yet.
+    pub fn sprite_from_grid(
+        textures: &AssetManager<SpriteAsset>,
+        sprite_id: &str,
+        cell_w: u32,
+        cell_h: u32,
+        sprite_nb: u32,
+    ) -> Option<Self> {
+        let (w, h) = textures
+            .get(&Handle(sprite_id.to_string()))?
+            .execute(|sprite| sprite.dimensions())?;
+        let (columns, rows) = grid_dimensions(w, h, cell_w, cell_h);
+        Some(Material::Sprite {
+            sprite_id: sprite_id.to_string(),
+            sprite_nb,
+            columns,
+            rows,
+            alpha_cutoff: None,
+            rotation_steps: 0,
+        })
+    }
+
+    /// Vertices and indices of the materials that have their own geometry.
+    fn geometry(&self) -> Option<(Vec<Vertex>, Vec<u32>)> {
+        match self {
+            Material::CustomMesh {
+                vertices,
+                indices,
+                color,
+            } => {
+                let color = color.to_normalized();
+                let vertices = vertices
+                    .iter()
+                    .map(|position| Vertex {
+                        position: VertexPosition::new(*position),
+                        uv: TextureCoord::new([0.0, 0.0]),
+                        color: VertexColor::new(color),
+                    })
+                    .collect();
+                Some((vertices, indices.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Key of the geometry in the tess cache.
+    fn geometry_key(&self) -> Option<u64> {
+        let mut hasher = DefaultHasher::new();
+        match self {
+            Material::CustomMesh {
+                vertices,
+                indices,
+                color,
+            } => {
+                for v in vertices {
+                    v[0].to_bits().hash(&mut hasher);
+                    v[1].to_bits().hash(&mut hasher);
+                }
+                indices.hash(&mut hasher);
+                hash_color(color, &mut hasher);
+            }
+            _ => return None,
+        }
+        Some(hasher.finish())
+    }
+
     pub fn material_id(&self) -> u16 {
         match self {
             Material::Sprite { .. } => 1,
+            Material::ColoredQuad { .. } | Material::CustomMesh { .. } => 3,
             // Should probably have a different ID for different shaders...
             Material::Shader { .. } => 2,
         }
@@ -120,11 +233,75 @@ impl Material {
 /// Render meshes with materials.
 pub struct MeshRenderer {
     tess: Tess<Vertex, u32>,
-    /// used to send elapsed time to shader.
-    creation_time: Instant,
+    /// Elapsed time in seconds sent to the shaders. Comes from the `ElapsedTime` resource.
+    pub(crate) elapsed: f32,
 
     /// shader for sprites.
     sprite_shader: Program<VertexSemantics, (), ShaderUniform>,
+
+    /// Magenta checkerboard drawn instead of the textures that failed to load.
+    placeholder: Texture<Dim2, NormRGBA8UI>,
+
+    /// shader for the materials that only use the vertex colors.
+    colored_shader: Program<VertexSemantics, (), ShaderUniform>,
+
+    /// Tess of the materials that have their own vertices, by hash of the vertices.
+    meshes: HashMap<u64, Tess<Vertex, u32>>,
+
+    /// Comes from the `LayerVisibility` resource.
+    pub(crate) visibility: LayerVisibility,
+
+    /// Meshes to draw this frame as (sorting key, query order, entity). Kept between frames so
+    /// that the buffer is reused instead of allocated each frame; query results cannot be kept
+    /// because they borrow the world, so the components are fetched again when drawing.
+    render_list: Vec<(u64, usize, hecs::Entity)>,
+}
+
+/// Group of meshes. Layers are drawn in order (background first) and the depth sorts the meshes
+/// inside a layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum RenderLayer {
+    Background,
+    World,
+    Foreground,
+    /// World-space UI such as health bars above the entities.
+    UiWorld,
+}
+
+impl Default for RenderLayer {
+    fn default() -> Self {
+        RenderLayer::World
+    }
+}
+
+impl RenderLayer {
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Layers that are drawn. All layers are visible by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LayerVisibility {
+    hidden: u8,
+}
+
+impl LayerVisibility {
+    pub fn is_visible(&self, layer: RenderLayer) -> bool {
+        self.hidden & layer.bit() == 0
+    }
+
+    pub fn set_visible(&mut self, layer: RenderLayer, visible: bool) {
+        if visible {
+            self.hidden &= !layer.bit();
+        } else {
+            self.hidden |= layer.bit();
+        }
+    }
+
+    pub fn toggle(&mut self, layer: RenderLayer) {
+        self.hidden ^= layer.bit();
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -133,60 +310,183 @@ pub struct MeshRender {
     pub material: Material,
     /// Depth of the material. Larger depth will be renderer first.
     pub depth: u16,
+    #[serde(default)]
+    pub layer: RenderLayer,
 }
 
 impl MeshRender {
-    fn sorting_key(&self) -> u32 {
-        let high = (self.depth as u32) << 16;
-        let low = self.material.material_id() as u32;
-        high + low
+    fn sorting_key(&self) -> u64 {
+        let layer = (self.layer as u64) << 32;
+        let high = (self.depth as u64) << 16;
+        let low = self.material.material_id() as u64;
+        layer + high + low
     }
 }
 
-impl MeshRenderer {
-    pub fn new(surface: &mut Context) -> Self {
-        let color = RgbaColor::new(255, 0, 0, 255).to_normalized();
-
-        let (vertices, indices) = (
-            vec![
-                Vertex {
-                    position: VertexPosition::new([-1.0, -1.0]),
-                    uv: TextureCoord::new([0.0, 0.0]),
-                    color: VertexColor::new(color),
-                },
-                Vertex {
-                    position: VertexPosition::new([-1.0, 1.0]),
-                    uv: TextureCoord::new([0.0, 1.0]),
-                    color: VertexColor::new(color),
-                },
-                Vertex {
-                    position: VertexPosition::new([1.0, 1.0]),
-                    uv: TextureCoord::new([1.0, 1.0]),
-                    color: VertexColor::new(color),
-                },
-                Vertex {
-                    position: VertexPosition::new([1.0, -1.0]),
-                    uv: TextureCoord::new([1.0, 0.0]),
-                    color: VertexColor::new(color),
-                },
-            ],
-            vec![0, 1, 2, 0, 2, 3],
-        );
+/// World-space bounding box (min and max corners) of a mesh. Sprites and quads are the unit
+/// quad from (-1,-1) to (1,1) transformed by the `Transform`; custom meshes use their vertices.
+pub fn world_aabb(transform: &Transform, render: &MeshRender) -> (Vector2f, Vector2f) {
+    const QUAD_CORNERS: [[f32; 2]; 4] = [[-1.0, -1.0], [-1.0, 1.0], [1.0, 1.0], [1.0, -1.0]];
+    let local: &[[f32; 2]] = match render.material {
+        Material::CustomMesh { ref vertices, .. } if !vertices.is_empty() => vertices,
+        _ => &QUAD_CORNERS,
+    };
+
+    let model = transform.to_mat();
+    let mut min = Vector2f::new(f32::MAX, f32::MAX);
+    let mut max = Vector2f::new(f32::MIN, f32::MIN);
+    for v in local {
+        let p = model.transform_point(&Point2::new(v[0], v[1]));
+        min = min.inf(&p.coords);
+        max = max.sup(&p.coords);
+    }
+    (min, max)
+}
+
+/// Vertices of a quad from (-1,-1) to (1,1). Colors are bottom-left, top-left, top-right and
+/// bottom-right.
+pub(crate) fn colored_quad_vertices(colors: &[RgbaColor; 4]) -> Vec<Vertex> {
+    let corners = [
+        ([-1.0, -1.0], [0.0, 0.0]),
+        ([-1.0, 1.0], [0.0, 1.0]),
+        ([1.0, 1.0], [1.0, 1.0]),
+        ([1.0, -1.0], [1.0, 0.0]),
+    ];
+
+    corners
+        .iter()
+        .zip(colors.iter())
+        .map(|((position, uv), color)| Vertex {
+            position: VertexPosition::new(*position),
+            uv: TextureCoord::new(*uv),
+            color: VertexColor::new(color.to_normalized()),
+        })
+        .collect()
+}
+
+const QUAD_INDICES: [u32; 6] = [0, 1, 2, 0, 2, 3];
+
+/// Quad from (-1,-1) to (1,1). Used for the sprites and for fullscreen passes.
+pub(crate) fn new_quad_tess(surface: &mut Context) -> Tess<Vertex, u32> {
+    let color = RgbaColor::new(255, 0, 0, 255);
+    let vertices = colored_quad_vertices(&[color; 4]);
+
+    surface
+        .new_tess()
+        .set_mode(Mode::Triangle)
+        .set_indices(QUAD_INDICES.to_vec())
+        .set_vertices(vertices)
+        .build()
+        .unwrap()
+}
+
+fn hash_color<H: Hasher>(color: &RgbaColor, hasher: &mut H) {
+    color.r.to_bits().hash(hasher);
+    color.g.to_bits().hash(hasher);
+    color.b.to_bits().hash(hasher);
+    color.a.to_bits().hash(hasher);
+}
 
-        let tess = surface
-            .new_tess()
-            .set_mode(Mode::Triangle)
-            .set_indices(indices)
-            .set_vertices(vertices)
-            .build()
-            .unwrap();
+/// Compile a shader that uses the mesh vertices and uniforms. The error contains the compilation
+/// log.
+pub(crate) fn compile_shader(
+    surface: &mut Context,
+    vertex_shader: &str,
+    fragment_shader: &str,
+) -> Result<Program<VertexSemantics, (), ShaderUniform>, ProgramError> {
+    let built = surface
+        .new_shader_program::<VertexSemantics, (), ShaderUniform>()
+        .from_strings(vertex_shader, None, None, fragment_shader)?;
+    for warning in &built.warnings {
+        warn!("Shader warning: {:?}", warning);
+    }
+    Ok(built.ignore_warnings())
+}
+
+/// Size in pixels of the placeholder texture.
+const PLACEHOLDER_SIZE: u32 = 8;
+
+/// Magenta and black checkerboard.
+fn new_placeholder_texture(surface: &mut Context) -> Texture<Dim2, NormRGBA8UI> {
+    let mut texels = Vec::with_capacity((PLACEHOLDER_SIZE * PLACEHOLDER_SIZE * 4) as usize);
+    for y in 0..PLACEHOLDER_SIZE {
+        for x in 0..PLACEHOLDER_SIZE {
+            let texel = if (x / 2 + y / 2) % 2 == 0 {
+                [255, 0, 255, 255]
+            } else {
+                [0, 0, 0, 255]
+            };
+            texels.extend_from_slice(&texel);
+        }
+    }
+
+    let sampler = Sampler {
+        mag_filter: MagFilter::Nearest,
+        min_filter: MinFilter::Nearest,
+        ..Sampler::default()
+    };
+    let mut texture = Texture::new(surface, [PLACEHOLDER_SIZE, PLACEHOLDER_SIZE], 0, sampler)
+        .expect("Cannot create placeholder texture");
+    texture
+        .upload_raw(GenMipmaps::No, &texels)
+        .expect("Cannot upload placeholder texture");
+    texture
+}
 
-        Self {
+impl MeshRenderer {
+    pub fn new(surface: &mut Context) -> Result<Self, ProgramError> {
+        let tess = new_quad_tess(surface);
+
+        Ok(Self {
             tess,
-            creation_time: Instant::now(),
-            sprite_shader: sprite_material::new_shader(surface),
+            elapsed: 0.0,
+            sprite_shader: sprite_material::new_shader(surface)?,
+            placeholder: new_placeholder_texture(surface),
+            colored_shader: colored_material::new_shader(surface)?,
+            meshes: HashMap::new(),
+            visibility: LayerVisibility::default(),
+            render_list: vec![],
+        })
+    }
+
+    /// Create the GPU buffers of the materials that have their own vertices. Buffers that are
+    /// not used anymore are released.
+    pub fn prepare(&mut self, surface: &mut Context, world: &hecs::World) {
+        let mut used = HashSet::new();
+        for (_, render) in world.query::<&MeshRender>().without::<Disabled>().iter() {
+            let key = match render.material.geometry_key() {
+                Some(key) => key,
+                None => continue,
+            };
+            used.insert(key);
+            if self.meshes.contains_key(&key) {
+                continue;
+            }
+
+            if let Some((vertices, indices)) = render.material.geometry() {
+                if indices.is_empty() || indices.iter().any(|&i| i as usize >= vertices.len()) {
+                    error!("Invalid indices for mesh material {:?}", render.material);
+                    continue;
+                }
+
+                match surface
+                    .new_tess()
+                    .set_mode(Mode::Triangle)
+                    .set_indices(indices)
+                    .set_vertices(vertices)
+                    .build()
+                {
+                    Ok(tess) => {
+                        self.meshes.insert(key, tess);
+                    }
+                    Err(e) => error!("Cannot create tess for mesh material = {:?}", e),
+                }
+            }
         }
+
+        self.meshes.retain(|key, _| used.contains(key));
     }
+
     pub fn render(
         &mut self,
         pipeline: &Pipeline,
@@ -213,20 +513,39 @@ impl MeshRenderer {
                     dst: Factor::Zero,
                 },
             );
-        let elapsed = self.creation_time.elapsed().as_secs_f32();
+        let cutout_st = RenderState::default()
+            .set_depth_test(None)
+            .set_blending(None);
+        let elapsed = self.elapsed;
 
-        let mut query = world.query::<(&Transform, &MeshRender)>();
-        let mut to_render = query
-            .iter()
-            .filter(|(_, (_, r))| r.enabled)
-            .collect::<Vec<_>>();
-        to_render.sort_by(|(_, (_, a)), (_, (_, b))| a.sorting_key().cmp(&b.sorting_key()));
+        self.render_list.clear();
+        {
+            let mut query = world
+                .query::<(&Transform, &MeshRender)>()
+                .without::<Disabled>();
+            let visibility = self.visibility;
+            self.render_list.extend(
+                query
+                    .iter()
+                    .filter(|(_, (_, r))| r.enabled && visibility.is_visible(r.layer))
+                    .enumerate()
+                    .map(|(i, (e, (_, r)))| (r.sorting_key(), i, e)),
+            );
+        }
+        // The query order breaks the ties so the unstable sort (which does not allocate) gives
+        // the same result as a stable sort.
+        self.render_list.sort_unstable();
 
         //[[f32; 4]; 4]
         let view: [[f32; 4]; 4] = (*view).into();
         let proj_matrix: [[f32; 4]; 4] = (*proj_matrix).into();
 
-        for (_, (t, render)) in to_render {
+        for i in 0..self.render_list.len() {
+            let e = self.render_list[i].2;
+            let (t, render) = match (world.get::<Transform>(e), world.get::<MeshRender>(e)) {
+                (Ok(t), Ok(render)) => (t, render),
+                _ => continue,
+            };
             let model: [[f32; 4]; 4] = t.to_model().into();
             let quad = &self.tess;
 
@@ -237,7 +556,21 @@ impl MeshRenderer {
                 } => {
                     let handle = Handle((vertex_shader_id.clone(), fragment_shader_id.clone()));
                     if let Some(shader) = shader_manager.get_mut(&handle) {
-                        if let Some(ret) = shader.execute_mut(|shader_asset| {
+                        // Shaders that do not compile are drawn as a red quad so that the game
+                        // keeps running until the shader is fixed.
+                        if shader.is_error() {
+                            shd_gate.shade(
+                                &mut self.colored_shader,
+                                |mut iface, uni, mut rdr_gate| {
+                                    iface.set(&uni.projection, proj_matrix);
+                                    iface.set(&uni.view, view);
+                                    iface.set(&uni.model, model);
+                                    iface.set(&uni.use_corner_colors, 0.0);
+                                    rdr_gate
+                                        .render(&render_st, |mut tess_gate| tess_gate.render(quad))
+                                },
+                            )?;
+                        } else if let Some(ret) = shader.execute_mut(|shader_asset| {
                             if let Some(ref mut shader) = shader_asset.shader {
                                 shd_gate.shade(shader, |mut iface, uni, mut rdr_gate| {
                                     iface.set(&uni.time, elapsed);
@@ -262,46 +595,153 @@ impl MeshRenderer {
                     sprite_nb,
                     columns,
                     rows,
+                    alpha_cutoff,
+                    rotation_steps,
                 } => {
+                    let handle = Handle(sprite_id.clone());
+                    let tex = textures.get_mut(&handle);
+                    if tex.is_none() {
+                        debug!("Texture is not loaded {}", sprite_id);
+                        textures.load(sprite_id.clone());
+                        continue;
+                    }
+                    let tex = tex.unwrap();
+
                     let shader = &mut self.sprite_shader;
+                    let placeholder = &mut self.placeholder;
+                    let sprite_st = if alpha_cutoff.is_some() {
+                        &cutout_st
+                    } else {
+                        &render_st
+                    };
                     shd_gate.shade(shader, |mut iface, uni, mut rdr_gate| {
                         iface.set(&uni.projection, proj_matrix);
                         iface.set(&uni.view, view);
                         iface.set(&uni.model, model);
+                        iface.set(&uni.alpha_cutoff, alpha_cutoff.unwrap_or(0.0));
+                        iface.set(&uni.rotation_steps, (rotation_steps % 4) as f32);
+
+                        // Textures that cannot be loaded are replaced by the placeholder so that
+                        // the broken sprite is visible. Textures that are still loading are
+                        // not drawn.
+                        if tex.is_error() {
+                            iface.set(&uni.sprite_number, 0.0);
+                            iface.set(&uni.spritesheet_columns, 1.0);
+                            iface.set(&uni.spritesheet_rows, 1.0);
+                            let bound_tex = pipeline.bind_texture(placeholder)?;
+                            iface.set(&uni.tex_1, bound_tex.binding());
+                            return rdr_gate
+                                .render(sprite_st, |mut tess_gate| tess_gate.render(quad));
+                        }
+
                         iface.set(&uni.sprite_number, sprite_nb as f32);
                         iface.set(&uni.spritesheet_columns, columns as f32);
                         iface.set(&uni.spritesheet_rows, rows as f32);
-                        if let Some(tex) = textures.get_mut(&Handle(sprite_id.clone())) {
-                            let mut res = Ok(());
-                            tex.execute_mut(|asset| {
-                                if let Some(tex) = asset.texture() {
-                                    let bound_tex = pipeline.bind_texture(tex);
-                                    match bound_tex {
-                                        Ok(bound_tex) => {
-                                            iface.set(&uni.tex_1, bound_tex.binding());
-                                            res = rdr_gate.render(&render_st, |mut tess_gate| {
-                                                tess_gate.render(quad)
-                                            });
-                                        }
-                                        Err(e) => {
-                                            res = Err(e);
-                                        }
+                        let mut res = Ok(());
+                        tex.execute_mut(|asset| {
+                            if let Some(tex) = asset.texture() {
+                                let bound_tex = pipeline.bind_texture(tex);
+                                match bound_tex {
+                                    Ok(bound_tex) => {
+                                        iface.set(&uni.tex_1, bound_tex.binding());
+                                        res = rdr_gate.render(sprite_st, |mut tess_gate| {
+                                            tess_gate.render(quad)
+                                        });
+                                    }
+                                    Err(e) => {
+                                        res = Err(e);
                                     }
                                 }
-                            });
-
-                            res?;
-                        } else {
-                            debug!("Texture is not loaded {}", sprite_id);
-                            textures.load(sprite_id.clone());
-                        }
+                            }
+                        });
 
-                        Ok(())
+                        res
                     })?;
                 }
+                Material::ColoredQuad { colors } => {
+                    let corner_colors = [
+                        colors[0].to_normalized(),
+                        colors[1].to_normalized(),
+                        colors[2].to_normalized(),
+                        colors[3].to_normalized(),
+                    ];
+                    shd_gate.shade(&mut self.colored_shader, |mut iface, uni, mut rdr_gate| {
+                        iface.set(&uni.projection, proj_matrix);
+                        iface.set(&uni.view, view);
+                        iface.set(&uni.model, model);
+                        iface.set(&uni.corner_colors, corner_colors);
+                        iface.set(&uni.use_corner_colors, 1.0);
+                        rdr_gate.render(&render_st, |mut tess_gate| tess_gate.render(quad))
+                    })?;
+                }
+                Material::CustomMesh { .. } => {
+                    let tess = render
+                        .material
+                        .geometry_key()
+                        .and_then(|key| self.meshes.get(&key));
+                    if let Some(tess) = tess {
+                        shd_gate.shade(
+                            &mut self.colored_shader,
+                            |mut iface, uni, mut rdr_gate| {
+                                iface.set(&uni.projection, proj_matrix);
+                                iface.set(&uni.view, view);
+                                iface.set(&uni.model, model);
+                                iface.set(&uni.use_corner_colors, 0.0);
+                                rdr_gate.render(&render_st, |mut tess_gate| tess_gate.render(tess))
+                            },
+                        )?;
+                    }
+                }
             }
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mesh(material: Material) -> MeshRender {
+        MeshRender {
+            enabled: true,
+            material,
+            depth: 0,
+            layer: RenderLayer::default(),
+        }
+    }
+
+    #[test]
+    fn aabb_of_a_scaled_quad() {
+        let transform = Transform::new(Vector2f::new(2.0, 3.0), 0.0, Vector2f::new(1.0, 2.0));
+        let render = mesh(Material::ColoredQuad {
+            colors: [RgbaColor::default(); 4],
+        });
+        assert_eq!(
+            world_aabb(&transform, &render),
+            (Vector2f::new(1.0, 1.0), Vector2f::new(3.0, 5.0))
+        );
+    }
+
+    #[test]
+    fn aabb_of_a_custom_mesh() {
+        let transform = Transform::from_translation(Vector2f::new(1.0, 0.0));
+        let render = mesh(Material::CustomMesh {
+            vertices: vec![[0.0, 0.0], [2.0, 0.0], [0.0, 4.0]],
+            indices: vec![0, 1, 2],
+            color: RgbaColor::default(),
+        });
+        assert_eq!(
+            world_aabb(&transform, &render),
+            (Vector2f::new(1.0, 0.0), Vector2f::new(3.0, 4.0))
+        );
+    }
+
+    #[test]
+    fn grid_ignores_incomplete_cells() {
+        assert_eq!(grid_dimensions(100, 64, 32, 32), (3, 2));
+        assert_eq!(grid_dimensions(16, 16, 32, 32), (1, 1));
+        assert_eq!(grid_dimensions(16, 16, 0, 32), (1, 1));
+    }
+}
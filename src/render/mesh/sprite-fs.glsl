@@ -3,8 +3,12 @@ in vec4 v_color;
 out vec4 frag;
 
 uniform sampler2D tex_1;
+uniform float u_alpha_cutoff;
 
 void main() {
     vec4 color = texture(tex_1, v_uv);
+    if (color.a < u_alpha_cutoff) {
+        discard;
+    }
     frag = color;
 }
\ No newline at end of file
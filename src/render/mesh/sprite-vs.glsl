@@ -12,9 +12,14 @@ uniform mat4 u_model;
 uniform float u_columns;
 uniform float u_rows;
 uniform float u_sprite_nb;
+uniform float u_rotation_steps;
 
 void main() {
     vec2 uv = uv;
+    // Rotate the sprite counter-clockwise around the center of the cell.
+    for (int i = 0; i < int(u_rotation_steps); i++) {
+        uv = vec2(uv.y, 1.0 - uv.x);
+    }
     float uv_width = 1.0 / u_columns;
     float uv_height = 1.0 / u_rows;
 
@@ -1,25 +1,31 @@
 use crate::assets::shader::ShaderManager;
 use crate::assets::sprite::SpriteAsset;
 use crate::assets::AssetManager;
-use crate::core::camera::{ProjectionMatrix, VirtualDim};
-use crate::render::mesh::MeshRenderer;
+use crate::core::camera::{ProjectionMatrix, ViewportRect, VirtualDim};
+use crate::core::colors::RgbaColor;
+use crate::core::timer::ElapsedTime;
+use crate::render::mesh::{LayerVisibility, MeshRenderer};
 use crate::render::particle::ParticleSystem;
 use crate::render::path::PathRenderer;
+use crate::render::post_process::{PostProcessRenderer, PostProcessStack};
 //use crate::render::sprite::SpriteRenderer;
 use crate::core::window::WindowDim;
-use crate::event::CustomGameEvent;
 use crate::render::ui::{text, Gui, GuiContext, UiRenderer};
 use crate::resources::Resources;
+use anyhow::anyhow;
+use crate::geom2::Matrix4f;
 use glyph_brush::GlyphBrush;
+use luminance::backend::color_slot::ColorSlot;
 use luminance::context::GraphicsContext;
 use luminance::pipeline::{PipelineError, PipelineState, Render, Viewport};
-use luminance::texture::Dim2;
+use luminance::pixel::NormRGBA8UI;
+use luminance::texture::{Dim2, Sampler};
 use luminance_front::framebuffer::Framebuffer;
-use std::time::Duration;
 
 pub mod mesh;
 pub mod particle;
 pub mod path;
+pub mod post_process;
 //pub mod sprite;
 pub mod ui;
 
@@ -35,6 +41,25 @@ pub type Backend = luminance_webgl::webgl2::WebGL2;
 #[cfg(target_arch = "wasm32")]
 pub type Context = luminance_web_sys::WebSysWebGL2Surface;
 
+/// Colors used to clear the screen. Black by default.
+#[derive(Debug, Clone, Copy)]
+pub struct ClearColor {
+    /// Background of the game viewport.
+    pub color: RgbaColor,
+    /// Bars around the game viewport when the window does not have the aspect ratio of the
+    /// virtual dimensions.
+    pub letterbox: RgbaColor,
+}
+
+impl Default for ClearColor {
+    fn default() -> Self {
+        Self {
+            color: RgbaColor::new(0, 0, 0, 255),
+            letterbox: RgbaColor::new(0, 0, 0, 255),
+        }
+    }
+}
+
 pub struct Renderer {
     /// Render sprites on screen.
     //sprite_renderer: SpriteRenderer,
@@ -43,6 +68,7 @@ pub struct Renderer {
     particle_renderer: ParticleSystem,
     ui_renderer: UiRenderer,
     path_renderer: PathRenderer,
+    post_process: PostProcessRenderer,
 }
 
 impl Renderer {
@@ -54,13 +80,16 @@ impl Renderer {
         info!("GUI renderer");
         let ui_renderer = UiRenderer::new(surface, gui_context);
         let path_renderer = PathRenderer::new(surface);
-        let mesh_renderer = MeshRenderer::new(surface);
+        let mesh_renderer =
+            MeshRenderer::new(surface).expect("Cannot compile the built-in mesh shaders");
+        let post_process = PostProcessRenderer::new(surface);
         Self {
             //     sprite_renderer,
             mesh_renderer,
             particle_renderer,
             ui_renderer,
             path_renderer,
+            post_process,
         }
     }
 
@@ -82,90 +111,468 @@ impl Renderer {
         world: &hecs::World,
         resources: &Resources,
     ) -> Render<PipelineError> {
-        let projection_matrix = resources.fetch::<ProjectionMatrix>().unwrap().0.clone();
-        let view = crate::core::camera::get_view_matrix(world).unwrap();
-
-        let window_dim = resources.fetch::<WindowDim>().unwrap();
-        let virtual_dim = resources.fetch::<VirtualDim>().unwrap();
-        let aspect_ratio = virtual_dim.aspect();
-
-        let w = window_dim.width;
-        let h = window_dim.height;
-        let (viewport_w, viewport_h, x, y) = if w as f32 > (h as f32 * aspect_ratio).ceil() {
-            let (viewport_w, viewport_h) = ((h as f32 * aspect_ratio).ceil(), h as f32);
-            let y = 0u32;
-            let x = ((w as f32 - viewport_w) / 2.0).round() as u32;
-            (viewport_w, viewport_h, x, y)
-        } else {
-            let (viewport_w, viewport_h) = (w as f32, (w as f32 / aspect_ratio).ceil());
-            let y = ((h as f32 - viewport_h) / 2.0).round() as u32;
-            let x = 0u32;
-            (viewport_w, viewport_h, x, y)
+        self.render_frame(surface, back_buffer, world, resources)
+    }
+
+    /// Render the frame to a framebuffer of the size of the window. It is the back buffer
+    /// except for `capture`.
+    fn render_frame<CS>(
+        &mut self,
+        surface: &mut Context,
+        back_buffer: &mut Framebuffer<Dim2, CS, ()>,
+        world: &hecs::World,
+        resources: &Resources,
+    ) -> Render<PipelineError>
+    where
+        CS: ColorSlot<Backend, Dim2>,
+    {
+        let projection = *resources.fetch::<ProjectionMatrix>().unwrap();
+        let elapsed = resources
+            .fetch::<ElapsedTime>()
+            .map(|t| t.0.as_secs_f32())
+            .unwrap_or(0.0);
+        self.mesh_renderer.elapsed = elapsed;
+        self.mesh_renderer.visibility = resources
+            .fetch::<LayerVisibility>()
+            .map(|v| *v)
+            .unwrap_or_default();
+        self.mesh_renderer.prepare(surface, world);
+        self.particle_renderer.prepare(surface, world);
+        self.post_process.elapsed = elapsed;
+        let targets = crate::core::camera::get_render_targets(world);
+
+        let (viewport_w, viewport_h, x, y) = letterbox_viewport(resources);
+        let clear_color = resources
+            .fetch::<ClearColor>()
+            .map(|c| *c)
+            .unwrap_or_default();
+        let game_viewport = Viewport::Specific {
+            x,
+            y,
+            width: viewport_w as u32,
+            height: viewport_h as u32,
         };
 
-        //println!("w,h ({}, {})-> ({},{})", w, h, viewport_w, viewport_h);
+        // Clearing is not limited to the viewport so clear everything with the letterbox color
+        // then fill the game viewport with the clear color. The next passes must not clear the
+        // back buffer.
+        let path_renderer = &mut self.path_renderer;
+        let render = surface
+            .new_pipeline_gate()
+            .pipeline(
+                back_buffer,
+                &PipelineState::default()
+                    .set_clear_color(clear_color.letterbox.to_normalized())
+                    .enable_clear_color(true),
+                |_, _| Ok(()),
+            )
+            .assume();
+        if !render.is_ok() {
+            return render;
+        }
+        let render = surface
+            .new_pipeline_gate()
+            .pipeline(
+                back_buffer,
+                &PipelineState::default()
+                    .set_viewport(game_viewport)
+                    .enable_clear_color(false),
+                |_, mut shd_gate| path_renderer.render_background(&mut shd_gate),
+            )
+            .assume();
+        if !render.is_ok() {
+            return render;
+        }
 
         let mut textures = resources.fetch_mut::<AssetManager<SpriteAsset>>().unwrap();
         let mut shaders = resources.fetch_mut::<ShaderManager>().unwrap();
+
+        let (effects, bloom) = match resources.fetch::<PostProcessStack>() {
+            Some(stack) => (
+                self.post_process.active_effects(&stack, &mut *shaders),
+                stack.bloom,
+            ),
+            None => (vec![], None),
+        };
+        let post_process = !effects.is_empty() || bloom.is_some();
+        if post_process {
+            self.post_process
+                .resize(surface, [viewport_w as u32, viewport_h as u32]);
+        }
+
+        let scene_fb = if post_process {
+            self.post_process.scene_framebuffer()
+        } else {
+            None
+        };
+        if let Some(scene_fb) = scene_fb {
+            // Render to texture then apply the effects. The last effect will write to
+            // the screen.
+            let render = render_world(
+                surface,
+                scene_fb,
+                &mut self.mesh_renderer,
+                &mut self.particle_renderer,
+                &mut self.path_renderer,
+                &targets,
+                (0, 0, viewport_w, viewport_h),
+                &projection,
+                world,
+                &mut *shaders,
+                &mut *textures,
+                Some(clear_color.color),
+            );
+            if !render.is_ok() {
+                return render;
+            }
+
+            let render = self.post_process.apply(
+                surface,
+                back_buffer,
+                &effects,
+                &mut *shaders,
+                game_viewport,
+                bloom,
+            );
+            if !render.is_ok() {
+                return render;
+            }
+        } else {
+            let render = render_world(
+                surface,
+                back_buffer,
+                &mut self.mesh_renderer,
+                &mut self.particle_renderer,
+                &mut self.path_renderer,
+                &targets,
+                (x, y, viewport_w, viewport_h),
+                &projection,
+                world,
+                &mut *shaders,
+                &mut *textures,
+                None,
+            );
+            if !render.is_ok() {
+                return render;
+            }
+        }
+
+        // The UI does not depend on the cameras. See `UiAnchor`.
+        let (ui_w, ui_h, ui_x, ui_y) = ui::ui_viewport(resources);
+        let ui_viewport = Viewport::Specific {
+            x: ui_x,
+            y: ui_y,
+            width: ui_w as u32,
+            height: ui_h as u32,
+        };
+        let screen_projection = particle::screen_projection(ui_w, ui_h);
+        let ui_renderer = &mut self.ui_renderer;
+        let particle_renderer = &mut self.particle_renderer;
         surface
             .new_pipeline_gate()
             .pipeline(
                 back_buffer,
+                &PipelineState::default()
+                    .set_viewport(ui_viewport)
+                    .enable_clear_color(false),
+                |pipeline, mut shd_gate| {
+                    ui_renderer.render(&pipeline, &mut shd_gate)?;
+                    // Screen space particles are drawn over the UI.
+                    particle_renderer.render(
+                        &pipeline,
+                        &mut shd_gate,
+                        &screen_projection,
+                        &Matrix4f::identity(),
+                        world,
+                        &mut *textures,
+                        true,
+                    )
+                },
+            )
+            .assume()
+    }
+
+    /// Render the current frame to an image of the size of the window. The frame goes through
+    /// the same passes as `render` (letterbox, post-processing and UI) but ends up in an
+    /// offscreen framebuffer instead of the back buffer.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn capture(
+        &mut self,
+        surface: &mut Context,
+        world: &hecs::World,
+        resources: &Resources,
+    ) -> Result<image::RgbaImage, anyhow::Error> {
+        let (width, height) = {
+            let window_dim = resources.fetch::<WindowDim>().unwrap();
+            (window_dim.width, window_dim.height)
+        };
+        let mut framebuffer = surface.new_framebuffer::<Dim2, NormRGBA8UI, ()>(
+            [width, height],
+            0,
+            Sampler::default(),
+        )?;
+
+        self.render_frame(surface, &mut framebuffer, world, resources)
+            .into_result()
+            .map_err(|e| anyhow!("Cannot render the frame for capture = {:?}", e))?;
+
+        let texels = framebuffer.color_slot().get_raw_texels()?;
+        let image = image::RgbaImage::from_raw(width, height, texels)
+            .ok_or_else(|| anyhow!("Captured texels do not match the framebuffer size"))?;
+
+        // OpenGL origin is the bottom-left corner.
+        Ok(image::imageops::flip_vertical(&image))
+    }
+}
+
+/// Window for the tests that need an OpenGL context. These tests are ignored by default. Run them
+/// with `cargo test -- --ignored --test-threads=1` as GLFW cannot be used from several threads.
+#[cfg(all(test, not(target_arch = "wasm32")))]
+pub(crate) fn test_surface(width: u32, height: u32) -> Context {
+    let dim = luminance_windowing::WindowDim::Windowed { width, height };
+    luminance_glfw::GlfwSurface::new_gl33(
+        "snoozeng test",
+        luminance_windowing::WindowOpt::default().set_dim(dim),
+    )
+    .expect("Cannot create an OpenGL context")
+}
+
+fn letterbox_viewport(resources: &Resources) -> (f32, f32, u32, u32) {
+    let window_dim = resources.fetch::<WindowDim>().unwrap();
+    let virtual_dim = resources.fetch::<VirtualDim>().unwrap();
+    crate::core::camera::letterbox_viewport(&window_dim, &virtual_dim)
+}
+
+/// Render the world once per camera. If a clear color is given, only the first pass clears the
+/// framebuffer, otherwise it would erase what the other cameras have drawn.
+#[allow(clippy::too_many_arguments)]
+fn render_world<CS>(
+    surface: &mut Context,
+    framebuffer: &mut Framebuffer<Dim2, CS, ()>,
+    mesh_renderer: &mut MeshRenderer,
+    particle_renderer: &mut ParticleSystem,
+    path_renderer: &mut PathRenderer,
+    targets: &[(Matrix4f, ViewportRect)],
+    (x, y, viewport_w, viewport_h): (u32, u32, f32, f32),
+    projection: &ProjectionMatrix,
+    world: &hecs::World,
+    shaders: &mut ShaderManager,
+    textures: &mut AssetManager<SpriteAsset>,
+    clear_color: Option<RgbaColor>,
+) -> Render<PipelineError>
+where
+    CS: ColorSlot<Backend, Dim2>,
+{
+    let mut should_clear = clear_color.is_some();
+    let clear_color = clear_color
+        .unwrap_or_else(|| ClearColor::default().color)
+        .to_normalized();
+    for (view, viewport) in targets {
+        let (cam_x, cam_y, cam_w, cam_h) = viewport.to_pixels(x, y, viewport_w, viewport_h);
+        let projection_matrix = &projection.for_viewport(viewport);
+        let render = surface
+            .new_pipeline_gate()
+            .pipeline(
+                framebuffer,
                 &PipelineState::default()
                     .set_viewport(Viewport::Specific {
-                        x,
-                        y,
-                        width: viewport_w as u32,
-                        height: viewport_h as u32,
+                        x: cam_x,
+                        y: cam_y,
+                        width: cam_w,
+                        height: cam_h,
                     })
-                    .set_clear_color([0.0, 0.0, 0.0, 1.0]),
+                    .set_clear_color(clear_color)
+                    .enable_clear_color(should_clear),
                 |pipeline, mut shd_gate| {
-                    // self.sprite_renderer.render(
-                    //     &pipeline,
-                    //     &mut shd_gate,
-                    //     &projection_matrix,
-                    //     &view,
-                    //     &world,
-                    //     &mut *textures,
-                    // )?;
-
-                    self.mesh_renderer.render(
+                    mesh_renderer.render(
                         &pipeline,
                         &mut shd_gate,
-                        &projection_matrix,
-                        &view,
-                        &world,
-                        &mut *shaders,
-                        &mut *textures,
+                        projection_matrix,
+                        view,
+                        world,
+                        shaders,
+                        textures,
                     )?;
 
-                    self.particle_renderer.render(
+                    particle_renderer.render(
                         &pipeline,
                         &mut shd_gate,
-                        &projection_matrix,
-                        &view,
+                        projection_matrix,
+                        view,
                         world,
-                        &mut *textures,
+                        textures,
+                        false,
                     )?;
 
-                    self.ui_renderer.render(&pipeline, &mut shd_gate)?;
-                    self.path_renderer
-                        .render(&projection_matrix, &view, &mut shd_gate)
+                    path_renderer.render(projection_matrix, view, &mut shd_gate)
                 },
             )
-            .assume()
+            .assume();
+        should_clear = false;
+
+        if !render.is_ok() {
+            return render;
+        }
     }
 
-    pub fn update<GE>(
-        &mut self,
-        _surface: &mut Context,
-        world: &hecs::World,
-        dt: Duration,
-        resources: &Resources,
-    ) where
-        GE: CustomGameEvent,
-    {
-        // update particle systems.
-        self.particle_renderer.update::<GE>(world, dt, resources);
+    Ok(()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::shader::ShaderLoader;
+    use crate::assets::sprite::SpriteSyncLoader;
+    use crate::core::camera::Camera;
+    use crate::core::colors;
+    use crate::core::transform::Transform;
+    use crate::geom2::Vector2f;
+    use crate::render::mesh::{Material, MeshRender, RenderLayer};
+    use crate::render::post_process::Bloom;
+
+    fn resources(window_dim: WindowDim, virtual_dim: VirtualDim) -> Resources {
+        let mut resources = Resources::default();
+        resources.insert(ProjectionMatrix::new(
+            virtual_dim.0 as f32,
+            virtual_dim.1 as f32,
+        ));
+        resources.insert(window_dim);
+        resources.insert(virtual_dim);
+        let sprites: AssetManager<SpriteAsset> =
+            AssetManager::from_loader(Box::new(SpriteSyncLoader::new(std::env::temp_dir())));
+        let shaders: ShaderManager =
+            AssetManager::from_loader(Box::new(ShaderLoader::new(std::env::temp_dir())));
+        resources.insert(sprites);
+        resources.insert(shaders);
+        resources
+    }
+
+    #[test]
+    #[ignore] // needs an OpenGL context
+    fn capture_the_clear_colors() {
+        let window_dim = WindowDim::new(200, 100);
+        let mut surface = test_surface(window_dim.width, window_dim.height);
+        let mut renderer = Renderer::new(&mut surface, &GuiContext::new(window_dim));
+        let mut resources = resources(window_dim, VirtualDim(100, 100));
+        resources.insert(ClearColor {
+            color: colors::RED,
+            letterbox: colors::BLUE,
+        });
+        let mut world = hecs::World::new();
+        world.spawn((Camera::new(),));
+
+        let image = renderer.capture(&mut surface, &world, &resources).unwrap();
+
+        assert_eq!((200, 100), image.dimensions());
+        // The game viewport is in the middle of the window, between two letterbox bars.
+        assert_eq!(image::Rgba([255, 0, 0, 255]), *image.get_pixel(100, 50));
+        assert_eq!(image::Rgba([0, 0, 255, 255]), *image.get_pixel(10, 50));
+        assert_eq!(image::Rgba([0, 0, 255, 255]), *image.get_pixel(190, 50));
+    }
+
+    #[test]
+    #[ignore] // needs an OpenGL context
+    fn clear_color_changes_at_runtime() {
+        let window_dim = WindowDim::new(100, 100);
+        let mut surface = test_surface(window_dim.width, window_dim.height);
+        let mut renderer = Renderer::new(&mut surface, &GuiContext::new(window_dim));
+        let mut resources = resources(window_dim, VirtualDim(100, 100));
+        let mut world = hecs::World::new();
+        world.spawn((Camera::new(),));
+
+        // Black without the resource.
+        let image = renderer.capture(&mut surface, &world, &resources).unwrap();
+        assert_eq!(image::Rgba([0, 0, 0, 255]), *image.get_pixel(50, 50));
+
+        resources.insert(ClearColor {
+            color: colors::GREEN,
+            ..ClearColor::default()
+        });
+        let image = renderer.capture(&mut surface, &world, &resources).unwrap();
+        assert_eq!(image::Rgba([0, 255, 0, 255]), *image.get_pixel(50, 50));
+    }
+
+    #[test]
+    #[ignore] // needs an OpenGL context
+    fn colored_quad_corners() {
+        let window_dim = WindowDim::new(100, 100);
+        let mut surface = test_surface(window_dim.width, window_dim.height);
+        let mut renderer = Renderer::new(&mut surface, &GuiContext::new(window_dim));
+        let resources = resources(window_dim, VirtualDim(100, 100));
+        let mut world = hecs::World::new();
+        world.spawn((Camera::new(),));
+        // Covers the whole screen.
+        world.spawn((
+            Transform::new(Vector2f::new(50.0, 50.0), 0.0, Vector2f::new(50.0, 50.0)),
+            MeshRender {
+                enabled: true,
+                material: Material::ColoredQuad {
+                    colors: [
+                        colors::RED,
+                        colors::GREEN,
+                        colors::BLUE,
+                        RgbaColor::new(255, 255, 255, 255),
+                    ],
+                },
+                depth: 0,
+                layer: RenderLayer::default(),
+            },
+        ));
+
+        let image = renderer.capture(&mut surface, &world, &resources).unwrap();
+
+        // Bottom-left, top-left, top-right then bottom-right. The first row of the image is the
+        // top of the screen.
+        let close_to = |x, y, expected: [u8; 3]| {
+            let pixel = image.get_pixel(x, y);
+            (0..3).all(|i| (pixel[i] as i32 - expected[i] as i32).abs() < 10)
+        };
+        assert!(close_to(0, 99, [255, 0, 0]));
+        assert!(close_to(0, 0, [0, 255, 0]));
+        assert!(close_to(99, 0, [0, 0, 255]));
+        assert!(close_to(99, 99, [255, 255, 255]));
+    }
+
+    #[test]
+    #[ignore] // needs an OpenGL context
+    fn bloom_brightens_the_neighbors() {
+        let window_dim = WindowDim::new(100, 100);
+        let mut surface = test_surface(window_dim.width, window_dim.height);
+        let mut renderer = Renderer::new(&mut surface, &GuiContext::new(window_dim));
+        let mut resources = resources(window_dim, VirtualDim(100, 100));
+        let mut world = hecs::World::new();
+        world.spawn((Camera::new(),));
+        // White square from 40 to 60 on a black background.
+        let white = RgbaColor::new(255, 255, 255, 255);
+        world.spawn((
+            Transform::new(Vector2f::new(50.0, 50.0), 0.0, Vector2f::new(10.0, 10.0)),
+            MeshRender {
+                enabled: true,
+                material: Material::ColoredQuad {
+                    colors: [white, white, white, white],
+                },
+                depth: 0,
+                layer: RenderLayer::default(),
+            },
+        ));
+        let brightness = |image: &image::RgbaImage| {
+            let pixel = image.get_pixel(63, 50);
+            (0..3).map(|i| pixel[i] as u32).sum::<u32>()
+        };
+
+        let image = renderer.capture(&mut surface, &world, &resources).unwrap();
+        let without_bloom = brightness(&image);
+
+        resources.insert(PostProcessStack {
+            bloom: Some(Bloom {
+                threshold: 0.5,
+                intensity: 1.0,
+            }),
+            ..PostProcessStack::default()
+        });
+        let image = renderer.capture(&mut surface, &world, &resources).unwrap();
+        let with_bloom = brightness(&image);
+
+        assert!(with_bloom > without_bloom);
     }
 }
@@ -4,8 +4,14 @@ in vec4 v_color;
 out vec4 frag;
 
 uniform sampler2D tex;
+// 0: multiply by the color, 1: only use the alpha of the color.
+uniform int color_mode;
 
 void main() {
     vec4 tex_color = texture(tex, v_uv);
-    frag = tex_color * v_color;
+    if (color_mode == 1) {
+        frag = vec4(tex_color.rgb, tex_color.a * v_color.a);
+    } else {
+        frag = tex_color * v_color;
+    }
 }
\ No newline at end of file
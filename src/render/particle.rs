@@ -2,6 +2,10 @@ use crate::assets::sprite::SpriteAsset;
 use crate::assets::{AssetManager, Handle};
 use crate::core::colors::RgbaColor;
 use crate::core::curve::Curve;
+use crate::core::disable::Disabled;
+use crate::core::physics::GlobalForce;
+use crate::core::random::{RandomGenerator, Seed};
+use crate::core::timer::scaled_dt;
 use crate::core::transform::Transform;
 use crate::event::{CustomGameEvent, EventQueue, GameEvent};
 use crate::resources::Resources;
@@ -17,12 +21,14 @@ use crate::core::colors;
 use crate::geom2::{Matrix4f, Vector2f};
 use luminance::tess::Mode;
 use luminance::texture::Dim2;
-use luminance_derive::UniformInterface;
+use luminance_derive::{Semantics, UniformInterface, Vertex};
 use luminance_front::tess::Tess;
 use luminance_front::{pipeline::Pipeline, shader::Program, shading_gate::ShadingGate};
-use rand::Rng;
-use rapier2d::na::{Rotation2, Vector3};
+use rand::prelude::StdRng;
+use rand::{Rng, SeedableRng};
+use rapier2d::na::Rotation2;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::Path;
 use std::time::Duration;
 
@@ -30,12 +36,35 @@ use std::time::Duration;
 pub enum ParticleScale {
     Constant(Vector2f),
     Random(Vector2f, Vector2f),
+    /// Pick one of the scales. The first element is the weight of the scale. Weights do not need
+    /// to sum to 1.
+    Choice(Vec<(f32, Vector2f)>),
+}
+
+/// Pick an element at random according to its weight. Negative weights are ignored.
+fn weighted_choice<'a, T, R: Rng>(choices: &'a [(f32, T)], rng: &mut R) -> Option<&'a T> {
+    let total: f32 = choices.iter().map(|(w, _)| w.max(0.0)).sum();
+    if total <= 0.0 {
+        return choices.first().map(|(_, v)| v);
+    }
+
+    let mut sample = rng.gen_range(0.0, total);
+    for (weight, value) in choices {
+        let weight = weight.max(0.0);
+        if sample < weight {
+            return Some(value);
+        }
+        sample -= weight;
+    }
+    choices.last().map(|(_, v)| v)
 }
 
 #[derive(Debug, Clone, Default)]
 struct Particle {
-    life: u32,
-    initial_life: u32,
+    /// Seconds since the particle was emitted.
+    age: f32,
+    /// Seconds the particle lives.
+    lifetime: f32,
     position: Vector2f,
     velocity: Vector2f,
     colors: Curve<RgbaColor>,
@@ -48,7 +77,7 @@ struct Particle {
 impl Particle {
     fn respawn(
         &mut self,
-        life: u32,
+        lifetime: f32,
         origin: Vector2f,
         velocity: Vector2f,
         scale: Vector2f,
@@ -56,29 +85,39 @@ impl Particle {
         scale_over_lifetime: Option<Curve<f32>>,
         rotation: f32,
     ) {
-        self.life = life;
+        self.age = 0.0;
+        self.lifetime = lifetime;
         self.position = origin;
         self.scale = scale;
         self.velocity = velocity;
         self.damping = damping;
         self.scale_over_lifetime = scale_over_lifetime;
-        self.initial_life = life;
         self.rotation = rotation;
     }
 
     /// return true if the particle is still alive
     fn alive(&self) -> bool {
-        self.life > 0
+        // The ages are sums of frame durations, so the error must not keep a particle alive for
+        // one more frame.
+        self.age + LIFE_EPSILON < self.lifetime
     }
 
-    fn update(&mut self, dt: f32) {
-        self.velocity *= (1.0 - self.damping / 1000.0);
+    /// The damping is the fraction (in thousandths) of the velocity lost per frame at 60 fps.
+    /// It is applied as an exponential decay so that the trajectory does not depend on the
+    /// frame rate.
+    fn update(&mut self, dt: f32, acceleration: &Vector2f) {
+        let decay = (1.0 - self.damping / 1000.0).max(0.0);
+        self.velocity += acceleration * dt;
+        self.velocity *= decay.powf(dt * REFERENCE_FPS);
         self.position += self.velocity.clone() * dt;
-        self.life -= 1; // one frame.
+        self.age += dt;
     }
 
     fn t(&self) -> f32 {
-        1.0 - self.life as f32 / self.initial_life as f32
+        if self.lifetime <= 0.0 {
+            return 1.0;
+        }
+        (self.age / self.lifetime).min(1.0)
     }
 
     fn color(&self) -> RgbaColor {
@@ -163,6 +202,48 @@ pub enum ParticleShape {
     Texture(String),
 }
 
+/// How the color of the particle is applied to the texture (only for `ParticleShape::Texture`).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum ParticleColorMode {
+    /// Multiply the texture by the color. Use it to tint a grayscale texture.
+    Multiply,
+    /// Keep the texture colors and only use the alpha of the color.
+    AlphaOnly,
+}
+
+impl Default for ParticleColorMode {
+    fn default() -> Self {
+        ParticleColorMode::Multiply
+    }
+}
+
+/// Order in which the particles of an emitter are drawn. The last particles are drawn on top.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum ParticleSort {
+    /// Order of the pool, fastest.
+    None,
+    /// Oldest particles first so that the new ones are on top.
+    ByAge,
+    /// Farthest particles from the emitter first.
+    ByDistance,
+}
+
+impl Default for ParticleSort {
+    fn default() -> Self {
+        ParticleSort::None
+    }
+}
+
+impl ParticleColorMode {
+    /// Value of the `color_mode` uniform in the texture shader.
+    pub fn as_uniform(&self) -> i32 {
+        match self {
+            ParticleColorMode::Multiply => 0,
+            ParticleColorMode::AlphaOnly => 1,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ParticleEmitter {
     pub enabled: bool,
@@ -172,6 +253,7 @@ pub struct ParticleEmitter {
     pub source: EmitterSource,
     pub shape: ParticleShape,
 
+    /// Thousandths of the velocity lost per frame at 60 fps.
     pub damping: f32,
 
     pub velocity_range: (f32, f32),
@@ -190,7 +272,21 @@ pub struct ParticleEmitter {
     /// Color of the particle
     pub colors: Curve<RgbaColor>,
 
-    /// How long does the particle (in frames)
+    /// If not empty, each particle will pick one of these color curves instead of `colors`. The
+    /// first element is the weight of the curve.
+    #[serde(default)]
+    pub color_choices: Vec<(f32, Curve<RgbaColor>)>,
+
+    /// How the colors are applied to the texture.
+    #[serde(default)]
+    pub color_mode: ParticleColorMode,
+
+    /// Draw order of the particles.
+    #[serde(default)]
+    pub sort_mode: ParticleSort,
+
+    /// How long does the particle (in frames at 60 fps). Particles live the same duration
+    /// whatever the frame rate.
     #[serde(default)]
     pub particle_life: u32,
 
@@ -201,8 +297,65 @@ pub struct ParticleEmitter {
     /// If true, only spawn stuff once
     #[serde(default)]
     pub burst: bool,
+
+    /// Number of particles spawned at once by a burst. 0 uses `particle_number`.
+    #[serde(default)]
+    pub burst_count: u32,
+
+    /// Seconds simulated before the first frame so that the emitter does not start empty. Capped
+    /// at `MAX_PREWARM` seconds. Ignored for bursts.
+    #[serde(default)]
+    pub prewarm: f32,
+
+    /// Set by `init_pool`. The prewarm is done at the next update, when the position of the
+    /// emitter is known.
+    #[serde(skip)]
+    prewarm_pending: bool,
+
+    /// Emit from the position of this entity instead of the emitter's entity (e.g. a moving
+    /// part of a character). Detached automatically when the entity is despawned.
+    #[serde(skip)]
+    pub attached_to: Option<hecs::Entity>,
+
+    /// If set, the emitter uses its own generator created from this seed in `init_pool`, so
+    /// that its particles are the same at each run (cutscenes...). Otherwise the
+    /// `RandomGenerator` resource is used.
+    #[serde(default)]
+    pub seed: Option<u64>,
+
+    /// Draw the particles over the UI, in pixels from the top-left corner of the UI area,
+    /// instead of in the world. The transform of the emitter is then in pixels too.
+    #[serde(default)]
+    pub screen_space: bool,
+
+    #[serde(skip)]
+    rng: Option<StdRng>,
+}
+
+/// Position the particles are emitted from. None if the entity the emitter is attached to does
+/// not exist anymore.
+fn emitter_origin(world: &World, t: &Transform, emitter: &ParticleEmitter) -> Option<Vector2f> {
+    match emitter.attached_to {
+        Some(target) => world.get::<Transform>(target).ok().map(|t| t.translation),
+        None => Some(t.translation),
+    }
+}
+
+/// Projection of the screen space emitters, from pixels (origin at the top-left corner) to
+/// normalized device coordinates.
+pub(crate) fn screen_projection(width: f32, height: f32) -> Matrix4f {
+    Matrix4f::new_orthographic(0.0, width, height, 0.0, -1.0, 1.0)
 }
 
+/// Maximum prewarm duration in seconds.
+pub const MAX_PREWARM: f32 = 10.0;
+/// Simulation step used for the prewarm.
+const PREWARM_STEP: f32 = 1.0 / 60.0;
+/// Frame rate used to convert the lifetime and damping of the emitters, given in frames.
+const REFERENCE_FPS: f32 = 60.0;
+/// Tolerance in seconds when checking if a particle is dead.
+const LIFE_EPSILON: f32 = 1e-4;
+
 impl Default for ParticleEmitter {
     fn default() -> Self {
         Self {
@@ -217,13 +370,20 @@ impl Default for ParticleEmitter {
             scale_over_lifetime: None,
             particle_number: 1.0,
             nb_accumulator: 0.0,
-            colors: Curve {
-                xs: vec![0.0],
-                ys: vec![colors::RED],
-            },
+            colors: Curve::new(vec![0.0], vec![colors::RED]),
+            color_choices: vec![],
+            color_mode: ParticleColorMode::Multiply,
+            sort_mode: ParticleSort::None,
             particle_life: 10,
             position_offset: Default::default(),
             burst: false,
+            burst_count: 0,
+            prewarm: 0.0,
+            prewarm_pending: false,
+            attached_to: None,
+            seed: None,
+            screen_space: false,
+            rng: None,
         }
     }
 }
@@ -246,21 +406,78 @@ impl ParticleEmitter {
 
     /// Necessary when getting the emitter from a file.
     pub fn init_pool(&mut self) {
-        let frame_needed = if self.burst {
-            1
+        let size = if self.burst && self.burst_count > 0 {
+            self.burst_count as usize
+        } else if self.burst {
+            self.particle_number.ceil() as usize
         } else {
-            self.particle_life as usize + 1
+            self.particle_number.ceil() as usize * (self.particle_life as usize + 1)
         };
-        self.particles = ParticlePool::of_size(self.particle_number.ceil() as usize * frame_needed);
+        self.particles = ParticlePool::of_size(size);
+        self.prewarm_pending = true;
+        self.rng = self
+            .seed
+            .map(|seed| StdRng::from_seed(Seed::from_u64(seed).0));
+    }
+
+    /// Particles in the order they should be drawn.
+    fn sorted_particles(&self, origin: &Vector2f) -> Vec<&Particle> {
+        let mut particles: Vec<_> = self.particles.particles.iter().collect();
+        match self.sort_mode {
+            ParticleSort::None => (),
+            ParticleSort::ByAge => particles.sort_by(|a, b| {
+                b.age
+                    .partial_cmp(&a.age)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            }),
+            ParticleSort::ByDistance => particles.sort_by(|a, b| {
+                let da = (a.position - origin).norm_squared();
+                let db = (b.position - origin).norm_squared();
+                db.partial_cmp(&da).unwrap_or(std::cmp::Ordering::Equal)
+            }),
+        }
+        particles
+    }
+
+    /// Simulate the emitter for `prewarm` seconds.
+    fn run_prewarm<R: Rng>(&mut self, position: &Vector2f, acceleration: &Vector2f, rng: &mut R) {
+        if self.burst || self.prewarm <= 0.0 || !self.enabled {
+            return;
+        }
+
+        let duration = if self.prewarm > MAX_PREWARM {
+            warn!(
+                "Prewarm of {}s is too long, will use {}s instead",
+                self.prewarm, MAX_PREWARM
+            );
+            MAX_PREWARM
+        } else {
+            self.prewarm
+        };
+
+        let steps = (duration / PREWARM_STEP).ceil() as usize;
+        for _ in 0..steps {
+            self.update(position, PREWARM_STEP, acceleration, rng);
+        }
     }
 
     /// Update the position and velocity of all particles. If a particle is dead, respawn it :)
+    /// The acceleration is applied to all the particles (wind...).
     /// Return true if should despawn the particle emitter.
-    fn update(&mut self, position: &Vector2f, dt: f32) -> bool {
+    fn update<R: Rng>(
+        &mut self,
+        position: &Vector2f,
+        dt: f32,
+        acceleration: &Vector2f,
+        rng: &mut R,
+    ) -> bool {
         if !self.particles.init {
             self.init_pool()
         }
-        let mut rng = rand::thread_rng();
+        if self.prewarm_pending {
+            self.prewarm_pending = false;
+            self.run_prewarm(position, acceleration, rng);
+        }
 
         // emit particles.
         trace!(
@@ -268,9 +485,12 @@ impl ParticleEmitter {
             self.particle_number,
             self.nb_accumulator
         );
-        self.nb_accumulator += self.particle_number;
-
-        let entire_nb = self.nb_accumulator.floor() as u32;
+        let entire_nb = if self.burst && self.burst_count > 0 {
+            self.burst_count
+        } else {
+            self.nb_accumulator += self.particle_number;
+            self.nb_accumulator.floor() as u32
+        };
         if entire_nb > 0 {
             if self.enabled {
                 for _ in 0..entire_nb {
@@ -289,11 +509,14 @@ impl ParticleEmitter {
                                 let y = rng.gen_range(low.y, high.y);
                                 Vector2f::new(x, y)
                             }
+                            ParticleScale::Choice(choices) => weighted_choice(choices, rng)
+                                .cloned()
+                                .unwrap_or_else(|| Vector2f::new(1.0, 1.0)),
                         };
 
                         particle.respawn(
-                            self.particle_life,
-                            self.source.spawn_position(position, &mut rng)
+                            self.particle_life as f32 / REFERENCE_FPS,
+                            self.source.spawn_position(position, rng)
                                 + self.position_offset.clone(),
                             rotation * (Vector2f::new(speed, 0.0)),
                             scale.clone(),
@@ -301,7 +524,9 @@ impl ParticleEmitter {
                             self.scale_over_lifetime.clone(),
                             angle,
                         );
-                        particle.colors = self.colors.clone();
+                        particle.colors = weighted_choice(&self.color_choices, rng)
+                            .unwrap_or(&self.colors)
+                            .clone();
                         trace!("{:?}", particle);
                     }
                 }
@@ -312,7 +537,7 @@ impl ParticleEmitter {
         // update existing particles.
         for (idx, p) in self.particles.particles.iter_mut().enumerate() {
             if p.alive() {
-                p.update(dt);
+                p.update(dt, acceleration);
             } else {
                 if !self.particles.free.contains(&idx) {
                     self.particles.free.push(idx);
@@ -336,9 +561,56 @@ const VS: &'static str = include_str!("particle-vs.glsl");
 const FS: &'static str = include_str!("particle-fs.glsl");
 const FS_TEXTURE: &'static str = include_str!("particle-texture-fs.glsl");
 
-pub fn new_shader(surface: &mut super::Context) -> Program<(), (), ParticleShaderInterface> {
+// Instance definition
+// -------------------
+// All the particles of an emitter are drawn in one call. Each instance is one particle.
+// -------------------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Semantics)]
+pub enum ParticleSemantics {
+    #[sem(name = "position", repr = "[f32; 2]", wrapper = "InstancePosition")]
+    Position,
+    #[sem(name = "scale", repr = "[f32; 2]", wrapper = "InstanceScale")]
+    Scale,
+    #[sem(name = "color", repr = "[f32; 4]", wrapper = "InstanceColor")]
+    Color,
+}
+
+#[allow(dead_code)]
+#[repr(C)]
+#[derive(Vertex, Copy, Debug, Clone)]
+#[vertex(sem = "ParticleSemantics", instanced = "true")]
+pub struct ParticleInstance {
+    position: InstancePosition,
+    scale: InstanceScale,
+    color: InstanceColor,
+}
+
+impl ParticleInstance {
+    /// Dead particles have a null scale so they do not produce any fragment.
+    fn from_particle(p: &Particle) -> Self {
+        let (scale, color) = if p.alive() {
+            let scale = p.scale();
+            ([scale.x, scale.y], p.color().to_normalized())
+        } else {
+            ([0.0, 0.0], [0.0; 4])
+        };
+
+        Self {
+            position: InstancePosition::new([p.position.x, p.position.y]),
+            scale: InstanceScale::new(scale),
+            color: InstanceColor::new(color),
+        }
+    }
+}
+
+type ParticleTess = Tess<(), (), ParticleInstance>;
+
+pub fn new_shader(
+    surface: &mut super::Context,
+) -> Program<ParticleSemantics, (), ParticleShaderInterface> {
     surface
-        .new_shader_program::<(), (), ParticleShaderInterface>()
+        .new_shader_program::<ParticleSemantics, (), ParticleShaderInterface>()
         .from_strings(VS, None, None, FS)
         .expect("Program creation")
         .ignore_warnings()
@@ -346,9 +618,9 @@ pub fn new_shader(surface: &mut super::Context) -> Program<(), (), ParticleShade
 
 pub fn new_texture_shader(
     surface: &mut super::Context,
-) -> Program<(), (), TextureParticleShaderInterface> {
+) -> Program<ParticleSemantics, (), TextureParticleShaderInterface> {
     surface
-        .new_shader_program::<(), (), TextureParticleShaderInterface>()
+        .new_shader_program::<ParticleSemantics, (), TextureParticleShaderInterface>()
         .from_strings(VS, None, None, FS_TEXTURE)
         .expect("Program creation")
         .ignore_warnings()
@@ -359,8 +631,6 @@ pub struct ParticleShaderInterface {
     pub projection: Uniform<[[f32; 4]; 4]>,
     #[uniform(unbound)]
     pub view: Uniform<[[f32; 4]; 4]>,
-    pub model: Uniform<[[f32; 4]; 4]>,
-    pub color: Uniform<[f32; 4]>,
 }
 
 #[derive(UniformInterface)]
@@ -368,48 +638,141 @@ pub struct TextureParticleShaderInterface {
     pub projection: Uniform<[[f32; 4]; 4]>,
     #[uniform(unbound)]
     pub view: Uniform<[[f32; 4]; 4]>,
-    pub model: Uniform<[[f32; 4]; 4]>,
-    pub color: Uniform<[f32; 4]>,
+    /// See `ParticleColorMode::as_uniform`
+    pub color_mode: Uniform<i32>,
 
     /// Texture for the sprite.
     tex: Uniform<TextureBinding<Dim2, NormUnsigned>>,
 }
 
+/// Move and emit the particles. It does not need the GPU so it also runs in a headless game.
+pub fn update_particles<GE>(world: &World, dt: Duration, resources: &Resources)
+where
+    GE: CustomGameEvent,
+{
+    // Time is frozen, particles should not move nor be emitted.
+    let dt = scaled_dt(resources, dt);
+    if dt.as_secs_f32() <= 0.0 {
+        return;
+    }
+
+    let mut chan = resources.fetch_mut::<EventQueue<GE>>().unwrap();
+    let mut remove_events = vec![];
+    // Use the seeded generator if there is one so that runs can be reproduced.
+    let mut random = resources.fetch_mut::<RandomGenerator>();
+    let wind = resources
+        .fetch::<GlobalForce>()
+        .map(|force| force.0)
+        .unwrap_or_else(Vector2f::zeros);
+    for (e, (t, emitter)) in world
+        .query::<(&Transform, &mut ParticleEmitter)>()
+        .without::<Disabled>()
+        .iter()
+    {
+        let origin = match emitter_origin(world, t, emitter) {
+            Some(origin) => origin,
+            None => {
+                debug!("Entity attached to the emitter {:?} is gone", e);
+                emitter.attached_to = None;
+                t.translation
+            }
+        };
+        // The UI is not affected by the wind.
+        let acceleration = if emitter.screen_space {
+            Vector2f::zeros()
+        } else {
+            wind
+        };
+        let dt = dt.as_secs_f32();
+        let alive = match (emitter.rng.take(), random.as_mut()) {
+            (Some(mut rng), _) => {
+                let alive = emitter.update(&origin, dt, &acceleration, &mut rng);
+                emitter.rng = Some(rng);
+                alive
+            }
+            (None, Some(random)) => emitter.update(&origin, dt, &acceleration, random.rng()),
+            (None, None) => emitter.update(&origin, dt, &acceleration, &mut rand::thread_rng()),
+        };
+        if !alive {
+            chan.single_write(GameEvent::Delete(e));
+        }
+    }
+    chan.drain_vec_write(&mut remove_events);
+}
+
 pub struct ParticleSystem {
-    tess: Tess<()>,
-    shader: Program<(), (), ParticleShaderInterface>,
-    texture_shader: Program<(), (), TextureParticleShaderInterface>,
+    /// Instance buffer of each emitter.
+    instances: HashMap<hecs::Entity, ParticleTess>,
+    shader: Program<ParticleSemantics, (), ParticleShaderInterface>,
+    texture_shader: Program<ParticleSemantics, (), TextureParticleShaderInterface>,
 }
 
 impl ParticleSystem {
     pub fn new(surface: &mut super::Context) -> Self {
-        let tess = surface
-            .new_tess()
-            .set_vertex_nb(4)
-            .set_mode(Mode::TriangleFan)
-            .build()
-            .expect("Tess creation");
         Self {
-            tess,
+            instances: HashMap::new(),
             shader: new_shader(surface),
             texture_shader: new_texture_shader(surface),
         }
     }
 
-    pub fn update<GE>(&mut self, world: &World, dt: Duration, resources: &Resources)
-    where
-        GE: CustomGameEvent,
-    {
-        let mut chan = resources.fetch_mut::<EventQueue<GE>>().unwrap();
-        let mut remove_events = vec![];
-        for (e, (t, emitter)) in world.query::<(&Transform, &mut ParticleEmitter)>().iter() {
-            if !emitter.update(&t.translation, dt.as_secs_f32()) {
-                chan.single_write(GameEvent::Delete(e));
+    /// Upload the particles of the emitters to their instance buffers. The buffers are reused
+    /// as long as the size of the particle pool does not change.
+    pub fn prepare(&mut self, surface: &mut super::Context, world: &World) {
+        let mut used = HashSet::new();
+        for (e, (t, emitter)) in world
+            .query::<(&Transform, &ParticleEmitter)>()
+            .without::<Disabled>()
+            .iter()
+        {
+            let origin = emitter_origin(world, t, emitter).unwrap_or(t.translation);
+            let instances: Vec<_> = emitter
+                .sorted_particles(&origin)
+                .into_iter()
+                .map(ParticleInstance::from_particle)
+                .collect();
+            if instances.is_empty() {
+                continue;
+            }
+            used.insert(e);
+
+            let updated = match self.instances.get_mut(&e) {
+                Some(tess) if tess.inst_nb() == instances.len() => match tess.instances_mut() {
+                    Ok(mut buffer) => {
+                        buffer.copy_from_slice(&instances);
+                        true
+                    }
+                    Err(err) => {
+                        error!("Cannot update particle instances = {:?}", err);
+                        false
+                    }
+                },
+                _ => false,
+            };
+
+            if !updated {
+                match surface
+                    .new_tess()
+                    .set_vertex_nb(4)
+                    .set_instances(instances)
+                    .set_mode(Mode::TriangleFan)
+                    .build()
+                {
+                    Ok(tess) => {
+                        self.instances.insert(e, tess);
+                    }
+                    Err(err) => error!("Cannot create particle instances = {:?}", err),
+                }
             }
         }
-        chan.drain_vec_write(&mut remove_events);
+
+        self.instances.retain(|e, _| used.contains(e));
     }
 
+    /// Draw all the particles of an emitter in one call. Emitters that use the same texture are
+    /// drawn together so that the texture is bound only once. Only the emitters with the given
+    /// `screen_space` flag are drawn, see `screen_projection` for the screen space ones.
+    #[allow(clippy::too_many_arguments)]
     pub fn render(
         &mut self,
         pipeline: &Pipeline,
@@ -418,8 +781,10 @@ impl ParticleSystem {
         view: &Matrix4f,
         world: &World,
         textures: &mut AssetManager<SpriteAsset>,
+        screen_space: bool,
     ) -> Result<(), PipelineError> {
-        let tess = &self.tess;
+        // Premultiplied alpha blending. Straight colors (see `RgbaColor::premultiplied`) are
+        // added to the background.
         let render_st = RenderState::default()
             .set_depth_test(None)
             .set_blending(Blending {
@@ -431,65 +796,68 @@ impl ParticleSystem {
         let view: [[f32; 4]; 4] = (*view).into();
         let projection: [[f32; 4]; 4] = (*projection).into();
 
-        for (_, emitter) in world.query::<&mut ParticleEmitter>().iter() {
+        let mut quads = vec![];
+        let mut by_texture: BTreeMap<&str, Vec<(&ParticleTess, i32)>> = BTreeMap::new();
+        let mut query = world.query::<&ParticleEmitter>().without::<Disabled>();
+        for (e, emitter) in query.iter() {
+            if emitter.screen_space != screen_space {
+                continue;
+            }
+            let tess = match self.instances.get(&e) {
+                Some(tess) => tess,
+                None => continue,
+            };
             match &emitter.shape {
-                ParticleShape::Quad => {
-                    shd_gate.shade(&mut self.shader, |mut iface, uni, mut rdr_gate| {
-                        iface.set(&uni.projection, projection.into());
-                        iface.set(&uni.view, view.into());
-
-                        for p in &emitter.particles.particles {
-                            if !p.alive() {
-                                continue;
-                            }
-
-                            iface.set(&uni.color, p.color().to_normalized());
-                            iface.set(&uni.model, to_model(&p.scale, p.rotation, &p.position));
-
-                            rdr_gate.render(&render_st, |mut tess_gate| tess_gate.render(tess))?;
-                        }
+                ParticleShape::Quad => quads.push(tess),
+                ParticleShape::Texture(id) => by_texture
+                    .entry(id.as_str())
+                    .or_default()
+                    .push((tess, emitter.color_mode.as_uniform())),
+            }
+        }
 
-                        Ok(())
-                    })?;
+        if !quads.is_empty() {
+            shd_gate.shade(&mut self.shader, |mut iface, uni, mut rdr_gate| {
+                iface.set(&uni.projection, projection);
+                iface.set(&uni.view, view);
+                for tess in quads {
+                    rdr_gate.render(&render_st, |mut tess_gate| tess_gate.render(tess))?;
                 }
-                ParticleShape::Texture(id) => {
-                    if let Some(tex) = textures.get_mut(&Handle(id.clone())) {
-                        let mut res = Ok(());
-                        let shader = &mut self.texture_shader;
-                        tex.execute_mut(|asset| {
-                            if let Some(tex) = asset.texture() {
-                                let bound_tex = pipeline.bind_texture(tex).unwrap();
-                                res = shd_gate.shade(shader, |mut iface, uni, mut rdr_gate| {
-                                    iface.set(&uni.projection, projection);
-                                    iface.set(&uni.view, view);
-                                    iface.set(&uni.tex, bound_tex.binding());
-                                    for p in &emitter.particles.particles {
-                                        if !p.alive() {
-                                            continue;
-                                        }
-
-                                        iface.set(&uni.color, p.color().to_normalized());
-                                        iface.set(
-                                            &uni.model,
-                                            to_model(&p.scale, p.rotation, &p.position),
-                                        );
-
-                                        rdr_gate.render(&render_st, |mut tess_gate| {
-                                            tess_gate.render(tess)
-                                        })?;
-                                    }
-
-                                    Ok(())
-                                });
+                Ok(())
+            })?;
+        }
+
+        for (id, emitters) in by_texture {
+            if let Some(tex) = textures.get_mut(&Handle(id.to_string())) {
+                let mut res = Ok(());
+                let shader = &mut self.texture_shader;
+                tex.execute_mut(|asset| {
+                    if let Some(tex) = asset.texture() {
+                        let bound_tex = match pipeline.bind_texture(tex) {
+                            Ok(bound_tex) => bound_tex,
+                            Err(e) => {
+                                res = Err(e);
+                                return;
+                            }
+                        };
+                        res = shd_gate.shade(shader, |mut iface, uni, mut rdr_gate| {
+                            iface.set(&uni.projection, projection);
+                            iface.set(&uni.view, view);
+                            iface.set(&uni.tex, bound_tex.binding());
+                            for (tess, color_mode) in &emitters {
+                                iface.set(&uni.color_mode, *color_mode);
+                                rdr_gate
+                                    .render(&render_st, |mut tess_gate| tess_gate.render(*tess))?;
                             }
+                            Ok(())
                         });
-
-                        res?;
-                    } else {
-                        debug!("Texture is not loaded {}", id);
-                        textures.load(id.clone());
                     }
-                }
+                });
+
+                res?;
+            } else {
+                debug!("Texture is not loaded {}", id);
+                textures.load(id.to_string());
             }
         }
 
@@ -497,9 +865,234 @@ impl ParticleSystem {
     }
 }
 
-fn to_model(scale: &Vector2f, rotation: f32, translation: &Vector2f) -> [[f32; 4]; 4] {
-    //let rot_mat = Matrix4f::new_rotation(Vector3::new(0.0, 0.0, rotation));
-    (Matrix4f::new_translation(&Vector3::new(translation.x, translation.y, 0.0))
-        * Matrix4f::new_nonuniform_scaling(&Vector3::new(scale.x, scale.y, 0.0)))
-    .into()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::timer::TimeScale;
+
+    #[derive(Debug, Clone)]
+    struct TestEvent;
+    impl CustomGameEvent for TestEvent {}
+
+    /// Position of the first particle after one real second, in two frames.
+    fn first_particle_after_one_second(time_scale: f32) -> Vector2f {
+        let mut resources = Resources::default();
+        resources.insert(EventQueue::<TestEvent>::new());
+        resources.insert(TimeScale(time_scale));
+        let mut world = World::new();
+        let e = world.spawn((
+            Transform::default(),
+            ParticleEmitter {
+                particle_life: 120,
+                seed: Some(7),
+                ..ParticleEmitter::default()
+            },
+        ));
+
+        for _ in 0..2 {
+            update_particles::<TestEvent>(&world, Duration::from_millis(500), &resources);
+        }
+        // The pool hands out the particles from the end.
+        let emitter = world.get::<ParticleEmitter>(e).unwrap();
+        emitter.particles.particles.last().unwrap().position
+    }
+
+    #[test]
+    fn half_time_scale_travels_half_as_far() {
+        let normal = first_particle_after_one_second(1.0);
+        let slow = first_particle_after_one_second(0.5);
+
+        assert!(normal.norm() > 0.0);
+        assert!((slow * 2.0 - normal).norm() < 1e-4);
+    }
+
+    fn particles_with_seed(seed: u64) -> Vec<Vector2f> {
+        let mut resources = Resources::default();
+        resources.insert(EventQueue::<TestEvent>::new());
+        resources.insert(RandomGenerator::new(Seed::from_u64(seed)));
+        let mut world = World::new();
+        let e = world.spawn((Transform::default(), ParticleEmitter::default()));
+
+        for _ in 0..5 {
+            update_particles::<TestEvent>(&world, Duration::from_millis(16), &resources);
+        }
+        let emitter = world.get::<ParticleEmitter>(e).unwrap();
+        emitter
+            .particles
+            .particles
+            .iter()
+            .map(|p| p.position)
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_same_particles() {
+        assert_eq!(particles_with_seed(3), particles_with_seed(3));
+        assert_ne!(particles_with_seed(3), particles_with_seed(4));
+    }
+
+    #[test]
+    fn color_mode_uniform() {
+        assert_eq!(
+            ParticleEmitter::default().color_mode,
+            ParticleColorMode::Multiply
+        );
+        assert_eq!(ParticleColorMode::Multiply.as_uniform(), 0);
+        // The texture shader only keeps the texture colors for this value.
+        assert_eq!(ParticleColorMode::AlphaOnly.as_uniform(), 1);
+        assert!(FS_TEXTURE.contains("color_mode == 1"));
+
+        let mode: ParticleColorMode = serde_json::from_str("\"AlphaOnly\"").unwrap();
+        assert_eq!(mode, ParticleColorMode::AlphaOnly);
+    }
+
+    #[test]
+    fn weighted_choice_follows_the_weights() {
+        let choices = [(1.0, 'a'), (3.0, 'b'), (-2.0, 'c'), (0.0, 'd')];
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut counts = HashMap::new();
+        let draws = 10_000;
+        for _ in 0..draws {
+            *counts
+                .entry(*weighted_choice(&choices, &mut rng).unwrap())
+                .or_insert(0) += 1;
+        }
+
+        let ratio = |c: char| *counts.get(&c).unwrap_or(&0) as f32 / draws as f32;
+        assert!((ratio('a') - 0.25).abs() < 0.02);
+        assert!((ratio('b') - 0.75).abs() < 0.02);
+        assert_eq!(ratio('c'), 0.0);
+        assert_eq!(ratio('d'), 0.0);
+
+        let none: [(f32, char); 0] = [];
+        assert_eq!(weighted_choice(&none, &mut rng), None);
+        assert_eq!(
+            weighted_choice(&[(0.0, 'x'), (0.0, 'y')], &mut rng),
+            Some(&'x')
+        );
+    }
+
+    #[test]
+    fn dead_particles_have_a_null_scale() {
+        let mut particle = Particle::default();
+        particle.respawn(
+            1.0,
+            Vector2f::new(3.0, 4.0),
+            Vector2f::zeros(),
+            Vector2f::new(2.0, 2.0),
+            0.0,
+            Some(Curve::new(vec![0.0, 1.0], vec![1.0, 0.0])),
+            0.0,
+        );
+        particle.colors = Curve::new(vec![0.0], vec![colors::RED]);
+        particle.update(0.5, &Vector2f::zeros());
+
+        let instance = ParticleInstance::from_particle(&particle);
+        assert_eq!(*instance.position, [3.0, 4.0]);
+        // Scaled by the curve.
+        assert_eq!(*instance.scale, [1.0, 1.0]);
+        assert_eq!(*instance.color, colors::RED.to_normalized());
+
+        particle.update(0.5, &Vector2f::zeros());
+        let instance = ParticleInstance::from_particle(&particle);
+        assert_eq!(*instance.scale, [0.0, 0.0]);
+        assert_eq!(*instance.color, [0.0; 4]);
+    }
+
+    #[test]
+    fn prewarmed_emitter_starts_full() {
+        let alive_after_first_frame = |prewarm: f32| {
+            let mut resources = Resources::default();
+            resources.insert(EventQueue::<TestEvent>::new());
+            let mut world = World::new();
+            let e = world.spawn((
+                Transform::default(),
+                ParticleEmitter {
+                    particle_life: 120,
+                    prewarm,
+                    seed: Some(7),
+                    ..ParticleEmitter::default()
+                },
+            ));
+
+            update_particles::<TestEvent>(&world, Duration::from_millis(16), &resources);
+            let emitter = world.get::<ParticleEmitter>(e).unwrap();
+            let particles = &emitter.particles.particles;
+            let oldest = particles.iter().map(|p| p.age).fold(0.0, f32::max);
+            (particles.iter().filter(|p| p.alive()).count(), oldest)
+        };
+
+        assert_eq!(alive_after_first_frame(0.0).0, 1);
+        // One particle per prewarm step, the oldest one was emitted one second ago.
+        let (alive, oldest) = alive_after_first_frame(1.0);
+        assert!(alive > 50);
+        assert!(oldest > 0.95);
+    }
+
+    fn moving_particle(damping: f32) -> Particle {
+        let mut particle = Particle::default();
+        particle.respawn(
+            1.0,
+            Vector2f::zeros(),
+            Vector2f::new(60.0, 0.0),
+            Vector2f::new(1.0, 1.0),
+            damping,
+            None,
+            0.0,
+        );
+        particle
+    }
+
+    #[test]
+    fn damping_does_not_depend_on_the_frame_rate() {
+        let mut slow = moving_particle(100.0);
+        slow.update(1.0 / 30.0, &Vector2f::zeros());
+        let mut fast = moving_particle(100.0);
+        fast.update(1.0 / 60.0, &Vector2f::zeros());
+        fast.update(1.0 / 60.0, &Vector2f::zeros());
+
+        assert!((slow.velocity.x - fast.velocity.x).abs() < 1e-3);
+    }
+
+    #[test]
+    fn lifetime_does_not_depend_on_the_frame_rate() {
+        let mut particle = moving_particle(0.0);
+        for _ in 0..3 {
+            particle.update(0.25, &Vector2f::zeros());
+        }
+        assert!(particle.alive());
+        particle.update(0.25, &Vector2f::zeros());
+        assert!(!particle.alive());
+
+        let mut particle = moving_particle(0.0);
+        for _ in 0..60 {
+            particle.update(1.0 / 60.0, &Vector2f::zeros());
+        }
+        assert!(!particle.alive());
+    }
+
+    #[test]
+    fn burst_count_is_emitted_in_one_frame() {
+        let mut emitter = ParticleEmitter {
+            burst: true,
+            burst_count: 200,
+            ..ParticleEmitter::default()
+        };
+        let alive = emitter.update(
+            &Vector2f::zeros(),
+            1.0 / 60.0,
+            &Vector2f::zeros(),
+            &mut rand::thread_rng(),
+        );
+
+        assert!(alive);
+        assert!(!emitter.enabled);
+        let emitted = emitter
+            .particles
+            .particles
+            .iter()
+            .filter(|p| p.alive())
+            .count();
+        assert_eq!(emitted, 200);
+    }
 }
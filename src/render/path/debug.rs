@@ -1,6 +1,6 @@
 //! Draw path, lines and so on for debug purposes.
 
-use crate::config::GameEngineConfig;
+use crate::config::{GameEngineConfig, Settings};
 use crate::core::colors::RgbaColor;
 use crate::geom2::Vector2f;
 use crate::render::path::{Color, Position, Vertex};
@@ -10,7 +10,9 @@ use lyon::math::Point;
 use lyon::tessellation::geometry_builder::simple_builder;
 use lyon::tessellation::{basic_shapes, StrokeOptions, VertexBuffers};
 
-pub struct DebugQueue(Vec<(Vec<Vertex>, Vec<u16>)>);
+type DebugPrimitive = (Vec<Vertex>, Vec<u16>);
+
+pub struct DebugQueue(Vec<DebugPrimitive>);
 
 impl Default for DebugQueue {
     fn default() -> Self {
@@ -19,86 +21,197 @@ impl Default for DebugQueue {
 }
 
 impl DebugQueue {
-    pub fn drain(&mut self) -> std::vec::Drain<(Vec<Vertex>, Vec<u16>)> {
+    pub fn drain(&mut self) -> std::vec::Drain<DebugPrimitive> {
         self.0.drain(..)
     }
 }
 
-fn show_gizmo(resources: &Resources) -> bool {
+#[derive(Debug, Clone, Copy)]
+enum GizmoShape {
+    Line(Vector2f, Vector2f),
+    /// Center and half-extents
+    Rect(Vector2f, Vector2f),
+    Circle(Vector2f, f32),
+}
+
+/// Immediate-mode debug drawing. Shapes are buffered during the frame and drawn by the
+/// `PathRenderer` if `show_gizmos` is true in the configuration. The buffer is cleared every
+/// frame.
+#[derive(Debug, Default)]
+pub struct Gizmos {
+    shapes: Vec<(GizmoShape, RgbaColor)>,
+}
+
+impl Gizmos {
+    pub fn line(&mut self, a: Vector2f, b: Vector2f, color: RgbaColor) {
+        self.shapes.push((GizmoShape::Line(a, b), color));
+    }
+
+    pub fn rect(&mut self, center: Vector2f, half_extents: Vector2f, color: RgbaColor) {
+        self.shapes
+            .push((GizmoShape::Rect(center, half_extents), color));
+    }
+
+    pub fn circle(&mut self, center: Vector2f, radius: f32, color: RgbaColor) {
+        self.shapes
+            .push((GizmoShape::Circle(center, radius), color));
+    }
+
+    /// Number of shapes waiting to be drawn.
+    pub fn len(&self) -> usize {
+        self.shapes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shapes.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.shapes.clear();
+    }
+
+    /// Tessellate the buffered shapes if `show` is true. The buffer is cleared in all cases.
+    pub(crate) fn flush(&mut self, show: bool) -> Vec<DebugPrimitive> {
+        let primitives = if show {
+            self.shapes
+                .iter()
+                .filter_map(|(shape, color)| match *shape {
+                    GizmoShape::Line(a, b) => tessellate_line(&a, &b, *color),
+                    GizmoShape::Rect(center, half_extents) => {
+                        tessellate_quad(&(center - half_extents), &(half_extents * 2.0), *color)
+                    }
+                    GizmoShape::Circle(center, radius) => {
+                        tessellate_circle(&center, radius, *color)
+                    }
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+
+        self.shapes.clear();
+        primitives
+    }
+}
+
+pub(crate) fn show_gizmo(resources: &Resources) -> bool {
+    if let Some(settings) = resources.fetch::<Settings>() {
+        return settings.engine().show_gizmos;
+    }
     resources
         .fetch::<GameEngineConfig>()
         .map(|conf| conf.show_gizmos)
         .unwrap_or(false)
 }
 
-pub fn stroke_circle(resources: &Resources, position: Vector2f, radius: f32, color: RgbaColor) {
-    if !show_gizmo(resources) {
-        return;
+fn to_primitive(geometry: VertexBuffers<Point, u16>, color: RgbaColor) -> DebugPrimitive {
+    let color = color.to_normalized();
+    (
+        geometry
+            .vertices
+            .iter()
+            .map(|p| Vertex {
+                position: Position::new([p.x, p.y]),
+                color: Color::new(color),
+            })
+            .collect::<Vec<_>>(),
+        geometry.indices,
+    )
+}
+
+fn tessellate_circle(position: &Vector2f, radius: f32, color: RgbaColor) -> Option<DebugPrimitive> {
+    let mut geometry: VertexBuffers<Point, u16> = VertexBuffers::new();
+    if let Err(e) = basic_shapes::stroke_circle(
+        Point::new(position.x, position.y),
+        radius,
+        &StrokeOptions::default(),
+        &mut simple_builder(&mut geometry),
+    ) {
+        error!("Error during stroke_circle = {:?}", e);
+        return None;
+    }
+    Some(to_primitive(geometry, color))
+}
+
+fn tessellate_quad(
+    position: &Vector2f,
+    dimensions: &Vector2f,
+    color: RgbaColor,
+) -> Option<DebugPrimitive> {
+    let mut geometry: VertexBuffers<Point, u16> = VertexBuffers::new();
+    let p1 = position;
+    let p2 = position + Vector2f::x() * dimensions.x;
+    let p3 = position + Vector2f::x() * dimensions.x + Vector2f::y() * dimensions.y;
+    let p4 = position + Vector2f::y() * dimensions.y;
+    if let Err(e) = basic_shapes::stroke_quad(
+        Point::new(p1.x, p1.y),
+        Point::new(p2.x, p2.y),
+        Point::new(p3.x, p3.y),
+        Point::new(p4.x, p4.y),
+        &StrokeOptions::default(),
+        &mut simple_builder(&mut geometry),
+    ) {
+        error!("Error during stroke_quad = {:?}", e);
+        return None;
+    }
+    Some(to_primitive(geometry, color))
+}
+
+fn tessellate_line(
+    position: &Vector2f,
+    target: &Vector2f,
+    color: RgbaColor,
+) -> Option<DebugPrimitive> {
+    let mut geometry: VertexBuffers<Point, u16> = VertexBuffers::new();
+    if let Err(e) = basic_shapes::stroke_polyline(
+        vec![
+            Point::new(position.x, position.y),
+            Point::new(target.x, target.y),
+        ],
+        false,
+        &StrokeOptions::default(),
+        &mut simple_builder(&mut geometry),
+    ) {
+        error!("Error during stroke_line = {:?}", e);
+        return None;
     }
+    Some(to_primitive(geometry, color))
+}
 
+fn push_primitive(resources: &Resources, primitive: Option<DebugPrimitive>) {
     match resources.fetch_mut::<DebugQueue>() {
         Some(mut debug_queue) => {
-            let mut geometry: VertexBuffers<Point, u16> = VertexBuffers::new();
-            let color = color.to_normalized();
-            if let Err(e) = basic_shapes::stroke_circle(
-                Point::new(position.x, position.y),
-                radius,
-                &StrokeOptions::default(),
-                &mut simple_builder(&mut geometry),
-            ) {
-                error!("Error during stroke_line = {:?}", e);
-                return;
+            if let Some(primitive) = primitive {
+                debug_queue.0.push(primitive);
             }
-
-            debug_queue.0.push((
-                geometry
-                    .vertices
-                    .iter()
-                    .map(|p| Vertex {
-                        position: Position::new([p.x, p.y]),
-                        color: Color::new(color),
-                    })
-                    .collect::<Vec<_>>(),
-                geometry.indices,
-            ));
         }
         None => error!("No DebugQueue in resources"),
     }
 }
 
-pub fn fill_circle(resources: &Resources, position: &Vector2f, radius: f32, color: RgbaColor) {
+pub fn stroke_circle(resources: &Resources, position: Vector2f, radius: f32, color: RgbaColor) {
     if !show_gizmo(resources) {
         return;
     }
+    push_primitive(resources, tessellate_circle(&position, radius, color));
+}
 
-    match resources.fetch_mut::<DebugQueue>() {
-        Some(mut debug_queue) => {
-            let mut geometry: VertexBuffers<Point, u16> = VertexBuffers::new();
-            let color = color.to_normalized();
-            if let Err(e) = basic_shapes::fill_circle(
-                Point::new(position.x, position.y),
-                radius,
-                &FillOptions::default(),
-                &mut simple_builder(&mut geometry),
-            ) {
-                error!("Error during stroke_line = {:?}", e);
-                return;
-            }
+pub fn fill_circle(resources: &Resources, position: &Vector2f, radius: f32, color: RgbaColor) {
+    if !show_gizmo(resources) {
+        return;
+    }
 
-            debug_queue.0.push((
-                geometry
-                    .vertices
-                    .iter()
-                    .map(|p| Vertex {
-                        position: Position::new([p.x, p.y]),
-                        color: Color::new(color),
-                    })
-                    .collect::<Vec<_>>(),
-                geometry.indices,
-            ));
-        }
-        None => error!("No DebugQueue in resources"),
+    let mut geometry: VertexBuffers<Point, u16> = VertexBuffers::new();
+    if let Err(e) = basic_shapes::fill_circle(
+        Point::new(position.x, position.y),
+        radius,
+        &FillOptions::default(),
+        &mut simple_builder(&mut geometry),
+    ) {
+        error!("Error during fill_circle = {:?}", e);
+        return;
     }
+    push_primitive(resources, Some(to_primitive(geometry, color)));
 }
 
 pub fn stroke_quad(
@@ -110,42 +223,7 @@ pub fn stroke_quad(
     if !show_gizmo(resources) {
         return;
     }
-
-    match resources.fetch_mut::<DebugQueue>() {
-        Some(mut debug_queue) => {
-            let mut geometry: VertexBuffers<Point, u16> = VertexBuffers::new();
-            let color = color.to_normalized();
-
-            let p1 = position;
-            let p2 = position + Vector2f::x() * dimensions.x;
-            let p3 = position + Vector2f::x() * dimensions.x + Vector2f::y() * dimensions.y;
-            let p4 = position + Vector2f::y() * dimensions.y;
-            if let Err(e) = basic_shapes::stroke_quad(
-                Point::new(p1.x, p1.y),
-                Point::new(p2.x, p2.y),
-                Point::new(p3.x, p3.y),
-                Point::new(p4.x, p4.y),
-                &StrokeOptions::default(),
-                &mut simple_builder(&mut geometry),
-            ) {
-                error!("Error during stroke_line = {:?}", e);
-                return;
-            }
-
-            debug_queue.0.push((
-                geometry
-                    .vertices
-                    .iter()
-                    .map(|p| Vertex {
-                        position: Position::new([p.x, p.y]),
-                        color: Color::new(color),
-                    })
-                    .collect::<Vec<_>>(),
-                geometry.indices,
-            ));
-        }
-        None => error!("No DebugQueue in resources"),
-    }
+    push_primitive(resources, tessellate_quad(position, dimensions, color));
 }
 
 pub fn stroke_line(
@@ -157,36 +235,35 @@ pub fn stroke_line(
     if !show_gizmo(resources) {
         return;
     }
+    push_primitive(resources, tessellate_line(position, target, color));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::colors;
+
+    #[test]
+    fn flush_tessellates_each_gizmo() {
+        let mut gizmos = Gizmos::default();
+        gizmos.line(Vector2f::zeros(), Vector2f::new(10.0, 0.0), colors::RED);
+        gizmos.rect(Vector2f::zeros(), Vector2f::new(5.0, 5.0), colors::GREEN);
+        gizmos.circle(Vector2f::zeros(), 5.0, colors::BLUE);
+        assert_eq!(gizmos.len(), 3);
+
+        let primitives = gizmos.flush(true);
+        assert_eq!(primitives.len(), 3);
+        // A single segment is stroked with two triangles.
+        assert_eq!(primitives[0].1.len(), 6);
+        assert!(gizmos.is_empty());
+    }
 
-    match resources.fetch_mut::<DebugQueue>() {
-        Some(mut debug_queue) => {
-            let mut geometry: VertexBuffers<Point, u16> = VertexBuffers::new();
-            let color = color.to_normalized();
-            if let Err(e) = basic_shapes::stroke_polyline(
-                vec![
-                    Point::new(position.x, position.y),
-                    Point::new(target.x, target.y),
-                ],
-                false,
-                &StrokeOptions::default(),
-                &mut simple_builder(&mut geometry),
-            ) {
-                error!("Error during stroke_line = {:?}", e);
-                return;
-            }
+    #[test]
+    fn hidden_gizmos_are_cleared() {
+        let mut gizmos = Gizmos::default();
+        gizmos.line(Vector2f::zeros(), Vector2f::new(10.0, 0.0), colors::RED);
 
-            debug_queue.0.push((
-                geometry
-                    .vertices
-                    .iter()
-                    .map(|p| Vertex {
-                        position: Position::new([p.x, p.y]),
-                        color: Color::new(color),
-                    })
-                    .collect::<Vec<_>>(),
-                geometry.indices,
-            ));
-        }
-        None => error!("No DebugQueue in resources"),
+        assert!(gizmos.flush(false).is_empty());
+        assert!(gizmos.is_empty());
     }
 }
@@ -1,6 +1,6 @@
 use crate::geom2::Matrix4f;
-use crate::render::path::debug::DebugQueue;
-use crate::render::Context;
+use crate::render::path::debug::{show_gizmo, DebugQueue, Gizmos};
+use crate::render::{ClearColor, Context};
 use crate::resources::Resources;
 use luminance::context::GraphicsContext;
 use luminance::pipeline::PipelineError;
@@ -51,6 +51,8 @@ pub fn new_shader(surface: &mut Context) -> Program<VertexSemantics, (), ShaderU
 
 pub struct PathRenderer {
     tesses: Vec<Tess<Vertex, u16>>,
+    /// Quad that covers the whole viewport, in the clear color.
+    background: Option<Tess<Vertex, u16>>,
     shader: Program<VertexSemantics, (), ShaderUniform>,
 }
 
@@ -60,26 +62,69 @@ impl PathRenderer {
         Self {
             shader,
             tesses: vec![],
+            background: None,
         }
     }
 
     pub fn prepare(&mut self, surface: &mut Context, resources: &Resources) {
         self.tesses.clear();
 
+        let clear_color = resources
+            .fetch::<ClearColor>()
+            .map(|c| *c)
+            .unwrap_or_default()
+            .color
+            .to_normalized();
+        let background = [[-1.0, -1.0], [1.0, -1.0], [1.0, 1.0], [-1.0, 1.0]]
+            .iter()
+            .map(|p| Vertex {
+                position: Position::new(*p),
+                color: Color::new(clear_color),
+            })
+            .collect::<Vec<_>>();
+        self.background = surface
+            .new_tess()
+            .set_mode(Mode::Triangle)
+            .set_vertices(background)
+            .set_indices(vec![0u16, 1, 2, 0, 2, 3])
+            .build()
+            .ok();
+
+        let mut primitives = vec![];
         if let Some(mut debug_queue) = resources.fetch_mut::<DebugQueue>() {
-            for debug_primitive in debug_queue.drain() {
-                let tess = surface
-                    .new_tess()
-                    .set_mode(Mode::Triangle)
-                    .set_vertices(debug_primitive.0)
-                    .set_indices(debug_primitive.1)
-                    .build()
-                    .unwrap();
-                self.tesses.push(tess);
-            }
+            primitives.extend(debug_queue.drain());
+        }
+        if let Some(mut gizmos) = resources.fetch_mut::<Gizmos>() {
+            primitives.extend(gizmos.flush(show_gizmo(resources)));
+        }
+
+        for debug_primitive in primitives {
+            let tess = surface
+                .new_tess()
+                .set_mode(Mode::Triangle)
+                .set_vertices(debug_primitive.0)
+                .set_indices(debug_primitive.1)
+                .build()
+                .unwrap();
+            self.tesses.push(tess);
         }
     }
 
+    /// Fill the current viewport with the clear color.
+    pub fn render_background(&mut self, shd_gate: &mut ShadingGate) -> Result<(), PipelineError> {
+        let background = match self.background.as_ref() {
+            Some(background) => background,
+            None => return Ok(()),
+        };
+        let render_state = &RenderState::default().set_depth_test(None);
+        let identity: [[f32; 4]; 4] = Matrix4f::identity().into();
+        shd_gate.shade(&mut self.shader, |mut iface, uni, mut rdr_gate| {
+            iface.set(&uni.view, identity);
+            iface.set(&uni.projection, identity);
+            rdr_gate.render(render_state, |mut tess_gate| tess_gate.render(background))
+        })
+    }
+
     pub fn render(
         &mut self,
         proj_matrix: &Matrix4f,
@@ -0,0 +1,620 @@
+//! Fullscreen effects applied after the world has been rendered (vignette, color grading...).
+//!
+//! When the `PostProcessStack` resource has some effects, the world is rendered to a texture
+//! first. Then each effect reads the texture produced by the previous one. The last effect is
+//! rendered to the screen.
+//!
+//! The built-in bloom is applied before the effects of the stack.
+use crate::assets::shader::{ShaderHandle, ShaderManager};
+use crate::assets::Handle;
+use crate::render::mesh::{new_quad_tess, Vertex, VertexSemantics};
+use crate::render::{Backend, Context};
+use luminance::backend::color_slot::ColorSlot;
+use luminance::context::GraphicsContext;
+use luminance::pipeline::{PipelineError, PipelineState, Render, TextureBinding, Viewport};
+use luminance::pixel::{NormRGBA8UI, NormUnsigned};
+use luminance::render_state::RenderState;
+use luminance::shader::{ProgramError, Uniform};
+use luminance::texture::{Dim2, Sampler};
+use luminance_derive::UniformInterface;
+use luminance_front::framebuffer::Framebuffer;
+use luminance_front::shader::Program;
+use luminance_front::tess::Tess;
+use std::collections::HashSet;
+
+const BLOOM_VS: &str = include_str!("bloom-vs.glsl");
+const BLOOM_EXTRACT_FS: &str = include_str!("bloom-extract-fs.glsl");
+const BLOOM_BLUR_FS: &str = include_str!("bloom-blur-fs.glsl");
+const BLOOM_COMBINE_FS: &str = include_str!("bloom-combine-fs.glsl");
+const COPY_FS: &str = include_str!("copy-fs.glsl");
+
+/// Vertex shader used by default for all the effects. It should just apply the MVP matrices
+/// (identity for the fullscreen passes) and forward the uvs. The shader loader serves the
+/// source shipped with the engine for this id.
+pub const DEFAULT_POST_PROCESS_VS: &str = "post-process-vs.glsl";
+pub(crate) const POST_PROCESS_VS_SOURCE: &str = include_str!("post-process-vs.glsl");
+
+#[derive(Debug, Clone)]
+pub struct PostEffect {
+    /// Fragment shader. The previous pass is bound to `u_tex_1`.
+    pub fragment_shader_id: String,
+    pub enabled: bool,
+}
+
+/// Bright areas bleed into their neighbors (neon, glow...).
+#[derive(Debug, Clone, Copy)]
+pub struct Bloom {
+    /// Pixels with a luminance above this value (between 0 and 1) glow.
+    pub threshold: f32,
+    /// Multiplier of the glow added back to the image.
+    pub intensity: f32,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self {
+            threshold: 0.8,
+            intensity: 1.0,
+        }
+    }
+}
+
+/// Ordered list of fullscreen effects. Add it as a resource to enable post-processing.
+#[derive(Debug, Clone)]
+pub struct PostProcessStack {
+    pub vertex_shader_id: String,
+    effects: Vec<PostEffect>,
+    /// Applied before the other effects when set.
+    pub bloom: Option<Bloom>,
+}
+
+impl Default for PostProcessStack {
+    fn default() -> Self {
+        Self {
+            vertex_shader_id: DEFAULT_POST_PROCESS_VS.to_string(),
+            effects: vec![],
+            bloom: None,
+        }
+    }
+}
+
+impl PostProcessStack {
+    /// Add an effect at the end of the chain.
+    pub fn push(&mut self, fragment_shader_id: &str) {
+        self.effects.push(PostEffect {
+            fragment_shader_id: fragment_shader_id.to_string(),
+            enabled: true,
+        });
+    }
+
+    pub fn enable(&mut self, fragment_shader_id: &str) {
+        self.set_enabled(fragment_shader_id, true);
+    }
+
+    pub fn disable(&mut self, fragment_shader_id: &str) {
+        self.set_enabled(fragment_shader_id, false);
+    }
+
+    pub fn set_enabled(&mut self, fragment_shader_id: &str, enabled: bool) {
+        for effect in self
+            .effects
+            .iter_mut()
+            .filter(|e| e.fragment_shader_id == fragment_shader_id)
+        {
+            effect.enabled = enabled;
+        }
+    }
+
+    pub fn effects(&self) -> &[PostEffect] {
+        &self.effects
+    }
+}
+
+#[derive(UniformInterface)]
+struct BloomUniform {
+    #[uniform(unbound, name = "u_tex_1")]
+    tex_1: Uniform<TextureBinding<Dim2, NormUnsigned>>,
+    #[uniform(unbound, name = "u_tex_2")]
+    tex_2: Uniform<TextureBinding<Dim2, NormUnsigned>>,
+    #[uniform(unbound, name = "u_threshold")]
+    threshold: Uniform<f32>,
+    #[uniform(unbound, name = "u_intensity")]
+    intensity: Uniform<f32>,
+    /// Size of a texel in the direction of the blur.
+    #[uniform(unbound, name = "u_direction")]
+    direction: Uniform<[f32; 2]>,
+}
+
+type BloomProgram = Program<VertexSemantics, (), BloomUniform>;
+
+/// Extract the bright pixels, blur them (separable gaussian) then add them to the scene.
+struct BloomShaders {
+    extract: BloomProgram,
+    blur: BloomProgram,
+    combine: BloomProgram,
+}
+
+impl BloomShaders {
+    fn new(surface: &mut Context) -> Result<Self, ProgramError> {
+        let mut compile = |fs: &str| -> Result<BloomProgram, ProgramError> {
+            let built = surface
+                .new_shader_program::<VertexSemantics, (), BloomUniform>()
+                .from_strings(BLOOM_VS, None, None, fs)?;
+            for warning in &built.warnings {
+                warn!("Shader warning: {:?}", warning);
+            }
+            Ok(built.ignore_warnings())
+        };
+        Ok(Self {
+            extract: compile(BLOOM_EXTRACT_FS)?,
+            blur: compile(BLOOM_BLUR_FS)?,
+            combine: compile(BLOOM_COMBINE_FS)?,
+        })
+    }
+}
+
+pub struct PostProcessRenderer {
+    quad: Tess<Vertex, u32>,
+    /// Ping-pong framebuffers. The world is rendered to the first one.
+    framebuffers: Option<[Framebuffer<Dim2, NormRGBA8UI, ()>; 2]>,
+    /// Bright pixels and blur passes of the bloom.
+    bloom_framebuffers: Option<[Framebuffer<Dim2, NormRGBA8UI, ()>; 2]>,
+    /// None if the shaders do not compile. The bloom is skipped then.
+    bloom_shaders: Option<BloomShaders>,
+    /// Copy the result to the back buffer when no effect could draw to it.
+    copy_shader: Option<BloomProgram>,
+    size: [u32; 2],
+    /// Effects whose shader error has already been logged.
+    reported_errors: HashSet<Handle<ShaderHandle>>,
+    /// Elapsed time in seconds sent to the shaders. Comes from the `ElapsedTime` resource.
+    pub(crate) elapsed: f32,
+}
+
+impl PostProcessRenderer {
+    pub fn new(surface: &mut Context) -> Self {
+        let bloom_shaders = match BloomShaders::new(surface) {
+            Ok(shaders) => Some(shaders),
+            Err(e) => {
+                error!("Cannot compile the bloom shaders = {}", e);
+                None
+            }
+        };
+        let copy_shader = match compile_fullscreen(surface, COPY_FS) {
+            Ok(shader) => Some(shader),
+            Err(e) => {
+                error!("Cannot compile the post-process copy shader = {}", e);
+                None
+            }
+        };
+        Self {
+            quad: new_quad_tess(surface),
+            framebuffers: None,
+            bloom_framebuffers: None,
+            bloom_shaders,
+            copy_shader,
+            size: [0, 0],
+            reported_errors: HashSet::new(),
+            elapsed: 0.0,
+        }
+    }
+
+    /// Get the effects that can be applied this frame. Shaders that are not loaded yet are
+    /// skipped. A shader in error is reported once, until it is loaded again.
+    pub fn active_effects(
+        &mut self,
+        stack: &PostProcessStack,
+        shader_manager: &mut ShaderManager,
+    ) -> Vec<Handle<ShaderHandle>> {
+        let mut active = vec![];
+        for effect in stack.effects.iter().filter(|e| e.enabled) {
+            let handle = Handle((
+                stack.vertex_shader_id.clone(),
+                effect.fragment_shader_id.clone(),
+            ));
+
+            if shader_manager.is_loaded(&handle) {
+                self.reported_errors.remove(&handle);
+                active.push(handle);
+            } else if shader_manager.is_error(&handle) {
+                if !self.reported_errors.contains(&handle) {
+                    warn!(
+                        "Post-process shader {} has an error, skipping it",
+                        effect.fragment_shader_id
+                    );
+                    self.reported_errors.insert(handle);
+                }
+            } else {
+                debug!(
+                    "Post-process shader {} is not loaded yet, skipping it",
+                    effect.fragment_shader_id
+                );
+                shader_manager.load(handle.0);
+            }
+        }
+        active
+    }
+
+    /// Create the framebuffers if they do not exist or if the viewport size has changed.
+    pub fn resize(&mut self, surface: &mut Context, size: [u32; 2]) {
+        if self.framebuffers.is_some() && self.size == size {
+            return;
+        }
+
+        self.framebuffers = new_framebuffers(surface, size);
+        self.bloom_framebuffers = new_framebuffers(surface, size);
+        if self.framebuffers.is_some() {
+            self.size = size;
+        }
+    }
+
+    /// Framebuffer in which the world should be rendered.
+    pub fn scene_framebuffer(&mut self) -> Option<&mut Framebuffer<Dim2, NormRGBA8UI, ()>> {
+        self.framebuffers.as_mut().map(|fbs| &mut fbs[0])
+    }
+
+    /// Run the bloom then all the effects in order. The last one is rendered to the back buffer
+    /// in the given viewport. The back buffer is not cleared. If nothing could draw to the back
+    /// buffer (shader not compiled...), the scene is copied to it so the frame is never lost.
+    pub fn apply<CS>(
+        &mut self,
+        surface: &mut Context,
+        back_buffer: &mut Framebuffer<Dim2, CS, ()>,
+        effects: &[Handle<ShaderHandle>],
+        shader_manager: &mut ShaderManager,
+        viewport: Viewport,
+        bloom: Option<Bloom>,
+    ) -> Render<PipelineError>
+    where
+        CS: ColorSlot<Backend, Dim2>,
+    {
+        // Only keep the effects that can draw so that the last one is really the last pass.
+        let effects: Vec<&Handle<ShaderHandle>> = effects
+            .iter()
+            .filter(|handle| {
+                shader_manager
+                    .get(handle)
+                    .and_then(|shader| shader.execute(|asset| asset.shader.is_some()))
+                    .unwrap_or(false)
+            })
+            .collect();
+        let bloom = bloom.filter(|_| self.bloom_shaders.is_some());
+
+        let mut src = 0;
+        if let Some(bloom) = bloom {
+            let render =
+                self.apply_bloom(surface, back_buffer, &bloom, effects.is_empty(), viewport);
+            if !render.is_ok() || effects.is_empty() {
+                return render;
+            }
+            // The scene with the bloom is in the second framebuffer.
+            src = 1;
+        }
+
+        let framebuffers = match self.framebuffers.as_mut() {
+            Some(fbs) => fbs,
+            None => return Ok(()).into(),
+        };
+        let quad = &self.quad;
+
+        if effects.is_empty() {
+            let copy_shader = match self.copy_shader.as_mut() {
+                Some(shader) => shader,
+                None => return Ok(()).into(),
+            };
+            let on_screen = PipelineState::default()
+                .set_viewport(viewport)
+                .enable_clear_color(false);
+            return bloom_pass(
+                surface,
+                back_buffer,
+                &on_screen,
+                copy_shader,
+                quad,
+                &mut framebuffers[src],
+                None,
+                &Bloom::default(),
+                [0.0, 0.0],
+            );
+        }
+
+        let elapsed = self.elapsed;
+        let identity: [[f32; 4]; 4] = crate::geom2::Matrix4f::identity().into();
+        let render_st = RenderState::default().set_depth_test(None);
+
+        for (i, handle) in effects.iter().enumerate() {
+            let is_last = i == effects.len() - 1;
+            let (first, second) = framebuffers.split_at_mut(1);
+            let (src_fb, dst_fb) = if src == 0 {
+                (&mut first[0], &mut second[0])
+            } else {
+                (&mut second[0], &mut first[0])
+            };
+
+            // Filtered above.
+            let shader = shader_manager.get_mut(handle).unwrap();
+
+            let mut pass = |pipeline: luminance_front::pipeline::Pipeline,
+                            mut shd_gate: luminance_front::shading_gate::ShadingGate|
+             -> Result<(), PipelineError> {
+                let bound_tex = pipeline.bind_texture(src_fb.color_slot())?;
+                shader
+                    .execute_mut(|shader_asset| {
+                        if let Some(ref mut shader) = shader_asset.shader {
+                            shd_gate.shade(shader, |mut iface, uni, mut rdr_gate| {
+                                iface.set(&uni.time, elapsed);
+                                iface.set(&uni.projection, identity);
+                                iface.set(&uni.view, identity);
+                                iface.set(&uni.model, identity);
+                                iface.set(&uni.tex_1, bound_tex.binding());
+                                rdr_gate.render(&render_st, |mut tess_gate| tess_gate.render(quad))
+                            })
+                        } else {
+                            Ok(())
+                        }
+                    })
+                    .unwrap_or(Ok(()))
+            };
+
+            let render = if is_last {
+                surface
+                    .new_pipeline_gate()
+                    .pipeline(
+                        back_buffer,
+                        &PipelineState::default()
+                            .set_viewport(viewport)
+                            .enable_clear_color(false),
+                        |pipeline, shd_gate| pass(pipeline, shd_gate),
+                    )
+                    .assume()
+            } else {
+                surface
+                    .new_pipeline_gate()
+                    .pipeline(dst_fb, &PipelineState::default(), |pipeline, shd_gate| {
+                        pass(pipeline, shd_gate)
+                    })
+                    .assume()
+            };
+
+            if !render.is_ok() {
+                return render;
+            }
+            src = 1 - src;
+        }
+
+        Ok(()).into()
+    }
+
+    /// Add the blurred bright pixels of the scene (first framebuffer) to the scene. The result
+    /// is rendered to the back buffer if `to_back_buffer` is true, otherwise to the second
+    /// framebuffer.
+    fn apply_bloom<CS>(
+        &mut self,
+        surface: &mut Context,
+        back_buffer: &mut Framebuffer<Dim2, CS, ()>,
+        bloom: &Bloom,
+        to_back_buffer: bool,
+        viewport: Viewport,
+    ) -> Render<PipelineError>
+    where
+        CS: ColorSlot<Backend, Dim2>,
+    {
+        let (framebuffers, bloom_framebuffers, shaders) = match (
+            self.framebuffers.as_mut(),
+            self.bloom_framebuffers.as_mut(),
+            self.bloom_shaders.as_mut(),
+        ) {
+            (Some(fbs), Some(bloom_fbs), Some(shaders)) => (fbs, bloom_fbs, shaders),
+            _ => return Ok(()).into(),
+        };
+        let quad = &self.quad;
+        let texel = [1.0 / self.size[0] as f32, 1.0 / self.size[1] as f32];
+        let (scene, output) = framebuffers.split_at_mut(1);
+        let (bright, blurred) = bloom_framebuffers.split_at_mut(1);
+        let offscreen = PipelineState::default();
+
+        // Bright pixels, then horizontal and vertical blur.
+        let render = bloom_pass(
+            surface,
+            &mut bright[0],
+            &offscreen,
+            &mut shaders.extract,
+            quad,
+            &mut scene[0],
+            None,
+            bloom,
+            [0.0, 0.0],
+        );
+        if !render.is_ok() {
+            return render;
+        }
+        let render = bloom_pass(
+            surface,
+            &mut blurred[0],
+            &offscreen,
+            &mut shaders.blur,
+            quad,
+            &mut bright[0],
+            None,
+            bloom,
+            [texel[0], 0.0],
+        );
+        if !render.is_ok() {
+            return render;
+        }
+        let render = bloom_pass(
+            surface,
+            &mut bright[0],
+            &offscreen,
+            &mut shaders.blur,
+            quad,
+            &mut blurred[0],
+            None,
+            bloom,
+            [0.0, texel[1]],
+        );
+        if !render.is_ok() {
+            return render;
+        }
+
+        if to_back_buffer {
+            let on_screen = PipelineState::default()
+                .set_viewport(viewport)
+                .enable_clear_color(false);
+            bloom_pass(
+                surface,
+                back_buffer,
+                &on_screen,
+                &mut shaders.combine,
+                quad,
+                &mut scene[0],
+                Some(&mut bright[0]),
+                bloom,
+                [0.0, 0.0],
+            )
+        } else {
+            bloom_pass(
+                surface,
+                &mut output[0],
+                &offscreen,
+                &mut shaders.combine,
+                quad,
+                &mut scene[0],
+                Some(&mut bright[0]),
+                bloom,
+                [0.0, 0.0],
+            )
+        }
+    }
+}
+
+fn new_framebuffers(
+    surface: &mut Context,
+    size: [u32; 2],
+) -> Option<[Framebuffer<Dim2, NormRGBA8UI, ()>; 2]> {
+    let first = surface.new_framebuffer::<Dim2, NormRGBA8UI, ()>(size, 0, Sampler::default());
+    let second = surface.new_framebuffer::<Dim2, NormRGBA8UI, ()>(size, 0, Sampler::default());
+    match (first, second) {
+        (Ok(first), Ok(second)) => Some([first, second]),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("Cannot create post-process framebuffers = {:?}", e);
+            None
+        }
+    }
+}
+
+/// Draw the fullscreen quad with one of the bloom shaders. The source textures are bound to
+/// `u_tex_1` and `u_tex_2`.
+#[allow(clippy::too_many_arguments)]
+fn bloom_pass<CS>(
+    surface: &mut Context,
+    dst: &mut Framebuffer<Dim2, CS, ()>,
+    pipeline_state: &PipelineState,
+    shader: &mut BloomProgram,
+    quad: &Tess<Vertex, u32>,
+    tex_1: &mut Framebuffer<Dim2, NormRGBA8UI, ()>,
+    tex_2: Option<&mut Framebuffer<Dim2, NormRGBA8UI, ()>>,
+    bloom: &Bloom,
+    direction: [f32; 2],
+) -> Render<PipelineError>
+where
+    CS: ColorSlot<Backend, Dim2>,
+{
+    let render_st = RenderState::default().set_depth_test(None);
+    surface
+        .new_pipeline_gate()
+        .pipeline(dst, pipeline_state, |pipeline, mut shd_gate| {
+            let bound_1 = pipeline.bind_texture(tex_1.color_slot())?;
+            let bound_2 = match tex_2 {
+                Some(tex_2) => Some(pipeline.bind_texture(tex_2.color_slot())?),
+                None => None,
+            };
+            shd_gate.shade(shader, |mut iface, uni, mut rdr_gate| {
+                iface.set(&uni.tex_1, bound_1.binding());
+                if let Some(ref bound_2) = bound_2 {
+                    iface.set(&uni.tex_2, bound_2.binding());
+                }
+                iface.set(&uni.threshold, bloom.threshold);
+                iface.set(&uni.intensity, bloom.intensity);
+                iface.set(&uni.direction, direction);
+                rdr_gate.render(&render_st, |mut tess_gate| tess_gate.render(quad))
+            })
+        })
+        .assume()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::shader::ShaderLoader;
+    use crate::assets::AssetManager;
+
+    const HALF_FS: &str = "in vec2 v_uv;
+out vec4 frag;
+uniform sampler2D u_tex_1;
+void main() {
+    frag = texture(u_tex_1, v_uv) * 0.5;
+}";
+
+    const ADD_QUARTER_FS: &str = "in vec2 v_uv;
+out vec4 frag;
+uniform sampler2D u_tex_1;
+void main() {
+    frag = texture(u_tex_1, v_uv) + vec4(0.25, 0.25, 0.25, 0.0);
+}";
+
+    #[test]
+    #[ignore] // needs an OpenGL context
+    fn two_effects_run_in_order() {
+        let dir = std::env::temp_dir().join("snoozeng-post-process-chain");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("half.glsl"), HALF_FS).unwrap();
+        std::fs::write(dir.join("add-quarter.glsl"), ADD_QUARTER_FS).unwrap();
+        let mut shaders: ShaderManager =
+            AssetManager::from_loader(Box::new(ShaderLoader::new(&dir)));
+
+        let mut surface = crate::render::test_surface(4, 4);
+        let mut renderer = PostProcessRenderer::new(&mut surface);
+        renderer.resize(&mut surface, [4, 4]);
+        let mut stack = PostProcessStack::default();
+        stack.push("half.glsl");
+        stack.push("add-quarter.glsl");
+
+        // The first call starts loading the shaders.
+        assert!(renderer.active_effects(&stack, &mut shaders).is_empty());
+        shaders.upload_all(&mut surface);
+        let effects = renderer.active_effects(&stack, &mut shaders);
+        assert_eq!(2, effects.len());
+
+        let scene = renderer.scene_framebuffer().unwrap();
+        surface
+            .new_pipeline_gate()
+            .pipeline(
+                scene,
+                &PipelineState::default().set_clear_color([1.0, 1.0, 1.0, 1.0]),
+                |_, _| Ok(()),
+            )
+            .assume()
+            .into_result()
+            .unwrap();
+        let mut target = surface
+            .new_framebuffer::<Dim2, NormRGBA8UI, ()>([4, 4], 0, Sampler::default())
+            .unwrap();
+        renderer
+            .apply(
+                &mut surface,
+                &mut target,
+                &effects,
+                &mut shaders,
+                Viewport::Whole,
+                None,
+            )
+            .into_result()
+            .unwrap();
+
+        // 1.0 * 0.5 + 0.25. The other order would give (1.0 + 0.25) * 0.5 = 0.5 after clamping.
+        let texels = target.color_slot().get_raw_texels().unwrap();
+        for pixel in texels.chunks(4) {
+            assert!((pixel[0] as i32 - 191).abs() <= 1, "{:?}", pixel);
+        }
+    }
+}
@@ -1,24 +1,86 @@
 use crate::core::colors::RgbaColor;
 use crate::core::window::WindowDim;
 use crate::render::ui::text::Text;
-use crate::render::ui::{text, Button, DrawData, Panel, FONT_DATA};
-use glyph_brush::GlyphBrushBuilder;
+use crate::render::ui::{text, Button, DrawData, Panel, ProgressBar, Vertex, FONT_DATA};
+use glyph_brush::{FontId, GlyphBrushBuilder};
 use serde_derive::{Deserialize, Serialize};
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
 use std::rc::Rc;
 
 use crate::core::input::ser::{InputEvent, VirtualAction, VirtualButton};
 use crate::geom2::Vector2f;
-use glyph_brush::rusttype::Scale;
+use glyph_brush::rusttype::{Scale, SharedBytes};
 use glyph_brush::{GlyphBrush, GlyphCruncher, Layout, Section};
 
 pub struct GuiContext {
     pub(crate) window_dim: WindowDim,
+    /// Top-left corner of the UI area in the window. See `UiAnchor`.
+    pub(crate) offset: Vector2f,
     pub(crate) mouse_pos: Vector2f,
     pub(crate) mouse_clicked: Vec<VirtualButton>,
     pub(crate) style: Style,
 
     pub(crate) fonts: Rc<RefCell<GlyphBrush<'static, text::Instance>>>,
+    /// Fonts added after the default one, by name.
+    font_ids: HashMap<String, FontId>,
+
+    /// Widgets that can be clicked, filled during the frame. Used at the next frame to
+    /// find which widget is on top of the others under the cursor.
+    pub(crate) hit_regions: Rc<RefCell<Vec<HitRegion>>>,
+}
+
+/// Area of the screen that is covered by a widget.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct HitRegion {
+    /// Top-left corner
+    pub(crate) anchor: Vector2f,
+    pub(crate) dimensions: Vector2f,
+    pub(crate) z_index: i32,
+}
+
+impl HitRegion {
+    fn contains(&self, pos: &Vector2f) -> bool {
+        let rel = pos - self.anchor;
+        rel.x >= 0.0 && rel.x < self.dimensions.x && rel.y >= 0.0 && rel.y <= self.dimensions.y
+    }
+}
+
+/// Rectangle outside of which the widgets are not drawn, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClipRect {
+    /// Top-left corner
+    pub anchor: Vector2f,
+    pub dimensions: Vector2f,
+}
+
+impl ClipRect {
+    /// Bottom-right corner
+    pub fn max(&self) -> Vector2f {
+        self.anchor + self.dimensions
+    }
+
+    /// Overlap of the two rectangles. The dimensions are zero if they do not overlap.
+    pub fn intersect(&self, other: &ClipRect) -> ClipRect {
+        let anchor = Vector2f::new(
+            self.anchor.x.max(other.anchor.x),
+            self.anchor.y.max(other.anchor.y),
+        );
+        let max = Vector2f::new(
+            self.max().x.min(other.max().x),
+            self.max().y.min(other.max().y),
+        );
+        ClipRect {
+            anchor,
+            dimensions: Vector2f::new((max.x - anchor.x).max(0.0), (max.y - anchor.y).max(0.0)),
+        }
+    }
+
+    pub fn contains(&self, pos: &Vector2f) -> bool {
+        let rel = pos - self.anchor;
+        rel.x >= 0.0 && rel.x < self.dimensions.x && rel.y >= 0.0 && rel.y < self.dimensions.y
+    }
 }
 
 impl GuiContext {
@@ -29,35 +91,85 @@ impl GuiContext {
 
         Self {
             fonts: Rc::new(RefCell::new(fonts)),
+            font_ids: HashMap::new(),
             window_dim,
+            offset: Vector2f::zeros(),
             mouse_pos: Vector2f::zeros(),
             mouse_clicked: vec![],
             style: Style::default(),
+            hit_regions: Rc::new(RefCell::new(vec![])),
         }
     }
 
+    /// Add a font that can be used by the widgets with `Gui::set_font`. The default font is
+    /// `FontId::default()`.
+    pub fn register_font<B>(&mut self, name: &str, font_data: B) -> FontId
+    where
+        B: Into<SharedBytes<'static>>,
+    {
+        let font_id = self.fonts.borrow_mut().add_font_bytes(font_data);
+        self.font_ids.insert(name.to_string(), font_id);
+        font_id
+    }
+
+    /// Load a font file and register it.
+    pub fn load_font<P: AsRef<Path>>(
+        &mut self,
+        name: &str,
+        path: P,
+    ) -> Result<FontId, std::io::Error> {
+        let font_data = std::fs::read(path)?;
+        Ok(self.register_font(name, font_data))
+    }
+
+    /// Get a font that was registered before.
+    pub fn font(&self, name: &str) -> Option<FontId> {
+        self.font_ids.get(name).copied()
+    }
+
     pub fn reset_inputs(&mut self) {
         self.mouse_clicked.clear();
     }
 
+    /// Set the area the UI is laid out in. The cursor position is moved along with the area so
+    /// that it stays relative to its top-left corner.
+    pub(crate) fn set_area(&mut self, window_dim: WindowDim, offset: Vector2f) {
+        self.mouse_pos += self.offset - offset;
+        self.window_dim = window_dim;
+        self.offset = offset;
+    }
+
     pub fn process_event(&mut self, ev: InputEvent) {
         match ev {
             InputEvent::MouseEvent(btn, VirtualAction::Pressed) => self.mouse_clicked.push(btn),
             InputEvent::CursorPos(x, y) => {
-                self.mouse_pos.x = x as f32;
-                self.mouse_pos.y = y as f32;
+                self.mouse_pos.x = x as f32 - self.offset.x;
+                self.mouse_pos.y = y as f32 - self.offset.y;
             }
             _ => (),
         }
     }
 
     pub fn new_frame(&self) -> Gui {
+        // Topmost widget under the mouse during the last frame. Widgets are identified by their
+        // area and z-index so that the capture does not move to another widget when the widgets
+        // are created in a different order.
+        let last_regions = std::mem::take(&mut *self.hit_regions.borrow_mut());
+        let capture = last_regions
+            .iter()
+            .enumerate()
+            .filter(|(_, region)| region.contains(&self.mouse_pos))
+            .max_by_key(|(i, region)| (region.z_index, *i))
+            .map(|(_, region)| *region);
+
         Gui::new(
             self.window_dim,
             self.mouse_pos.clone(),
             self.mouse_clicked.clone(),
             self.style,
             Rc::clone(&self.fonts),
+            capture,
+            Rc::clone(&self.hit_regions),
         )
     }
 }
@@ -69,6 +181,17 @@ pub struct Gui {
     pub(crate) mouse_clicked: Vec<VirtualButton>,
     pub(crate) style: Style,
     pub(crate) fonts: Rc<RefCell<GlyphBrush<'static, text::Instance>>>,
+
+    /// Font used for the next widgets.
+    pub(crate) font_id: FontId,
+    /// z-index used for the next widgets. Larger z-index is drawn on top.
+    pub(crate) z_index: i32,
+    /// Widget that captures the mouse this frame. None if no widget was under the mouse
+    /// during the last frame, in which case no widget is hovered.
+    pub(crate) capture: Option<HitRegion>,
+    pub(crate) hit_regions: Rc<RefCell<Vec<HitRegion>>>,
+    /// Clip rectangles pushed with `push_clip`. The last one is the intersection of all of them.
+    clip_stack: Vec<ClipRect>,
 }
 
 impl Gui {
@@ -78,6 +201,8 @@ impl Gui {
         mouse_clicked: Vec<VirtualButton>,
         style: Style,
         fonts: Rc<RefCell<GlyphBrush<'static, text::Instance>>>,
+        capture: Option<HitRegion>,
+        hit_regions: Rc<RefCell<Vec<HitRegion>>>,
     ) -> Self {
         Self {
             draw_data: vec![],
@@ -86,53 +211,197 @@ impl Gui {
             mouse_pos,
             style,
             fonts,
+            font_id: FontId::default(),
+            z_index: 0,
+            capture,
+            hit_regions,
+            clip_stack: vec![],
+        }
+    }
+
+    /// Only draw the next widgets inside this rectangle, until `pop_clip` is called. Nested clip
+    /// rectangles are intersected. Text is cut at the edges and wrapped at the right edge.
+    pub fn push_clip(&mut self, pos: Vector2f, dimensions: Vector2f) {
+        let rect = ClipRect {
+            anchor: pos,
+            dimensions,
+        };
+        let rect = match self.clip_stack.last() {
+            Some(parent) => parent.intersect(&rect),
+            None => rect,
+        };
+        self.clip_stack.push(rect);
+    }
+
+    pub fn pop_clip(&mut self) {
+        if self.clip_stack.pop().is_none() {
+            warn!("pop_clip called without a clip rectangle");
         }
     }
-    pub fn panel(&mut self, pos: Vector2f, dimensions: Vector2f, color: RgbaColor) {
+
+    pub(crate) fn clip(&self) -> Option<ClipRect> {
+        self.clip_stack.last().copied()
+    }
+
+    pub(crate) fn push_vertices(&mut self, vertices: Vec<Vertex>, indices: Vec<u32>, z_index: i32) {
+        let clip = self.clip();
+        self.draw_data
+            .push(DrawData::Vertices(vertices, indices, z_index, clip));
+    }
+
+    pub(crate) fn push_text(&mut self, text: Text, pos: Vector2f) {
+        let clip = self.clip();
+        let z_index = self.z_index;
+        self.draw_data
+            .push(DrawData::Text(text, pos, z_index, clip));
+    }
+
+    /// Set the z-index of the widgets created after this call. Widgets with a larger z-index
+    /// are drawn on top and will receive the clicks first.
+    pub fn set_z_index(&mut self, z_index: i32) {
+        self.z_index = z_index;
+    }
+
+    /// Set the font of the widgets created after this call. Use `FontId::default()` to go back
+    /// to the default font.
+    pub fn set_font(&mut self, font_id: FontId) {
+        self.font_id = font_id;
+    }
+
+    /// Register the area covered by a widget. Returns true if the mouse is above this widget and
+    /// no other widget is on top of it. The widgets on top are only known from the last frame,
+    /// so a widget that just appeared under the mouse is hovered one frame later.
+    pub(crate) fn register_hit_region(
+        &mut self,
+        anchor: Vector2f,
+        dimensions: Vector2f,
+        z_index: i32,
+    ) -> bool {
+        // Clipped parts of the widget cannot be clicked.
+        let (anchor, dimensions) = match self.clip() {
+            Some(clip) => {
+                let visible = clip.intersect(&ClipRect { anchor, dimensions });
+                (visible.anchor, visible.dimensions)
+            }
+            None => (anchor, dimensions),
+        };
+        let region = HitRegion {
+            anchor,
+            dimensions,
+            z_index,
+        };
+        self.hit_regions.borrow_mut().push(region);
+
+        region.contains(&self.mouse_pos) && self.capture == Some(region)
+    }
+
+    /// Draw a panel. The returned builder can be used to add widgets inside the panel,
+    /// e.g. `gui.panel(pos, dim, color).button(btn_pos, None, "Play".to_string()).clicked()`
+    pub fn panel(
+        &mut self,
+        pos: Vector2f,
+        dimensions: Vector2f,
+        color: RgbaColor,
+    ) -> PanelBuilder<'_> {
         let (vertices, indices) = Panel {
             anchor: pos,
             dimensions,
             color,
         }
         .vertices(self.window_dim);
-        self.draw_data.push(DrawData::Vertices(vertices, indices));
+
+        // Panels block the clicks for the widgets below.
+        let z_index = self.z_index;
+        self.register_hit_region(pos, dimensions, z_index);
+        self.push_vertices(vertices, indices, z_index);
+
+        PanelBuilder {
+            gui: self,
+            anchor: pos,
+            clicked: vec![],
+        }
     }
 
     pub fn label(&mut self, pos: Vector2f, text: String) {
-        self.draw_data.push(DrawData::Text(
+        self.push_text(
             Text {
                 content: text,
                 font_size: self.style.font_size,
                 color: self.style.text_color,
                 align: (HorizontalAlign::Left, VerticalAlign::Top),
+                bounds: None,
+                font_id: self.font_id,
+                shadow: self.style.text_shadow,
+                outline: self.style.text_outline,
             },
             pos,
-        ));
+        );
     }
 
     pub fn centered_label(&mut self, pos: Vector2f, text: String) {
         let bounds = self.text_bounds(text.as_str(), self.style.font_size);
         let real_pos = pos - bounds / 2.0;
-        self.draw_data.push(DrawData::Text(
+        self.push_text(
             Text {
                 content: text,
                 font_size: self.style.font_size,
                 color: self.style.text_color,
                 align: (HorizontalAlign::Left, VerticalAlign::Top),
+                bounds: None,
+                font_id: self.font_id,
+                shadow: self.style.text_shadow,
+                outline: self.style.text_outline,
             },
             real_pos,
-        ));
+        );
     }
     pub fn colored_label(&mut self, pos: Vector2f, text: String, color: RgbaColor) {
-        self.draw_data.push(DrawData::Text(
+        self.push_text(
             Text {
                 content: text,
                 font_size: self.style.font_size,
                 color,
                 align: (HorizontalAlign::Left, VerticalAlign::Top),
+                bounds: None,
+                font_id: self.font_id,
+                shadow: self.style.text_shadow,
+                outline: self.style.text_outline,
             },
             pos,
-        ));
+        );
+    }
+
+    /// Label that wraps at `max_width`. Lines are also broken at `\n`. Returns the height of the
+    /// text so that the surrounding panel can be sized to it.
+    pub fn wrapped_label(&mut self, pos: Vector2f, text: String, max_width: f32) -> f32 {
+        let bounds = Vector2f::new(max_width, self.window_dim.height as f32);
+        let height = self
+            .measure_text(
+                text.as_str(),
+                self.style.font_size,
+                bounds,
+                (HorizontalAlign::Left, VerticalAlign::Top),
+            )
+            .y;
+        self.push_text(
+            Text {
+                content: text,
+                font_size: self.style.font_size,
+                color: self.style.text_color,
+                align: (HorizontalAlign::Left, VerticalAlign::Top),
+                bounds: Some(bounds),
+                font_id: self.font_id,
+                shadow: self.style.text_shadow,
+                outline: self.style.text_outline,
+            },
+            pos,
+        );
+        height
+    }
+
+    /// Horizontal bar filled according to `value` (between 0 and 1) with the default colors.
+    pub fn progress_bar(&mut self, pos: Vector2f, dimensions: Vector2f, value: f32) {
+        ProgressBar::new(value, pos, dimensions).build(self);
     }
 
     pub fn button(&mut self, pos: Vector2f, dimensions: Option<Vector2f>, text: String) -> bool {
@@ -145,31 +414,87 @@ impl Gui {
     }
 
     pub fn text_bounds(&mut self, text: &str, font_size: f32) -> Vector2f {
+        let bounds = Vector2f::new(
+            self.window_dim.width as f32 / 3.15,
+            self.window_dim.height as f32,
+        );
+        self.measure_text(text, font_size, bounds, self.style.button_text_align)
+    }
+
+    /// Size of the text once laid out within the bounds.
+    fn measure_text(
+        &mut self,
+        text: &str,
+        font_size: f32,
+        bounds: Vector2f,
+        align: (HorizontalAlign, VerticalAlign),
+    ) -> Vector2f {
         let scale = Scale::uniform(font_size.round());
         let section = Section {
             text,
             scale,
             screen_position: (0.0, 0.0), //(text_position.x(), text_position.y()),
-            bounds: (
-                self.window_dim.width as f32 / 3.15,
-                self.window_dim.height as f32,
-            ),
+            bounds: (bounds.x, bounds.y),
             color: RgbaColor::new(0, 0, 0, 0).to_normalized(),
+            font_id: self.font_id,
             layout: Layout::default()
-                .h_align(self.style.button_text_align.0.into())
-                .v_align(self.style.button_text_align.1.into()),
+                .h_align(align.0.into())
+                .v_align(align.1.into()),
             ..Section::default()
         };
 
-        let bounds = self
-            .fonts
-            .borrow_mut()
-            .glyph_bounds(section)
-            .expect("Text should have bounds");
+        // Empty text does not have bounds.
+        match self.fonts.borrow_mut().glyph_bounds(section) {
+            Some(bounds) => Vector2f::new(bounds.width(), bounds.height()),
+            None => Vector2f::zeros(),
+        }
+    }
+}
+
+/// Add widgets to a panel. Positions are relative to the top-left corner of the panel.
+/// Nothing is kept between frames so the panel should be built every frame.
+pub struct PanelBuilder<'a> {
+    gui: &'a mut Gui,
+    anchor: Vector2f,
+    /// Result of the buttons, in order of creation.
+    clicked: Vec<bool>,
+}
+
+impl<'a> PanelBuilder<'a> {
+    pub fn button(mut self, pos: Vector2f, dimensions: Option<Vector2f>, text: String) -> Self {
+        let clicked = self.gui.button(self.anchor + pos, dimensions, text);
+        self.clicked.push(clicked);
+        self
+    }
 
-        let height = bounds.height();
-        let width = bounds.width();
-        Vector2f::new(width, height)
+    pub fn label(self, pos: Vector2f, text: String) -> Self {
+        self.gui.label(self.anchor + pos, text);
+        self
+    }
+
+    pub fn colored_label(self, pos: Vector2f, text: String, color: RgbaColor) -> Self {
+        self.gui.colored_label(self.anchor + pos, text, color);
+        self
+    }
+
+    pub fn wrapped_label(self, pos: Vector2f, text: String, max_width: f32) -> Self {
+        self.gui.wrapped_label(self.anchor + pos, text, max_width);
+        self
+    }
+
+    /// Returns true if any button of the panel was clicked this frame.
+    pub fn clicked(&self) -> bool {
+        self.clicked.iter().any(|c| *c)
+    }
+
+    /// Returns the index of the button that was clicked this frame, if any.
+    pub fn clicked_index(&self) -> Option<usize> {
+        self.clicked.iter().position(|c| *c)
+    }
+
+    /// Click result for each button, in order of creation.
+    pub fn results(self) -> Vec<bool> {
+        self.clicked
     }
 }
 
@@ -223,6 +548,12 @@ pub struct Style {
     pub text_color: RgbaColor,
     /// font size
     pub font_size: f32,
+    /// Offset and color of the shadow of the labels.
+    #[serde(default)]
+    pub text_shadow: Option<(Vector2f, RgbaColor)>,
+    /// Color of the outline of the labels.
+    #[serde(default)]
+    pub text_outline: Option<RgbaColor>,
 }
 
 impl Default for Style {
@@ -235,6 +566,141 @@ impl Default for Style {
             button_text_align: (HorizontalAlign::Center, VerticalAlign::Center),
             text_color: RgbaColor::new(255, 255, 255, 255),
             font_size: 16.0,
+            text_shadow: None,
+            text_outline: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two buttons that overlap between x = 50 and x = 100. The second one is on top.
+    fn overlapping_buttons(context: &GuiContext, top_first: bool) -> (bool, bool) {
+        let mut gui = context.new_frame();
+        let dimensions = Some(Vector2f::new(100.0, 50.0));
+        let bottom = |gui: &mut Gui| {
+            gui.set_z_index(0);
+            gui.button(Vector2f::new(0.0, 0.0), dimensions, "Bottom".to_string())
+        };
+        let top = |gui: &mut Gui| {
+            gui.set_z_index(1);
+            gui.button(Vector2f::new(50.0, 0.0), dimensions, "Top".to_string())
+        };
+        if top_first {
+            let top = top(&mut gui);
+            (bottom(&mut gui), top)
+        } else {
+            (bottom(&mut gui), top(&mut gui))
         }
     }
+
+    #[test]
+    fn only_the_top_button_is_clicked() {
+        let mut context = GuiContext::new(WindowDim::new(800, 600));
+        context.process_event(InputEvent::CursorPos(75.0, 25.0));
+        context.process_event(InputEvent::MouseEvent(
+            VirtualButton::Button1,
+            VirtualAction::Pressed,
+        ));
+
+        // The widgets under the mouse are not known yet.
+        assert_eq!(overlapping_buttons(&context, false), (false, false));
+        assert_eq!(overlapping_buttons(&context, false), (false, true));
+        // The capture follows the widget when the creation order changes.
+        assert_eq!(overlapping_buttons(&context, true), (false, true));
+    }
+
+    #[test]
+    fn panel_draw_list() {
+        let context = GuiContext::new(WindowDim::new(800, 600));
+        let mut gui = context.new_frame();
+        let clicked = gui
+            .panel(
+                Vector2f::new(10.0, 20.0),
+                Vector2f::new(200.0, 100.0),
+                RgbaColor::new(0, 0, 0, 255),
+            )
+            .button(
+                Vector2f::new(5.0, 5.0),
+                Some(Vector2f::new(50.0, 20.0)),
+                "Play".to_string(),
+            )
+            .label(Vector2f::new(5.0, 40.0), "Score".to_string())
+            .results();
+        assert_eq!(clicked, vec![false]);
+
+        let texts: Vec<_> = gui
+            .draw_data
+            .iter()
+            .filter_map(|data| match data {
+                DrawData::Text(text, pos, _, _) => Some((text.content.as_str(), *pos)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                ("Play", Vector2f::new(15.0, 25.0)),
+                ("Score", Vector2f::new(15.0, 60.0))
+            ]
+        );
+        // Panel background then button background.
+        let quads = gui
+            .draw_data
+            .iter()
+            .filter(|data| matches!(data, DrawData::Vertices(..)))
+            .count();
+        assert_eq!(quads, 2);
+        assert!(matches!(gui.draw_data[0], DrawData::Vertices(..)));
+    }
+
+    #[test]
+    fn wrapped_label_breaks_lines() {
+        let context = GuiContext::new(WindowDim::new(800, 600));
+        let mut gui = context.new_frame();
+        let one_line = gui.wrapped_label(Vector2f::zeros(), "Hello".to_string(), 800.0);
+        assert!(one_line > 0.0);
+
+        let text = "A long line of dialogue that does not fit in the box".to_string();
+        let wrapped = gui.wrapped_label(Vector2f::zeros(), text, 100.0);
+        assert!(wrapped >= 2.0 * one_line);
+
+        let two_lines = gui.wrapped_label(Vector2f::zeros(), "Hello\nWorld".to_string(), 800.0);
+        assert!(two_lines >= 2.0 * one_line);
+        assert!(two_lines < 3.0 * one_line);
+    }
+
+    #[test]
+    fn draw_with_a_registered_font() {
+        let mut context = GuiContext::new(WindowDim::new(800, 600));
+        let title = context.register_font("title", FONT_DATA);
+        assert_eq!(context.font("title"), Some(title));
+        assert_ne!(title, FontId::default());
+        assert_eq!(context.font("body"), None);
+
+        let mut gui = context.new_frame();
+        gui.set_font(title);
+        gui.label(Vector2f::zeros(), "Title".to_string());
+        gui.set_font(FontId::default());
+        gui.label(Vector2f::new(0.0, 50.0), "Body".to_string());
+        let fonts: Vec<_> = gui
+            .draw_data
+            .iter()
+            .filter_map(|data| match data {
+                DrawData::Text(text, ..) => Some(text.font_id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(fonts, vec![title, FontId::default()]);
+
+        // The text can be laid out with the new font.
+        let section = Section {
+            text: "Title",
+            font_id: title,
+            ..Section::default()
+        };
+        assert!(context.fonts.borrow_mut().glyph_bounds(section).is_some());
+    }
 }
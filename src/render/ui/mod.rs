@@ -1,3 +1,4 @@
+use crate::core::window::WindowDim;
 use crate::render::ui::text::{Text, TextRenderer};
 use crate::resources::Resources;
 use glyph_brush::GlyphBrush;
@@ -5,6 +6,7 @@ use luminance::blending::{Blending, Equation, Factor};
 use luminance::context::GraphicsContext;
 use luminance::pipeline::PipelineError;
 use luminance::render_state::RenderState;
+use luminance::scissor::ScissorRegion;
 use luminance::tess::Mode;
 use luminance_derive::{Semantics, Vertex};
 use luminance_front::{pipeline::Pipeline, shader::Program, shading_gate::ShadingGate, tess::Tess};
@@ -13,6 +15,7 @@ pub mod gui;
 pub use gui::*;
 pub mod text;
 pub mod widgets;
+pub mod world_text;
 use crate::geom2::Vector2f;
 pub use widgets::*;
 
@@ -47,16 +50,105 @@ pub fn new_shader(surface: &mut super::Context) -> Program<VertexSemantics, (),
 
 const FONT_DATA: &'static [u8] = include_bytes!("../../../assets/fonts/FFFFORWA.TTF");
 
+/// Draw call of the UI, in drawing order.
+enum UiDrawCall {
+    /// Tess and scissor region of a widget.
+    Vertices(Tess<Vertex, u32>, Option<ScissorRegion>),
+    /// Batch of consecutive texts of the `TextRenderer` that have the same scissor region.
+    Text(usize, Option<ScissorRegion>),
+}
+
 pub struct UiRenderer {
-    tesses: Vec<Tess<Vertex, u32>>,
+    draw_calls: Vec<UiDrawCall>,
     shader: Program<VertexSemantics, (), ()>,
     render_state: RenderState,
     text_renderer: TextRenderer,
 }
 
+/// Vertices and text are drawn by z-index then in creation order, so a label created after its
+/// panel is drawn on top of it.
 pub enum DrawData {
-    Vertices(Vec<Vertex>, Vec<u32>),
-    Text(Text, Vector2f),
+    /// Vertices, indices, z-index and clip rectangle. Larger z-index is drawn last.
+    Vertices(Vec<Vertex>, Vec<u32>, i32, Option<ClipRect>),
+    /// Text, position, z-index and clip rectangle.
+    Text(Text, Vector2f, i32, Option<ClipRect>),
+}
+
+impl DrawData {
+    fn z_index(&self) -> i32 {
+        match self {
+            DrawData::Vertices(_, _, z_index, _) => *z_index,
+            DrawData::Text(_, _, z_index, _) => *z_index,
+        }
+    }
+}
+
+/// Area of the window the UI is laid out in. Widget positions are in pixels from the top-left
+/// corner of that area. Insert it as a resource to change it. Defaults to the window, as the UI
+/// was laid out before the anchor existed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UiAnchor {
+    /// The whole window, black bars included.
+    Window,
+    /// The letterboxed game viewport, so that the HUD stays aligned with the play area.
+    Viewport,
+}
+
+impl Default for UiAnchor {
+    fn default() -> Self {
+        UiAnchor::Window
+    }
+}
+
+/// Rectangle the UI is drawn in, as (width, height, x, y) with y from the bottom of the window.
+pub(crate) fn ui_viewport(resources: &Resources) -> (f32, f32, u32, u32) {
+    let anchor = resources
+        .fetch::<UiAnchor>()
+        .map(|a| *a)
+        .unwrap_or_default();
+    match anchor {
+        UiAnchor::Viewport => super::letterbox_viewport(resources),
+        UiAnchor::Window => {
+            let window_dim = resources.fetch::<WindowDim>().unwrap();
+            (window_dim.width as f32, window_dim.height as f32, 0, 0)
+        }
+    }
+}
+
+/// Dimensions of the UI area and position of its top-left corner in the window.
+pub(crate) fn ui_area(resources: &Resources) -> (WindowDim, Vector2f) {
+    let (width, height, x, y) = ui_viewport(resources);
+    let window_height = resources.fetch::<WindowDim>().unwrap().height as f32;
+    let top = window_height - y as f32 - height;
+    (
+        WindowDim::new(width as u32, height as u32),
+        Vector2f::new(x as f32, top),
+    )
+}
+
+/// Scissor regions use framebuffer pixels from the bottom-left corner, whereas the UI uses
+/// pixels from the top-left corner of its viewport.
+fn to_scissor_region(
+    clip: &ClipRect,
+    (viewport_w, viewport_h, x, y): (f32, f32, u32, u32),
+) -> ScissorRegion {
+    let bottom = viewport_h - clip.max().y;
+    ScissorRegion {
+        x: x + clip.anchor.x.max(0.0).min(viewport_w) as u32,
+        y: y + bottom.max(0.0) as u32,
+        width: clip.dimensions.x as u32,
+        height: clip.dimensions.y as u32,
+    }
+}
+
+fn same_scissor(a: &Option<ScissorRegion>, b: &Option<ScissorRegion>) -> bool {
+    match (a, b) {
+        (None, None) => true,
+        (Some(a), Some(b)) => {
+            a.x == b.x && a.y == b.y && a.width == b.width && a.height == b.height
+        }
+        _ => false,
+    }
 }
 
 impl UiRenderer {
@@ -89,7 +181,7 @@ impl UiRenderer {
         }
 
         Self {
-            tesses: vec![],
+            draw_calls: vec![],
             shader,
             render_state,
             text_renderer: TextRenderer::new(surface, &mut *gui_context.fonts.borrow_mut()),
@@ -104,13 +196,25 @@ impl UiRenderer {
         resources: &Resources,
         fonts: &mut GlyphBrush<'static, text::Instance>,
     ) {
-        self.tesses.clear();
+        self.draw_calls.clear();
 
         if let Some(gui) = gui {
+            let viewport = ui_viewport(resources);
+            let mut draw_data = gui.draw_data;
+            // stable sort so that widgets with the same z-index keep their creation order.
+            draw_data.sort_by_key(DrawData::z_index);
+
             let mut text_data = vec![];
-            for draw_data in gui.draw_data {
+            let mut batches = 0;
+            for draw_data in draw_data {
                 match draw_data {
-                    DrawData::Vertices(vertices, indices) => {
+                    DrawData::Vertices(vertices, indices, _, clip) => {
+                        // Fully clipped widgets are not drawn at all.
+                        if let Some(ref clip) = clip {
+                            if clip.dimensions.x <= 0.0 || clip.dimensions.y <= 0.0 {
+                                continue;
+                            }
+                        }
                         let tess = surface
                             .new_tess()
                             .set_mode(Mode::Triangle)
@@ -118,16 +222,38 @@ impl UiRenderer {
                             .set_vertices(vertices)
                             .build()
                             .unwrap();
-                        self.tesses.push(tess);
+                        let scissor = clip.map(|c| to_scissor_region(&c, viewport));
+                        self.draw_calls.push(UiDrawCall::Vertices(tess, scissor));
+                    }
+                    DrawData::Text(text, pos, _, clip) => {
+                        if let Some(ref clip) = clip {
+                            if clip.dimensions.x <= 0.0 || clip.dimensions.y <= 0.0 {
+                                continue;
+                            }
+                        }
+                        let scissor = clip.map(|c| to_scissor_region(&c, viewport));
+                        // Consecutive texts with the same clip rectangle are drawn together.
+                        let batch = match self.draw_calls.last() {
+                            Some(UiDrawCall::Text(batch, batch_scissor))
+                                if same_scissor(batch_scissor, &scissor) =>
+                            {
+                                *batch
+                            }
+                            _ => {
+                                batches += 1;
+                                self.draw_calls.push(UiDrawCall::Text(batches - 1, scissor));
+                                batches - 1
+                            }
+                        };
+                        text_data.push((text, pos, batch, clip));
                     }
-                    DrawData::Text(text, pos) => text_data.push((text, pos)),
                 }
             }
 
             self.text_renderer
                 .prepare(surface, text_data, fonts, resources);
         } else {
-            self.text_renderer.tess = None;
+            self.text_renderer.batches.clear();
         }
     }
 
@@ -136,15 +262,24 @@ impl UiRenderer {
         pipeline: &Pipeline,
         shd_gate: &mut ShadingGate,
     ) -> Result<(), PipelineError> {
-        let tesses = &self.tesses;
+        let shader = &mut self.shader;
+        let text_renderer = &mut self.text_renderer;
         let render_state = &self.render_state;
 
-        for tess in tesses {
-            shd_gate.shade(&mut self.shader, |_iface, _uni, mut rdr_gate| {
-                rdr_gate.render(render_state, |mut tess_gate| tess_gate.render(tess))
-            })?;
+        for draw_call in &self.draw_calls {
+            match draw_call {
+                UiDrawCall::Vertices(tess, scissor) => {
+                    let render_state = render_state.clone().set_scissor(scissor.clone());
+                    shd_gate.shade(shader, |_iface, _uni, mut rdr_gate| {
+                        rdr_gate.render(&render_state, |mut tess_gate| tess_gate.render(tess))
+                    })?;
+                }
+                UiDrawCall::Text(batch, scissor) => {
+                    text_renderer.render(pipeline, shd_gate, *batch, scissor.clone())?
+                }
+            }
         }
 
-        self.text_renderer.render(pipeline, shd_gate)
+        Ok(())
     }
 }
@@ -45,5 +45,6 @@ void main() {
     }
 
     f_color = color;
-    gl_Position = vec4(pos, left_top.z, 1.0);
+    // z is the batch of the glyph, not a depth.
+    gl_Position = vec4(pos, 0.0, 1.0);
 }
\ No newline at end of file
@@ -1,17 +1,17 @@
 use crate::core::colors::RgbaColor;
-use crate::core::window::WindowDim;
 use crate::geom2::Vector2f;
-use crate::render::ui::gui::{HorizontalAlign, VerticalAlign};
+use crate::render::ui::gui::{ClipRect, HorizontalAlign, VerticalAlign};
 use crate::render::Context;
 use crate::resources::Resources;
 use glyph_brush::rusttype::*;
 use glyph_brush::BrushError::TextureTooSmall;
-use glyph_brush::{BrushAction, GlyphBrush, Layout, Section};
+use glyph_brush::{BrushAction, FontId, GlyphBrush, Layout, Section};
 use luminance::blending::{Blending, Equation, Factor};
 use luminance::context::GraphicsContext;
 use luminance::pipeline::{PipelineError, TextureBinding};
 use luminance::pixel::{NormR8UI, NormUnsigned};
 use luminance::render_state::RenderState;
+use luminance::scissor::ScissorRegion;
 use luminance::shader::Uniform;
 use luminance::tess::Mode;
 use luminance::texture::{Dim2, GenMipmaps, Sampler};
@@ -70,8 +70,28 @@ pub struct Text {
     pub font_size: f32,
     pub color: RgbaColor,
     pub align: (HorizontalAlign, VerticalAlign),
+    /// Maximum width and height in pixels. Words that do not fit are wrapped to the next line.
+    /// None will use the default bounds.
+    pub bounds: Option<Vector2f>,
+    pub font_id: FontId,
+    /// Offset in pixels and color of a drop shadow drawn below the text.
+    pub shadow: Option<(Vector2f, RgbaColor)>,
+    /// Color of a one pixel outline around the text.
+    pub outline: Option<RgbaColor>,
 }
 
+/// Offsets of the copies of the text that make the outline.
+const OUTLINE_OFFSETS: [(f32, f32); 8] = [
+    (-1.0, -1.0),
+    (0.0, -1.0),
+    (1.0, -1.0),
+    (-1.0, 0.0),
+    (1.0, 0.0),
+    (-1.0, 1.0),
+    (0.0, 1.0),
+    (1.0, 1.0),
+];
+
 /// X and Y coords between 0 and 1. (0,0) being the top-left corner and (1,1) bottom-right corner
 pub struct ScreenPosition {
     pub x: f32,
@@ -91,7 +111,8 @@ pub fn new_shader(surface: &mut Context) -> Program<VertexSemantics, (), ShaderI
 
 pub struct TextRenderer {
     texture: Texture<Dim2, NormR8UI>,
-    pub(crate) tess: Option<Tess<(), (), Instance>>,
+    /// Glyphs of each batch of texts. None if the batch has no visible glyph.
+    pub(crate) batches: Vec<Option<Tess<(), (), Instance>>>,
     render_state: RenderState,
     shader: Program<VertexSemantics, (), ShaderInterface>,
 }
@@ -129,44 +150,72 @@ impl TextRenderer {
 
         Self {
             texture: tex,
-            tess: None,
+            batches: vec![],
             render_state,
             shader: new_shader(surface),
         }
     }
 
+    /// Lay out the texts. Each text has the index of its batch, see `render`.
     pub fn prepare(
         &mut self,
         surface: &mut Context,
-        text_data: Vec<(Text, Vector2f)>,
+        text_data: Vec<(Text, Vector2f, usize, Option<ClipRect>)>,
         glyph_brush: &mut GlyphBrush<'static, Instance>,
         resources: &Resources,
     ) {
-        let window_dim = resources.fetch::<WindowDim>().unwrap();
-        let width = window_dim.width as f32;
-        let height = window_dim.height as f32;
+        let (width, height, _, _) = super::ui_viewport(resources);
 
         //
         // glyph_brush.pixel_bounds()
 
-        for (text, position) in text_data {
+        for (text, position, batch, clip) in text_data {
             // screen position is top-left origin
             let pos_x = position.x;
             let pos_y = position.y;
             debug!("Will display text at {}/{}", pos_x, pos_y);
 
             let scale = Scale::uniform(text.font_size.round());
-            glyph_brush.queue(Section {
-                text: text.content.as_str(),
-                scale,
-                screen_position: (pos_x, pos_y),
-                bounds: (width / 3.15, height),
-                color: text.color.to_normalized(),
-                layout: Layout::default()
-                    .h_align(text.align.0.into())
-                    .v_align(text.align.1.into()),
-                ..Section::default()
-            });
+            let mut bounds = text
+                .bounds
+                .map(|b| (b.x, b.y))
+                .unwrap_or((width / 3.15, height));
+            // The scissor region cuts the glyphs, the bounds only wrap the text at the right edge
+            // of the clip rectangle.
+            if let Some(clip) = clip {
+                let max = clip.max() - position;
+                if max.x <= 0.0 || max.y <= 0.0 {
+                    continue;
+                }
+                bounds = (bounds.0.min(max.x), bounds.1.min(max.y));
+            }
+
+            // Sections are drawn in order so the shadow and outline go first.
+            let mut layers = vec![];
+            if let Some((offset, color)) = text.shadow {
+                layers.push(((offset.x, offset.y), color));
+            }
+            if let Some(color) = text.outline {
+                layers.extend(OUTLINE_OFFSETS.iter().map(|offset| (*offset, color)));
+            }
+            layers.push(((0.0, 0.0), text.color));
+
+            for ((offset_x, offset_y), color) in layers {
+                glyph_brush.queue(Section {
+                    text: text.content.as_str(),
+                    scale,
+                    screen_position: (pos_x + offset_x, pos_y + offset_y),
+                    bounds,
+                    color: color.to_normalized(),
+                    font_id: text.font_id,
+                    layout: Layout::default()
+                        .h_align(text.align.0.into())
+                        .v_align(text.align.1.into()),
+                    // The glyphs are split by batch with the z coordinate.
+                    z: batch as f32,
+                    ..Section::default()
+                });
+            }
         }
 
         let action = glyph_brush.process_queued(
@@ -192,28 +241,47 @@ impl TextRenderer {
         let action = action.unwrap();
         match action {
             BrushAction::Draw(v) => {
-                let tess = surface
-                    .new_tess()
-                    .set_vertex_nb(4)
-                    .set_instances(v)
-                    .set_mode(Mode::TriangleStrip)
-                    .build()
-                    .unwrap();
-                self.tess = Some(tess);
+                let mut batches: Vec<Vec<Instance>> = vec![];
+                for instance in v {
+                    let batch = instance.left_top[2] as usize;
+                    if batches.len() <= batch {
+                        batches.resize(batch + 1, vec![]);
+                    }
+                    batches[batch].push(instance);
+                }
+                self.batches = batches
+                    .into_iter()
+                    .map(|instances| {
+                        if instances.is_empty() {
+                            return None;
+                        }
+                        let tess = surface
+                            .new_tess()
+                            .set_vertex_nb(4)
+                            .set_instances(instances)
+                            .set_mode(Mode::TriangleStrip)
+                            .build()
+                            .unwrap();
+                        Some(tess)
+                    })
+                    .collect();
             }
             BrushAction::ReDraw => (),
         };
     }
 
+    /// Draw the glyphs of one batch of texts, only inside the scissor region if there is one.
     pub fn render(
         &mut self,
         pipeline: &Pipeline,
         shd_gate: &mut ShadingGate,
+        batch: usize,
+        scissor: Option<ScissorRegion>,
     ) -> Result<(), PipelineError> {
         let tex = &mut self.texture;
         let shader = &mut self.shader;
-        let render_state = &self.render_state;
-        if let Some(tess) = self.tess.as_ref() {
+        let render_state = &self.render_state.clone().set_scissor(scissor);
+        if let Some(Some(tess)) = self.batches.get(batch) {
             shd_gate.shade(shader, |mut iface, uni, mut rdr_gate| {
                 let bound_tex = pipeline.bind_texture(tex)?;
                 iface.set(&uni.tex, bound_tex.binding());
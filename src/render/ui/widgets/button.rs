@@ -3,7 +3,7 @@ use crate::core::input::ser::VirtualButton;
 use crate::geom2::Vector2f;
 use crate::render::ui::gui::{HorizontalAlign, VerticalAlign};
 use crate::render::ui::text::Text;
-use crate::render::ui::{DrawData, Gui, Panel};
+use crate::render::ui::{Gui, Panel};
 
 pub struct Button {
     /// Text of the button
@@ -20,6 +20,8 @@ pub struct Button {
     font_size: Option<f32>,
     text_align: Option<(HorizontalAlign, VerticalAlign)>,
     padding: Option<f32>,
+    /// Override the z-index of the gui
+    z_index: Option<i32>,
 }
 
 impl Button {
@@ -35,6 +37,7 @@ impl Button {
             font_size: None,
             text_align: None,
             padding: None,
+            z_index: None,
         }
     }
 
@@ -74,6 +77,11 @@ impl Button {
         self
     }
 
+    pub fn z_index(mut self, z_index: i32) -> Self {
+        self.z_index = Some(z_index);
+        self
+    }
+
     fn text_color(&self, ui: &Gui, is_above: bool) -> RgbaColor {
         if is_above {
             self.hover_text_color
@@ -104,11 +112,9 @@ impl Button {
             ui.text_bounds(self.text.as_str(), font_size)
         };
 
-        let mouse_pos_rel = &ui.mouse_pos - self.anchor.clone();
-        let is_above = mouse_pos_rel.x >= 0.0
-            && mouse_pos_rel.x < dimensions.x
-            && mouse_pos_rel.y >= 0.0
-            && mouse_pos_rel.y <= dimensions.y;
+        // Only the topmost widget under the mouse is considered hovered.
+        let z_index = self.z_index.unwrap_or(ui.z_index);
+        let is_above = ui.register_hit_region(self.anchor, dimensions, z_index);
         let color = self.background_color(ui, is_above);
         let text_color = self.text_color(ui, is_above);
         let (vertices, indices) = Panel {
@@ -118,18 +124,22 @@ impl Button {
         }
         .vertices(ui.window_dim);
 
-        ui.draw_data.push(DrawData::Vertices(vertices, indices));
+        ui.push_vertices(vertices, indices, z_index);
 
         //let horizontal_align = self.text_align.unwrap_or(ui.style.button_text_align).0;
-        ui.draw_data.push(DrawData::Text(
+        ui.push_text(
             Text {
                 content: self.text,
                 font_size,
                 color: text_color,
                 align: text_align,
+                bounds: None,
+                font_id: ui.font_id,
+                shadow: ui.style.text_shadow,
+                outline: ui.style.text_outline,
             },
             text_position,
-        ));
+        );
 
         if ui.mouse_clicked.contains(&VirtualButton::Button1) {
             return is_above;
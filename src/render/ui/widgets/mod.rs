@@ -1,4 +1,6 @@
 pub mod button;
 pub mod panel;
+pub mod progress_bar;
 pub use button::*;
 pub use panel::*;
+pub use progress_bar::*;
@@ -0,0 +1,95 @@
+use crate::core::colors::RgbaColor;
+use crate::geom2::Vector2f;
+use crate::render::ui::{Gui, Panel};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    /// Filled from left to right.
+    Horizontal,
+    /// Filled from bottom to top.
+    Vertical,
+}
+
+/// Bar filled according to a value between 0 and 1 (health, mana...).
+pub struct ProgressBar {
+    /// Between 0 and 1. Clamped.
+    value: f32,
+    /// top-left corner of the bar
+    anchor: Vector2f,
+    dimensions: Vector2f,
+    orientation: Orientation,
+    background_color: RgbaColor,
+    fill_color: RgbaColor,
+    /// Override the z-index of the gui
+    z_index: Option<i32>,
+}
+
+impl ProgressBar {
+    pub fn new(value: f32, position: Vector2f, dimensions: Vector2f) -> Self {
+        Self {
+            value,
+            anchor: position,
+            dimensions,
+            orientation: Orientation::Horizontal,
+            background_color: RgbaColor::new(50, 50, 50, 200),
+            fill_color: RgbaColor::new(80, 200, 80, 255),
+            z_index: None,
+        }
+    }
+
+    pub fn orientation(mut self, orientation: Orientation) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    pub fn set_colors(mut self, background: RgbaColor, fill: RgbaColor) -> Self {
+        self.background_color = background;
+        self.fill_color = fill;
+        self
+    }
+
+    pub fn z_index(mut self, z_index: i32) -> Self {
+        self.z_index = Some(z_index);
+        self
+    }
+
+    /// Top-left corner and dimensions of the filled part.
+    fn fill_rect(&self) -> (Vector2f, Vector2f) {
+        let value = self.value.max(0.0).min(1.0);
+        match self.orientation {
+            Orientation::Horizontal => (
+                self.anchor,
+                Vector2f::new(self.dimensions.x * value, self.dimensions.y),
+            ),
+            Orientation::Vertical => {
+                let height = self.dimensions.y * value;
+                (
+                    self.anchor + Vector2f::new(0.0, self.dimensions.y - height),
+                    Vector2f::new(self.dimensions.x, height),
+                )
+            }
+        }
+    }
+
+    pub fn build(self, ui: &mut Gui) {
+        let z_index = self.z_index.unwrap_or(ui.z_index);
+        let (vertices, indices) = Panel {
+            anchor: self.anchor,
+            dimensions: self.dimensions,
+            color: self.background_color,
+        }
+        .vertices(ui.window_dim);
+        ui.push_vertices(vertices, indices, z_index);
+
+        let (anchor, dimensions) = self.fill_rect();
+        if dimensions.x > 0.0 && dimensions.y > 0.0 {
+            let (vertices, indices) = Panel {
+                anchor,
+                dimensions,
+                color: self.fill_color,
+            }
+            .vertices(ui.window_dim);
+            ui.push_vertices(vertices, indices, z_index);
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Text that follows an entity (damage numbers, nameplates...).
+use crate::core::camera::{ViewProjection, VirtualDim};
+use crate::core::colors::RgbaColor;
+use crate::core::disable::Disabled;
+use crate::core::transform::Transform;
+use crate::core::window::WindowDim;
+use crate::geom2::Vector2f;
+use crate::render::ui::text::Text;
+use crate::render::ui::{ui_area, Gui, GuiContext, HorizontalAlign, VerticalAlign};
+use crate::resources::Resources;
+use serde_derive::{Deserialize, Serialize};
+
+/// Text drawn with the UI at the position of the entity. It is centered on the entity.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorldText {
+    pub content: String,
+    pub color: RgbaColor,
+    /// Offset in pixels from the position of the entity on the screen. Y goes down.
+    #[serde(default = "Vector2f::zeros")]
+    pub offset: Vector2f,
+}
+
+impl WorldText {
+    pub fn new(content: String, color: RgbaColor) -> Self {
+        Self {
+            content,
+            color,
+            offset: Vector2f::zeros(),
+        }
+    }
+
+    pub fn with_offset(mut self, offset: Vector2f) -> Self {
+        self.offset = offset;
+        self
+    }
+}
+
+/// Add the world texts to the GUI of the scene. A GUI is created if the scene has none. The
+/// texts outside of the UI area are not drawn.
+pub(crate) fn add_world_texts(
+    gui: Option<Gui>,
+    gui_context: &GuiContext,
+    world: &hecs::World,
+    resources: &Resources,
+) -> Option<Gui> {
+    let mut query = world
+        .query::<(&Transform, &WorldText)>()
+        .without::<Disabled>();
+    let mut texts = query.iter().peekable();
+    if texts.peek().is_none() {
+        return gui;
+    }
+
+    let view_projection = resources.fetch::<ViewProjection>().unwrap();
+    let window_dim = *resources.fetch::<WindowDim>().unwrap();
+    let virtual_dim = *resources.fetch::<VirtualDim>().unwrap();
+    let (ui_dim, ui_offset) = ui_area(resources);
+
+    let mut gui = gui.unwrap_or_else(|| gui_context.new_frame());
+    for (_, (transform, text)) in texts {
+        let screen =
+            view_projection.world_to_screen(transform.translation, &window_dim, &virtual_dim)
+                - ui_offset
+                + text.offset;
+        if screen.x < 0.0
+            || screen.y < 0.0
+            || screen.x > ui_dim.width as f32
+            || screen.y > ui_dim.height as f32
+        {
+            continue;
+        }
+
+        gui.push_text(
+            Text {
+                content: text.content.clone(),
+                font_size: gui.style.font_size,
+                color: text.color,
+                align: (HorizontalAlign::Center, VerticalAlign::Center),
+                bounds: None,
+                font_id: gui.font_id,
+                shadow: gui.style.text_shadow,
+                outline: gui.style.text_outline,
+            },
+            screen,
+        );
+    }
+    Some(gui)
+}